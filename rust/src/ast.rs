@@ -0,0 +1,225 @@
+mod ast_parser;
+mod lower;
+
+pub use self::ast_parser::parse;
+pub use self::ast_parser::parse_expr;
+pub use self::lower::lower;
+use crate::compiler::Token;
+use crate::mem::GarbageCollector;
+use crate::mem::GcRef;
+use crate::obj::ObjFunction;
+
+pub type Errors = Vec<eyre::Report>;
+
+/// Parses `source` into an AST and lowers it to the same bytecode that the
+/// single-pass `Compiler` produces.
+pub fn compile(
+	gc: &GarbageCollector,
+	source: &str,
+) -> Result<GcRef<ObjFunction>, Errors> {
+	lower(gc, &parse(source)?)
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct Program<'source> {
+	pub stmts:    Vec<Stmt<'source>>,
+	pub eof_line: u32,
+}
+
+#[derive(Clone, Debug)]
+pub enum Expr<'source> {
+	Assign {
+		name:  Token<'source>,
+		value: Box<Expr<'source>>,
+	},
+	Binary {
+		left:  Box<Expr<'source>>,
+		op:    Token<'source>,
+		right: Box<Expr<'source>>,
+	},
+	Call {
+		callee: Box<Expr<'source>>,
+		paren:  Token<'source>,
+		args:   Vec<Expr<'source>>,
+	},
+	Get {
+		object: Box<Expr<'source>>,
+		name:   Token<'source>,
+	},
+	Grouping(Box<Expr<'source>>),
+	Literal(Token<'source>),
+	Logical {
+		left:  Box<Expr<'source>>,
+		op:    Token<'source>,
+		right: Box<Expr<'source>>,
+	},
+	Set {
+		object: Box<Expr<'source>>,
+		name:   Token<'source>,
+		value:  Box<Expr<'source>>,
+	},
+	Super {
+		keyword: Token<'source>,
+		method:  Token<'source>,
+	},
+	This(Token<'source>),
+	Unary {
+		op:    Token<'source>,
+		right: Box<Expr<'source>>,
+	},
+	Variable(Token<'source>),
+}
+
+#[derive(Clone, Debug)]
+pub enum Stmt<'source> {
+	Block {
+		open:  Token<'source>,
+		stmts: Vec<Stmt<'source>>,
+		close: Token<'source>,
+	},
+	Class(ClassDecl<'source>),
+	Expression(Expr<'source>),
+	For {
+		keyword:     Token<'source>,
+		initializer: Option<Box<Stmt<'source>>>,
+		condition:   Option<Expr<'source>>,
+		increment:   Option<Expr<'source>>,
+		body:        Box<Stmt<'source>>,
+	},
+	Function(FunctionDecl<'source>),
+	If {
+		keyword:     Token<'source>,
+		condition:   Expr<'source>,
+		then_branch: Box<Stmt<'source>>,
+		else_branch: Option<Box<Stmt<'source>>>,
+	},
+	Print {
+		keyword: Token<'source>,
+		value:   Expr<'source>,
+	},
+	Return {
+		keyword: Token<'source>,
+		value:   Option<Expr<'source>>,
+	},
+	Var {
+		name:        Token<'source>,
+		initializer: Option<Expr<'source>>,
+	},
+	While {
+		keyword:   Token<'source>,
+		condition: Expr<'source>,
+		body:      Box<Stmt<'source>>,
+	},
+}
+
+#[derive(Clone, Debug)]
+pub struct ClassDecl<'source> {
+	pub name:       Token<'source>,
+	pub superclass: Option<Token<'source>>,
+	pub methods:    Vec<FunctionDecl<'source>>,
+	pub close:      Token<'source>,
+}
+
+#[derive(Clone, Debug)]
+pub struct FunctionDecl<'source> {
+	pub name:   Token<'source>,
+	pub params: Vec<Token<'source>>,
+	pub body:   Vec<Stmt<'source>>,
+	pub close:  Token<'source>,
+}
+
+impl<'source> Expr<'source> {
+	/// The line on which this expression starts.
+	pub fn line(&self) -> u32 {
+		match self {
+			Expr::Binary { left, .. } => left.line(),
+			Expr::Call { callee, .. } => callee.line(),
+			Expr::Get { object, .. } => object.line(),
+			Expr::Logical { left, .. } => left.line(),
+			Expr::Set { object, .. } => object.line(),
+			_ => self.token().line,
+		}
+	}
+
+	/// The token that best identifies this expression in diagnostics.
+	pub fn token(&self) -> &Token<'source> {
+		match self {
+			Expr::Assign { name, .. } => name,
+			Expr::Binary { op, .. } => op,
+			Expr::Call { paren, .. } => paren,
+			Expr::Get { name, .. } => name,
+			Expr::Grouping(inner) => inner.token(),
+			Expr::Literal(token) => token,
+			Expr::Logical { op, .. } => op,
+			Expr::Set { name, .. } => name,
+			Expr::Super { keyword, .. } => keyword,
+			Expr::This(keyword) => keyword,
+			Expr::Unary { op, .. } => op,
+			Expr::Variable(name) => name,
+		}
+	}
+}
+
+impl<'source> Stmt<'source> {
+	/// The line on which this statement starts.
+	pub fn line(&self) -> u32 {
+		match self {
+			Stmt::Block { open, .. } => open.line,
+			Stmt::Class(decl) => decl.name.line,
+			Stmt::Expression(expr) => expr.line(),
+			Stmt::For { keyword, .. } => keyword.line,
+			Stmt::Function(decl) => decl.name.line,
+			Stmt::If { keyword, .. } => keyword.line,
+			Stmt::Print { keyword, .. } => keyword.line,
+			Stmt::Return { keyword, .. } => keyword.line,
+			Stmt::Var { name, .. } => name.line,
+			Stmt::While { keyword, .. } => keyword.line,
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn lowers_examples_like_the_compiler() {
+		let dir = concat!(env!("CARGO_MANIFEST_DIR"), "/../lox_programs");
+		for entry in std::fs::read_dir(dir).unwrap() {
+			let path = entry.unwrap().path();
+			let source = std::fs::read_to_string(&path).unwrap();
+			let gc = GarbageCollector::default();
+
+			let expected = crate::compiler::compile(&gc, &source);
+			let actual = compile(&gc, &source);
+			match (expected, actual) {
+				(Ok(expected), Ok(actual)) => assert!(
+					actual.chunk.same_code(&expected.chunk),
+					"{} lowers to different code",
+					path.display()
+				),
+				// the compiler also resolves variables after a syntax error,
+				// so it can report more errors than the parser does
+				(Err(expected), Err(actual)) => {
+					let expected = expected
+						.iter()
+						.map(ToString::to_string)
+						.collect::<Vec<_>>();
+					for error in actual {
+						assert!(
+							expected.contains(&error.to_string()),
+							"{} has an error the compiler lacks: {error}",
+							path.display()
+						);
+					}
+				},
+				(expected, actual) => panic!(
+					"{} compiles with one front-end only: {:?} vs {:?}",
+					path.display(),
+					expected.err(),
+					actual.err()
+				),
+			}
+		}
+	}
+}
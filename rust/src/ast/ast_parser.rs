@@ -0,0 +1,552 @@
+use super::*;
+use crate::compiler::parser;
+use crate::compiler::parser::Parser;
+use crate::compiler::TokenKind;
+
+type Result<T = (), E = ()> = std::result::Result<T, E>;
+
+const MAX_ARGS: usize = u8::MAX as _;
+
+pub fn parse(source: &str) -> std::result::Result<Program<'_>, Errors> {
+	let mut parser = AstParser {
+		parser: Parser::new(source),
+		errors: Vec::new(),
+	};
+
+	let mut stmts = Vec::new();
+	while !parser.check(TokenKind::Eof) {
+		stmts.extend(parser.declaration());
+	}
+
+	if parser.errors.is_empty() {
+		Ok(Program {
+			stmts,
+			eof_line: parser.parser.current.line,
+		})
+	} else {
+		Err(parser.errors)
+	}
+}
+
+/// Parses `source` as a single expression with nothing after it.
+pub fn parse_expr(source: &str) -> std::result::Result<Expr<'_>, Errors> {
+	let mut parser = AstParser {
+		parser: Parser::new(source),
+		errors: Vec::new(),
+	};
+
+	let expr = parser.expression();
+	if expr.is_ok() && !parser.check(TokenKind::Eof) {
+		_ = parser.error_at_current::<()>("Expect end of expression.");
+	}
+
+	match expr {
+		Ok(expr) if parser.errors.is_empty() => Ok(expr),
+		_ => Err(parser.errors),
+	}
+}
+
+struct AstParser<'source> {
+	parser: Parser<'source>,
+	errors: Errors,
+}
+
+impl<'source> AstParser<'source> {
+	fn advance(&mut self) -> Token<'source> {
+		self.parser.advance(&mut self.errors);
+		self.previous()
+	}
+
+	fn check(&self, kind: TokenKind) -> bool {
+		self.parser.check(kind)
+	}
+
+	fn check_eat(&mut self, kind: TokenKind) -> Option<Token<'source>> {
+		self.check(kind).then(|| self.advance())
+	}
+
+	fn check_eat_any(&mut self, kinds: &[TokenKind]) -> Option<Token<'source>> {
+		kinds.iter().find_map(|kind| self.check_eat(*kind))
+	}
+
+	fn consume(
+		&mut self,
+		kind: TokenKind,
+		msg: impl AsRef<str>,
+	) -> Result<Token<'source>> {
+		self.parser.consume(kind, msg, &mut self.errors)?;
+		Ok(self.previous())
+	}
+
+	fn error_at<T>(&mut self, tok: &Token, msg: impl AsRef<str>) -> Result<T> {
+		self.errors.push(parser::error_at(tok.clone(), msg));
+		Err(())
+	}
+
+	fn error_at_current<T>(&mut self, msg: impl AsRef<str>) -> Result<T> {
+		self.errors.push(self.parser.error_at_current(msg));
+		Err(())
+	}
+
+	fn previous(&self) -> Token<'source> {
+		self.parser.previous.clone()
+	}
+
+	fn synchronize(&mut self) {
+		while !self.check(TokenKind::Eof) {
+			if self.parser.previous.kind == TokenKind::Semicolon {
+				return;
+			}
+
+			if matches!(
+				self.parser.current.kind,
+				TokenKind::Class
+					| TokenKind::Fun
+					| TokenKind::Var
+					| TokenKind::For
+					| TokenKind::If | TokenKind::While
+					| TokenKind::Print
+					| TokenKind::Return
+			) {
+				return;
+			}
+
+			self.advance();
+		}
+	}
+}
+
+impl<'source> AstParser<'source> {
+	fn block(&mut self) -> Result<(Vec<Stmt<'source>>, Token<'source>)> {
+		let mut stmts = Vec::new();
+		while !self.check(TokenKind::RBrace) && !self.check(TokenKind::Eof) {
+			stmts.extend(self.declaration());
+		}
+
+		let close =
+			self.consume(TokenKind::RBrace, "Expect '}' after block.")?;
+		Ok((stmts, close))
+	}
+
+	fn class_declaration(&mut self) -> Result<Stmt<'source>> {
+		let name = self.consume(TokenKind::Identifier, "Expect class name.")?;
+
+		let superclass = match self.check_eat(TokenKind::Less) {
+			Some(_) => Some(
+				self.consume(TokenKind::Identifier, "Expect superclass name.")?,
+			),
+			None => None,
+		};
+
+		self.consume(TokenKind::LBrace, "Expect '{' before class body.")?;
+		let mut methods = Vec::new();
+		while !self.check(TokenKind::RBrace) && !self.check(TokenKind::Eof) {
+			methods.push(self.function("Expect method name.")?);
+		}
+		let close =
+			self.consume(TokenKind::RBrace, "Expect '}' after class body.")?;
+
+		Ok(Stmt::Class(ClassDecl {
+			name,
+			superclass,
+			methods,
+			close,
+		}))
+	}
+
+	fn declaration(&mut self) -> Option<Stmt<'source>> {
+		let res = if self.check_eat(TokenKind::Class).is_some() {
+			self.class_declaration()
+		} else if self.check_eat(TokenKind::Fun).is_some() {
+			self.function("Expect function name.").map(Stmt::Function)
+		} else if self.check_eat(TokenKind::Var).is_some() {
+			self.var_declaration()
+		} else {
+			self.statement()
+		};
+
+		if res.is_err() {
+			self.synchronize();
+		}
+		res.ok()
+	}
+
+	fn function(&mut self, name_msg: &str) -> Result<FunctionDecl<'source>> {
+		let name = self.consume(TokenKind::Identifier, name_msg)?;
+		self.consume(TokenKind::LParen, "Expect '(' after function name.")?;
+
+		let mut params = Vec::new();
+		if !self.check(TokenKind::RParen) {
+			loop {
+				if params.len() == MAX_ARGS {
+					let error = self.parser.error_at_current(
+						"Can't have more than 255 parameters.",
+					);
+					self.errors.push(error);
+				}
+				params.push(self.consume(
+					TokenKind::Identifier,
+					"Expect parameter name.",
+				)?);
+
+				if self.check_eat(TokenKind::Comma).is_none() {
+					break;
+				}
+			}
+		}
+
+		self.consume(TokenKind::RParen, "Expect ')' after parameters.")?;
+		self.consume(TokenKind::LBrace, "Expect '{' before function body.")?;
+		let (body, close) = self.block()?;
+
+		Ok(FunctionDecl {
+			name,
+			params,
+			body,
+			close,
+		})
+	}
+
+	fn var_declaration(&mut self) -> Result<Stmt<'source>> {
+		let name =
+			self.consume(TokenKind::Identifier, "Expect variable name.")?;
+		let initializer = match self.check_eat(TokenKind::Equal) {
+			Some(_) => Some(self.expression()?),
+			None => None,
+		};
+		self.consume(
+			TokenKind::Semicolon,
+			"Expect ';' after variable declaration.",
+		)?;
+
+		Ok(Stmt::Var { name, initializer })
+	}
+}
+
+impl<'source> AstParser<'source> {
+	fn expression_statement(&mut self) -> Result<Stmt<'source>> {
+		let expr = self.expression()?;
+		self.consume(TokenKind::Semicolon, "Expect ';' after expression.")?;
+		Ok(Stmt::Expression(expr))
+	}
+
+	fn for_statement(
+		&mut self,
+		keyword: Token<'source>,
+	) -> Result<Stmt<'source>> {
+		self.consume(TokenKind::LParen, "Expect '(' after 'for'.")?;
+
+		let initializer = if self.check_eat(TokenKind::Semicolon).is_some() {
+			None
+		} else if self.check_eat(TokenKind::Var).is_some() {
+			Some(Box::new(self.var_declaration()?))
+		} else {
+			Some(Box::new(self.expression_statement()?))
+		};
+
+		let condition = if self.check_eat(TokenKind::Semicolon).is_some() {
+			None
+		} else {
+			let condition = self.expression()?;
+			self.consume(
+				TokenKind::Semicolon,
+				"Expect ';' after loop condition.",
+			)?;
+			Some(condition)
+		};
+
+		let increment = if self.check_eat(TokenKind::RParen).is_some() {
+			None
+		} else {
+			let increment = self.expression()?;
+			self.consume(TokenKind::RParen, "Expect ')' after for clauses.")?;
+			Some(increment)
+		};
+
+		let body = Box::new(self.statement()?);
+		Ok(Stmt::For {
+			keyword,
+			initializer,
+			condition,
+			increment,
+			body,
+		})
+	}
+
+	fn if_statement(
+		&mut self,
+		keyword: Token<'source>,
+	) -> Result<Stmt<'source>> {
+		self.consume(TokenKind::LParen, "Expect '(' after 'if'.")?;
+		let condition = self.expression()?;
+		self.consume(TokenKind::RParen, "Expect ')' after condition.")?;
+
+		let then_branch = Box::new(self.statement()?);
+		let else_branch = match self.check_eat(TokenKind::Else) {
+			Some(_) => Some(Box::new(self.statement()?)),
+			None => None,
+		};
+
+		Ok(Stmt::If {
+			keyword,
+			condition,
+			then_branch,
+			else_branch,
+		})
+	}
+
+	fn print_statement(
+		&mut self,
+		keyword: Token<'source>,
+	) -> Result<Stmt<'source>> {
+		let value = self.expression()?;
+		self.consume(TokenKind::Semicolon, "Expect ';' after value.")?;
+		Ok(Stmt::Print { keyword, value })
+	}
+
+	fn return_statement(
+		&mut self,
+		keyword: Token<'source>,
+	) -> Result<Stmt<'source>> {
+		if self.check_eat(TokenKind::Semicolon).is_some() {
+			return Ok(Stmt::Return {
+				keyword,
+				value: None,
+			});
+		}
+
+		let value = self.expression()?;
+		self.consume(TokenKind::Semicolon, "Expect ';' after return value.")?;
+		Ok(Stmt::Return {
+			keyword,
+			value: Some(value),
+		})
+	}
+
+	fn statement(&mut self) -> Result<Stmt<'source>> {
+		match self.parser.current.kind {
+			TokenKind::For => {
+				let keyword = self.advance();
+				self.for_statement(keyword)
+			},
+			TokenKind::If => {
+				let keyword = self.advance();
+				self.if_statement(keyword)
+			},
+			TokenKind::LBrace => {
+				let open = self.advance();
+				let (stmts, close) = self.block()?;
+				Ok(Stmt::Block { open, stmts, close })
+			},
+			TokenKind::Print => {
+				let keyword = self.advance();
+				self.print_statement(keyword)
+			},
+			TokenKind::Return => {
+				let keyword = self.advance();
+				self.return_statement(keyword)
+			},
+			TokenKind::While => {
+				let keyword = self.advance();
+				self.while_statement(keyword)
+			},
+			_ => self.expression_statement(),
+		}
+	}
+
+	fn while_statement(
+		&mut self,
+		keyword: Token<'source>,
+	) -> Result<Stmt<'source>> {
+		self.consume(TokenKind::LParen, "Expect '(' after 'while'.")?;
+		let condition = self.expression()?;
+		self.consume(TokenKind::RParen, "Expect ')' after condition.")?;
+
+		let body = Box::new(self.statement()?);
+		Ok(Stmt::While {
+			keyword,
+			condition,
+			body,
+		})
+	}
+}
+
+impl<'source> AstParser<'source> {
+	fn expression(&mut self) -> Result<Expr<'source>> {
+		self.assignment()
+	}
+
+	fn assignment(&mut self) -> Result<Expr<'source>> {
+		let expr = self.or()?;
+
+		let Some(equals) = self.check_eat(TokenKind::Equal) else {
+			return Ok(expr);
+		};
+		let value = Box::new(self.assignment()?);
+
+		match expr {
+			Expr::Variable(name) => Ok(Expr::Assign { name, value }),
+			Expr::Get { object, name } => Ok(Expr::Set {
+				object,
+				name,
+				value,
+			}),
+			_ => self.error_at(&equals, "Invalid assignment target."),
+		}
+	}
+
+	fn or(&mut self) -> Result<Expr<'source>> {
+		let mut expr = self.and()?;
+		while let Some(op) = self.check_eat(TokenKind::Or) {
+			let right = Box::new(self.and()?);
+			expr = Expr::Logical {
+				left: Box::new(expr),
+				op,
+				right,
+			};
+		}
+		Ok(expr)
+	}
+
+	fn and(&mut self) -> Result<Expr<'source>> {
+		let mut expr = self.equality()?;
+		while let Some(op) = self.check_eat(TokenKind::And) {
+			let right = Box::new(self.equality()?);
+			expr = Expr::Logical {
+				left: Box::new(expr),
+				op,
+				right,
+			};
+		}
+		Ok(expr)
+	}
+
+	fn equality(&mut self) -> Result<Expr<'source>> {
+		self.binary(Self::comparison, &[
+			TokenKind::BangEqual,
+			TokenKind::EqualEqual,
+		])
+	}
+
+	fn comparison(&mut self) -> Result<Expr<'source>> {
+		self.binary(Self::term, &[
+			TokenKind::Greater,
+			TokenKind::GreaterEqual,
+			TokenKind::Less,
+			TokenKind::LessEqual,
+		])
+	}
+
+	fn term(&mut self) -> Result<Expr<'source>> {
+		self.binary(Self::factor, &[TokenKind::Minus, TokenKind::Plus])
+	}
+
+	fn factor(&mut self) -> Result<Expr<'source>> {
+		self.binary(Self::unary, &[TokenKind::Slash, TokenKind::Star])
+	}
+
+	fn binary(
+		&mut self,
+		operand: fn(&mut Self) -> Result<Expr<'source>>,
+		ops: &[TokenKind],
+	) -> Result<Expr<'source>> {
+		let mut expr = operand(self)?;
+		while let Some(op) = self.check_eat_any(ops) {
+			let right = Box::new(operand(self)?);
+			expr = Expr::Binary {
+				left: Box::new(expr),
+				op,
+				right,
+			};
+		}
+		Ok(expr)
+	}
+
+	fn unary(&mut self) -> Result<Expr<'source>> {
+		match self.check_eat_any(&[TokenKind::Bang, TokenKind::Minus]) {
+			Some(op) => Ok(Expr::Unary {
+				op,
+				right: Box::new(self.unary()?),
+			}),
+			None => self.call(),
+		}
+	}
+
+	fn call(&mut self) -> Result<Expr<'source>> {
+		let mut expr = self.primary()?;
+		loop {
+			if self.check_eat(TokenKind::LParen).is_some() {
+				expr = self.finish_call(expr)?;
+			} else if self.check_eat(TokenKind::Dot).is_some() {
+				let name = self.consume(
+					TokenKind::Identifier,
+					"Expect property name after '.'.",
+				)?;
+				expr = Expr::Get {
+					object: Box::new(expr),
+					name,
+				};
+			} else {
+				return Ok(expr);
+			}
+		}
+	}
+
+	fn finish_call(&mut self, callee: Expr<'source>) -> Result<Expr<'source>> {
+		let mut args = Vec::new();
+		if !self.check(TokenKind::RParen) {
+			loop {
+				args.push(self.expression()?);
+				if args.len() > MAX_ARGS {
+					let error = self
+						.parser
+						.error("Can't have more than 255 arguments.");
+					self.errors.push(error);
+				}
+
+				if self.check_eat(TokenKind::Comma).is_none() {
+					break;
+				}
+			}
+		}
+
+		let paren =
+			self.consume(TokenKind::RParen, "Expect ')' after arguments.")?;
+		Ok(Expr::Call {
+			callee: Box::new(callee),
+			paren,
+			args,
+		})
+	}
+
+	fn primary(&mut self) -> Result<Expr<'source>> {
+		match self.parser.current.kind {
+			TokenKind::False
+			| TokenKind::Nil
+			| TokenKind::Number
+			| TokenKind::String
+			| TokenKind::True => Ok(Expr::Literal(self.advance())),
+			TokenKind::Identifier => Ok(Expr::Variable(self.advance())),
+			TokenKind::This => Ok(Expr::This(self.advance())),
+			TokenKind::Super => {
+				let keyword = self.advance();
+				self.consume(TokenKind::Dot, "Expect '.' after 'super'.")?;
+				let method = self.consume(
+					TokenKind::Identifier,
+					"Expect superclass method name.",
+				)?;
+				Ok(Expr::Super { keyword, method })
+			},
+			TokenKind::LParen => {
+				self.advance();
+				let expr = self.expression()?;
+				self.consume(
+					TokenKind::RParen,
+					"Expect ')' after expression.",
+				)?;
+				Ok(Expr::Grouping(Box::new(expr)))
+			},
+			_ => self.error_at_current("Expect expression."),
+		}
+	}
+}
@@ -0,0 +1,826 @@
+use super::*;
+use crate::chunk::Bytecode;
+use crate::chunk::Chunk;
+use crate::chunk::Op;
+use crate::compiler::parser;
+use crate::compiler::FunctionKind;
+use crate::compiler::TokenKind;
+use crate::compiler::MAX_UPVALUES;
+use crate::mem::GarbageCollector;
+use crate::obj::LocalInfo;
+use crate::obj::ObjString;
+use crate::value::Value;
+use crate::vm::InlineCache;
+
+const MAX_LOCALS: usize = u8::MAX as _;
+
+/// Lowers `program` to bytecode. The emitted instructions, constant order and
+/// diagnostics follow the single-pass `Compiler` so that both front-ends
+/// produce the same `Chunk`s.
+pub fn lower(
+	gc: &GarbageCollector,
+	program: &Program,
+) -> Result<GcRef<ObjFunction>, Errors> {
+	let mut lowerer = Lowerer {
+		gc,
+		functions: Vec::new(),
+		classes: Vec::new(),
+		errors: Vec::new(),
+		line: 1,
+	};
+
+	lowerer.begin_function(FunctionKind::Script, None);
+	for stmt in &program.stmts {
+		lowerer.stmt(stmt);
+	}
+	lowerer.line = program.eof_line;
+	let (function, _) = lowerer.end_function();
+
+	if lowerer.errors.is_empty() {
+		Ok(function)
+	} else {
+		Err(lowerer.errors)
+	}
+}
+
+struct Lowerer<'ast> {
+	gc:        &'ast GarbageCollector,
+	functions: Vec<FunctionState<'ast>>,
+	classes:   Vec<ClassState>,
+	errors:    Errors,
+	line:      u32,
+}
+
+struct FunctionState<'ast> {
+	function:    GcRef<ObjFunction>,
+	kind:        FunctionKind,
+	locals:      Vec<Local<'ast>>,
+	upvalues:    Vec<Upvalue>,
+	scope_depth: usize,
+}
+
+struct ClassState {
+	has_superclass: bool,
+}
+
+struct Local<'ast> {
+	name:        &'ast str,
+	depth:       Option<usize>,
+	is_captured: bool,
+}
+
+#[derive(Clone, Copy)]
+struct Upvalue {
+	index:    u8,
+	is_local: bool,
+}
+
+impl<'ast> Lowerer<'ast> {
+	fn begin_function(&mut self, kind: FunctionKind, name: Option<&str>) {
+		let mut function = ObjFunction::new(self.gc);
+		// nothing else refers to the function until `end_function`
+		self.gc.push_root(function.downcast());
+		function.name = name.map(|name| ObjString::new(self.gc, name));
+
+		let this_name = match kind {
+			FunctionKind::Function => "",
+			_ => "this",
+		};
+		// a script's slot 0 holds its closure, which isn't a variable
+		if matches!(kind, FunctionKind::Method | FunctionKind::Initializer) {
+			function.locals.push(LocalInfo {
+				name:  ObjString::new(self.gc, this_name),
+				slot:  0,
+				start: 0,
+				end:   usize::MAX,
+			});
+		}
+		self.functions.push(FunctionState {
+			function,
+			kind,
+			locals: vec![Local {
+				name:        this_name,
+				depth:       Some(0),
+				is_captured: false,
+			}],
+			upvalues: Vec::new(),
+			scope_depth: 0,
+		});
+	}
+
+	fn end_function(&mut self) -> (GcRef<ObjFunction>, Vec<Upvalue>) {
+		self.emit_return();
+		self.gc.pop_root();
+		let state = self.functions.pop().unwrap();
+		(state.function, state.upvalues)
+	}
+
+	fn current(&self) -> &FunctionState<'ast> {
+		self.functions.last().unwrap()
+	}
+
+	fn current_mut(&mut self) -> &mut FunctionState<'ast> {
+		self.functions.last_mut().unwrap()
+	}
+
+	fn chunk(&mut self) -> &mut Chunk {
+		&mut self.current_mut().function.chunk
+	}
+}
+
+impl<'ast> Lowerer<'ast> {
+	fn error(&mut self, msg: impl AsRef<str>) {
+		let tok = Token {
+			kind:   TokenKind::Error,
+			text:   "".into(),
+			line:   self.line,
+			column: 0,
+		};
+		self.errors.push(parser::error_at(tok, msg));
+	}
+
+	fn error_at(&mut self, tok: &Token, msg: impl AsRef<str>) {
+		self.errors.push(parser::error_at(tok.clone(), msg));
+	}
+}
+
+impl<'ast> Lowerer<'ast> {
+	fn emit_byte(&mut self, byte: u8) {
+		let line = self.line;
+		self.chunk().push(Bytecode { byte }, line);
+	}
+
+	fn emit_op(&mut self, op: Op) {
+		let line = self.line;
+		self.chunk().push(Bytecode { op }, line);
+	}
+
+	fn emit_ops(&mut self, op1: Op, op2: Op) {
+		self.emit_op(op1);
+		self.emit_op(op2);
+	}
+
+	fn emit_op_arg(&mut self, op: Op, arg: u8) {
+		self.emit_op(op);
+		self.emit_byte(arg);
+	}
+
+	fn emit_jump(&mut self, op: Op) -> usize {
+		self.emit_op(op);
+		self.emit_byte(0xFF);
+		self.emit_byte(0xFF);
+		self.chunk().bytecode.len() - 2
+	}
+
+	fn emit_loop(&mut self, loop_start: usize) {
+		self.emit_op(Op::Loop);
+
+		let offset = self.chunk().bytecode.len() - loop_start + 2;
+		if offset > u16::MAX as _ {
+			self.error("Loop body too large.");
+		}
+
+		self.emit_byte(((offset >> 8) & 0xFF) as _);
+		self.emit_byte((offset & 0xFF) as _);
+	}
+
+	fn emit_return(&mut self) {
+		match self.current().kind {
+			FunctionKind::Initializer => self.emit_op_arg(Op::GetLocal, 0),
+			_ => self.emit_op(Op::Nil),
+		}
+		self.emit_op(Op::Return);
+	}
+
+	fn identifier_constant(&mut self, name: &str) -> u8 {
+		let string = ObjString::new(self.gc, name);
+		self.make_constant(string.value())
+	}
+
+	fn make_constant(&mut self, value: Value) -> u8 {
+		let constants = &mut self.chunk().constants;
+		let Ok(const_id) = constants.len().try_into() else {
+			self.error("Too many constants in one chunk.");
+			return 0;
+		};
+		constants.push(value);
+		const_id
+	}
+
+	fn make_cache(&mut self) -> u8 {
+		let caches = &mut self.chunk().caches;
+		let Ok(cache_id) = caches.len().try_into() else {
+			self.error("Too many property accesses in one chunk.");
+			return 0;
+		};
+		caches.push(InlineCache::default());
+		cache_id
+	}
+
+	fn patch_jump(&mut self, offset: usize) {
+		let jump = self.chunk().bytecode.len() - offset - 2;
+		if jump > u16::MAX as _ {
+			self.error("Too much code to jump over.");
+		}
+
+		let bytecode = &mut self.chunk().bytecode;
+		bytecode[offset] = Bytecode {
+			byte: ((jump >> 8) & 0xFF) as _,
+		};
+		bytecode[offset + 1] = Bytecode {
+			byte: (jump & 0xFF) as _,
+		};
+	}
+}
+
+impl<'ast> Lowerer<'ast> {
+	fn add_local(&mut self, name: &'ast Token) {
+		if self.current().locals.len() == MAX_LOCALS {
+			return self
+				.error_at(name, "Too many local variables in function.");
+		}
+
+		self.current_mut().locals.push(Local {
+			name:        &name.text,
+			depth:       None,
+			is_captured: false,
+		});
+	}
+
+	fn add_upvalue(
+		&mut self,
+		depth: usize,
+		name: &str,
+		index: u8,
+		is_local: bool,
+	) -> u8 {
+		let upvalue = Upvalue { index, is_local };
+		let state = &mut self.functions[depth];
+		if let Some(existing) = state
+			.upvalues
+			.iter()
+			.position(|uv| uv.index == index && uv.is_local == is_local)
+		{
+			return existing as _;
+		}
+
+		if state.upvalues.len() == MAX_UPVALUES {
+			self.error("Too many closure variables in function.");
+			return 0;
+		}
+
+		state.upvalues.push(upvalue);
+		state.function.upvalue_count += 1;
+		state
+			.function
+			.upvalue_names
+			.push(ObjString::new(self.gc, name));
+		(state.upvalues.len() - 1) as _
+	}
+
+	fn begin_scope(&mut self) {
+		self.current_mut().scope_depth += 1;
+	}
+
+	fn declare_variable(&mut self, name: &'ast Token) {
+		let state = self.current();
+		if state.scope_depth == 0 {
+			return;
+		}
+
+		let duplicate = state
+			.locals
+			.iter()
+			.rev()
+			.take_while(|local| {
+				local.depth.is_none_or(|depth| depth >= state.scope_depth)
+			})
+			.any(|local| local.name == name.text);
+		if duplicate {
+			self.error_at(
+				name,
+				"Already a variable with this name in this scope.",
+			);
+		}
+
+		self.add_local(name);
+	}
+
+	fn define_variable(&mut self, global: u8) {
+		if self.current().scope_depth > 0 {
+			self.mark_initialized();
+			self.record_local();
+		} else {
+			self.emit_op_arg(Op::DefineGlobal, global);
+		}
+	}
+
+	fn end_scope(&mut self) {
+		self.current_mut().scope_depth -= 1;
+
+		loop {
+			let state = self.current();
+			let Some(local) = state.locals.last() else {
+				break;
+			};
+			if !local.depth.is_some_and(|depth| depth > state.scope_depth) {
+				break;
+			}
+
+			let op = if local.is_captured {
+				Op::CloseUpvalue
+			} else {
+				Op::Pop
+			};
+			self.emit_op(op);
+
+			let state = self.current_mut();
+			let slot = state.locals.len() - 1;
+			let end = state.function.chunk.bytecode.len();
+			if let Some(info) = state
+				.function
+				.locals
+				.iter_mut()
+				.rev()
+				.find(|info| info.slot as usize == slot)
+			{
+				info.end = end;
+			}
+			state.locals.pop();
+		}
+	}
+
+	fn mark_initialized(&mut self) {
+		let state = self.current_mut();
+		if state.scope_depth == 0 {
+			return;
+		}
+
+		let depth = state.scope_depth;
+		state.locals.last_mut().unwrap().depth = Some(depth);
+	}
+
+	/// Records where the newest local lives for debuggers. Its scope starts
+	/// once its initializer has run, which for a function is after its
+	/// closure is made, though it's marked initialized before.
+	fn record_local(&mut self) {
+		let gc = self.gc;
+		let state = self.current_mut();
+		let local = state.locals.last().unwrap();
+		let info = LocalInfo {
+			name:  ObjString::new(gc, local.name),
+			slot:  (state.locals.len() - 1) as _,
+			start: state.function.chunk.bytecode.len(),
+			end:   usize::MAX,
+		};
+		state.function.locals.push(info);
+	}
+
+	fn parse_variable(&mut self, name: &'ast Token) -> u8 {
+		self.declare_variable(name);
+		if self.current().scope_depth > 0 {
+			0
+		} else {
+			self.identifier_constant(&name.text)
+		}
+	}
+
+	fn resolve_local(&mut self, depth: usize, name: &Token) -> Option<u8> {
+		let (slot, local) = self.functions[depth]
+			.locals
+			.iter()
+			.enumerate()
+			.rev()
+			.find(|(_, local)| local.name == name.text)?;
+
+		if local.depth.is_none() {
+			self.error_at(
+				name,
+				"Can't read local variable in its own initializer.",
+			);
+		}
+		Some(slot as _)
+	}
+
+	fn resolve_upvalue(&mut self, depth: usize, name: &Token) -> Option<u8> {
+		let enclosing = depth.checked_sub(1)?;
+
+		if let Some(local) = self.resolve_local(enclosing, name) {
+			self.functions[enclosing].locals[local as usize].is_captured = true;
+			return Some(self.add_upvalue(depth, &name.text, local, true));
+		}
+
+		let upvalue = self.resolve_upvalue(enclosing, name)?;
+		Some(self.add_upvalue(depth, &name.text, upvalue, false))
+	}
+}
+
+impl<'ast> Lowerer<'ast> {
+	fn stmt(&mut self, stmt: &'ast Stmt) {
+		match stmt {
+			Stmt::Block { stmts, close, .. } => {
+				self.begin_scope();
+				stmts.iter().for_each(|stmt| self.stmt(stmt));
+				self.line = close.line;
+				self.end_scope();
+			},
+			Stmt::Class(decl) => self.class_declaration(decl),
+			Stmt::Expression(expr) => {
+				self.expr(expr);
+				self.emit_op(Op::Pop);
+			},
+			Stmt::For {
+				keyword,
+				initializer,
+				condition,
+				increment,
+				body,
+			} => {
+				self.line = keyword.line;
+				self.begin_scope();
+				if let Some(initializer) = initializer {
+					self.stmt(initializer);
+				}
+
+				let mut loop_start = self.chunk().bytecode.len();
+				let exit_jump = condition.as_ref().map(|condition| {
+					self.expr(condition);
+					let exit_jump = self.emit_jump(Op::JumpIfFalse);
+					self.emit_op(Op::Pop);
+					exit_jump
+				});
+
+				if let Some(increment) = increment {
+					let body_jump = self.emit_jump(Op::Jump);
+					let increment_start = self.chunk().bytecode.len();
+					self.expr(increment);
+					self.emit_op(Op::Pop);
+
+					self.emit_loop(loop_start);
+					loop_start = increment_start;
+					self.patch_jump(body_jump);
+				}
+
+				self.stmt(body);
+				self.emit_loop(loop_start);
+				if let Some(exit_jump) = exit_jump {
+					self.patch_jump(exit_jump);
+					self.emit_op(Op::Pop);
+				}
+
+				self.end_scope();
+			},
+			Stmt::Function(decl) => {
+				let global = self.parse_variable(&decl.name);
+				self.mark_initialized();
+				self.function(FunctionKind::Function, decl);
+				self.define_variable(global);
+			},
+			Stmt::If {
+				keyword,
+				condition,
+				then_branch,
+				else_branch,
+			} => {
+				self.line = keyword.line;
+				self.expr(condition);
+
+				let then_jump = self.emit_jump(Op::JumpIfFalse);
+				self.emit_op(Op::Pop);
+				self.stmt(then_branch);
+
+				let else_jump = self.emit_jump(Op::Jump);
+				self.patch_jump(then_jump);
+				self.emit_op(Op::Pop);
+				if let Some(else_branch) = else_branch {
+					self.stmt(else_branch);
+				}
+				self.patch_jump(else_jump);
+			},
+			Stmt::Print { keyword, value } => {
+				self.line = keyword.line;
+				self.expr(value);
+				self.emit_op(Op::Print);
+			},
+			Stmt::Return { keyword, value } => {
+				self.line = keyword.line;
+				let kind = self.current().kind;
+				if kind == FunctionKind::Script {
+					self.error_at(keyword, "Can't return from top-level code.");
+				}
+
+				match value {
+					None => self.emit_return(),
+					Some(value) => {
+						if kind == FunctionKind::Initializer {
+							self.error_at(
+								keyword,
+								"Can't return a value from an initializer.",
+							);
+						}
+						self.expr(value);
+						self.emit_op(Op::Return);
+					},
+				}
+			},
+			Stmt::Var { name, initializer } => {
+				self.line = name.line;
+				let global = self.parse_variable(name);
+				match initializer {
+					Some(initializer) => self.expr(initializer),
+					None => self.emit_op(Op::Nil),
+				}
+				self.define_variable(global);
+			},
+			Stmt::While {
+				keyword,
+				condition,
+				body,
+			} => {
+				self.line = keyword.line;
+				let loop_start = self.chunk().bytecode.len();
+				self.expr(condition);
+
+				let exit_jump = self.emit_jump(Op::JumpIfFalse);
+				self.emit_op(Op::Pop);
+				self.stmt(body);
+				self.emit_loop(loop_start);
+
+				self.patch_jump(exit_jump);
+				self.emit_op(Op::Pop);
+			},
+		}
+	}
+
+	fn class_declaration(&mut self, decl: &'ast ClassDecl) {
+		self.line = decl.name.line;
+		let name_constant = self.identifier_constant(&decl.name.text);
+		self.declare_variable(&decl.name);
+
+		self.emit_op_arg(Op::Class, name_constant);
+		self.define_variable(name_constant);
+
+		self.classes.push(ClassState {
+			has_superclass: false,
+		});
+
+		if let Some(superclass) = &decl.superclass {
+			self.named_variable(superclass, None);
+			if superclass.text == decl.name.text {
+				self.error_at(superclass, "A class can't inherit from itself.");
+			}
+
+			self.begin_scope();
+			self.current_mut().locals.push(Local {
+				name:        "super",
+				depth:       None,
+				is_captured: false,
+			});
+			self.define_variable(0);
+
+			self.named_variable(&decl.name, None);
+			self.emit_op(Op::Inherit);
+			self.classes.last_mut().unwrap().has_superclass = true;
+		}
+
+		self.named_variable(&decl.name, None);
+		for method in &decl.methods {
+			let constant = self.identifier_constant(&method.name.text);
+			let kind = if method.name.text == "init" {
+				FunctionKind::Initializer
+			} else {
+				FunctionKind::Method
+			};
+			self.function(kind, method);
+			self.emit_op_arg(Op::Method, constant);
+		}
+
+		self.line = decl.close.line;
+		self.emit_op(Op::Pop);
+		if self.classes.pop().unwrap().has_superclass {
+			self.end_scope();
+		}
+	}
+
+	fn function(&mut self, kind: FunctionKind, decl: &'ast FunctionDecl) {
+		self.line = decl.name.line;
+		self.begin_function(kind, Some(&decl.name.text));
+		self.begin_scope();
+
+		for param in &decl.params {
+			self.current_mut().function.arity += 1;
+			let constant = self.parse_variable(param);
+			self.define_variable(constant);
+		}
+
+		decl.body.iter().for_each(|stmt| self.stmt(stmt));
+
+		self.line = decl.close.line;
+		let (function, upvalues) = self.end_function();
+		let constant = self.make_constant(function.value());
+		self.emit_op_arg(Op::Closure, constant);
+		for upvalue in upvalues {
+			self.emit_byte(u8::from(upvalue.is_local));
+			self.emit_byte(upvalue.index);
+		}
+	}
+}
+
+impl<'ast> Lowerer<'ast> {
+	fn expr(&mut self, expr: &'ast Expr) {
+		match expr {
+			Expr::Assign { name, value } => {
+				self.named_variable(name, Some(value))
+			},
+			Expr::Binary { left, op, right } => {
+				self.expr(left);
+				self.expr(right);
+
+				self.line = op.line;
+				match op.kind {
+					TokenKind::BangEqual => self.emit_ops(Op::Equal, Op::Not),
+					TokenKind::EqualEqual => self.emit_op(Op::Equal),
+					TokenKind::Greater => self.emit_op(Op::Greater),
+					TokenKind::GreaterEqual => self.emit_ops(Op::Less, Op::Not),
+					TokenKind::Less => self.emit_op(Op::Less),
+					TokenKind::LessEqual => self.emit_ops(Op::Greater, Op::Not),
+					TokenKind::Minus => self.emit_op(Op::Subtract),
+					TokenKind::Plus => self.emit_op(Op::Add),
+					TokenKind::Slash => self.emit_op(Op::Divide),
+					TokenKind::Star => self.emit_op(Op::Multiply),
+					_ => unreachable!("not a binary operator"),
+				}
+			},
+			Expr::Call {
+				callee,
+				paren,
+				args,
+			} => match callee.as_ref() {
+				Expr::Get { object, name } => {
+					self.expr(object);
+					self.line = name.line;
+					let name = self.identifier_constant(&name.text);
+					let arg_count = self.arguments(args);
+
+					self.line = paren.line;
+					let cache = self.make_cache();
+					self.emit_op_arg(Op::Invoke, name);
+					self.emit_byte(arg_count);
+					self.emit_byte(cache);
+				},
+				Expr::Super { keyword, method } => {
+					self.check_super(keyword);
+					let name = self.identifier_constant(&method.text);
+					self.named_variable(&Token::synthetic("this"), None);
+					let arg_count = self.arguments(args);
+
+					self.line = paren.line;
+					self.named_variable(&Token::synthetic("super"), None);
+					self.emit_op_arg(Op::SuperInvoke, name);
+					self.emit_byte(arg_count);
+				},
+				_ => {
+					self.expr(callee);
+					let arg_count = self.arguments(args);
+
+					self.line = paren.line;
+					self.emit_op_arg(Op::Call, arg_count);
+				},
+			},
+			Expr::Get { object, name } => {
+				self.expr(object);
+				self.line = name.line;
+				let name = self.identifier_constant(&name.text);
+				let cache = self.make_cache();
+				self.emit_op_arg(Op::GetProperty, name);
+				self.emit_byte(cache);
+			},
+			Expr::Grouping(inner) => self.expr(inner),
+			Expr::Literal(token) => {
+				self.line = token.line;
+				match token.kind {
+					TokenKind::False => self.emit_op(Op::False),
+					TokenKind::Nil => self.emit_op(Op::Nil),
+					TokenKind::True => self.emit_op(Op::True),
+					TokenKind::Number => {
+						let number = token.text.parse().unwrap_or(f64::NAN);
+						let constant =
+							self.make_constant(Value::Number(number));
+						self.emit_op_arg(Op::Constant, constant);
+					},
+					TokenKind::String => {
+						let text = &token.text[1..token.text.len() - 1];
+						let string = ObjString::new(self.gc, text);
+						let constant = self.make_constant(string.value());
+						self.emit_op_arg(Op::Constant, constant);
+					},
+					_ => unreachable!("not a literal"),
+				}
+			},
+			Expr::Logical { left, op, right } => {
+				self.expr(left);
+				self.line = op.line;
+
+				if op.kind == TokenKind::And {
+					let end_jump = self.emit_jump(Op::JumpIfFalse);
+					self.emit_op(Op::Pop);
+					self.expr(right);
+					self.patch_jump(end_jump);
+				} else {
+					let else_jump = self.emit_jump(Op::JumpIfFalse);
+					let end_jump = self.emit_jump(Op::Jump);
+
+					self.patch_jump(else_jump);
+					self.emit_op(Op::Pop);
+
+					self.expr(right);
+					self.patch_jump(end_jump);
+				}
+			},
+			Expr::Set {
+				object,
+				name,
+				value,
+			} => {
+				self.expr(object);
+				self.line = name.line;
+				let name = self.identifier_constant(&name.text);
+				self.expr(value);
+				let cache = self.make_cache();
+				self.emit_op_arg(Op::SetProperty, name);
+				self.emit_byte(cache);
+			},
+			Expr::Super { keyword, method } => {
+				self.check_super(keyword);
+				let name = self.identifier_constant(&method.text);
+				self.named_variable(&Token::synthetic("this"), None);
+				self.named_variable(&Token::synthetic("super"), None);
+				self.emit_op_arg(Op::GetSuper, name);
+			},
+			Expr::This(keyword) => {
+				self.line = keyword.line;
+				if self.classes.is_empty() {
+					return self.error_at(
+						keyword,
+						"Can't use 'this' outside of a class.",
+					);
+				}
+				self.named_variable(keyword, None);
+			},
+			Expr::Unary { op, right } => {
+				self.expr(right);
+				self.line = op.line;
+				match op.kind {
+					TokenKind::Bang => self.emit_op(Op::Not),
+					TokenKind::Minus => self.emit_op(Op::Negate),
+					_ => unreachable!("not a unary operator"),
+				}
+			},
+			Expr::Variable(name) => self.named_variable(name, None),
+		}
+	}
+
+	fn arguments(&mut self, args: &'ast [Expr]) -> u8 {
+		args.iter().for_each(|arg| self.expr(arg));
+		args.len().min(u8::MAX as _) as _
+	}
+
+	fn check_super(&mut self, keyword: &Token) {
+		self.line = keyword.line;
+		match self.classes.last() {
+			None => {
+				self.error_at(keyword, "Can't use 'super' outside of a class.")
+			},
+			Some(class) if !class.has_superclass => self.error_at(
+				keyword,
+				"Can't use 'super' in a class with no superclass.",
+			),
+			Some(_) => (),
+		}
+	}
+
+	fn named_variable(&mut self, name: &Token, value: Option<&'ast Expr>) {
+		if name.line != 0 {
+			self.line = name.line;
+		}
+
+		let depth = self.functions.len() - 1;
+		let (get_op, set_op, arg) =
+			if let Some(slot) = self.resolve_local(depth, name) {
+				(Op::GetLocal, Op::SetLocal, slot)
+			} else if let Some(slot) = self.resolve_upvalue(depth, name) {
+				(Op::GetUpvalue, Op::SetUpvalue, slot)
+			} else {
+				let constant = self.identifier_constant(&name.text);
+				(Op::GetGlobal, Op::SetGlobal, constant)
+			};
+
+		match value {
+			Some(value) => {
+				self.expr(value);
+				self.emit_op_arg(set_op, arg);
+			},
+			None => self.emit_op_arg(get_op, arg),
+		}
+	}
+}
@@ -1,6 +1,9 @@
 use crate::mem::GcVec;
+use crate::mem::Marker;
 use crate::mem::Trace;
+use crate::obj::ObjFunction;
 use crate::value::Value;
+use crate::vm::InlineCache;
 
 pub union Bytecode {
 	pub op:    Op,
@@ -10,7 +13,7 @@ pub union Bytecode {
 
 impl Trace for Bytecode {}
 
-#[derive(Clone, Copy)]
+#[derive(Clone, Copy, Debug)]
 #[repr(u8)]
 pub enum Op {
 	// raw value instructions
@@ -74,6 +77,9 @@ pub struct Chunk {
 	pub bytecode:  GcVec<Bytecode>,
 	pub lines:     GcVec<u32>,
 	pub constants: GcVec<Value>,
+	/// One per `GetProperty`, `SetProperty` and `Invoke`, indexed by their
+	/// last operand.
+	pub caches:    GcVec<InlineCache>,
 }
 
 impl Chunk {
@@ -81,6 +87,128 @@ impl Chunk {
 		self.bytecode.push(bytecode);
 		self.lines.push(line);
 	}
+
+	/// Number of bytecodes taken by the instruction at `offset`, including its
+	/// operands.
+	pub fn instruction_len(&self, offset: usize) -> usize {
+		use Op::*;
+
+		match unsafe { self.bytecode[offset].op } {
+			Closure => {
+				let constant = unsafe { self.bytecode[offset + 1].byte };
+				let upvalue_count = self.constants[constant as usize]
+					.as_casted_obj::<ObjFunction>()
+					.map_or(0, |function| function.upvalue_count);
+				2 + 2 * upvalue_count
+			},
+			Invoke => 4,
+			GetProperty | Jump | JumpIfFalse | Loop | SetProperty
+			| SuperInvoke => 3,
+			Call | Class | Constant | DefineGlobal | GetGlobal | GetLocal
+			| GetSuper | GetUpvalue | Method | SetGlobal | SetLocal
+			| SetUpvalue => 2,
+			_ => 1,
+		}
+	}
+
+	/// A listing of every instruction in the chunk, headed by `name`.
+	pub fn disassemble(&self, name: &str) -> String {
+		let mut listing = format!("== {name} ==\n");
+		let mut offset = 0;
+		while offset < self.bytecode.len() {
+			listing += &self.disassemble_instruction(offset);
+			listing.push('\n');
+			offset += self.instruction_len(offset);
+		}
+		listing
+	}
+
+	/// A listing of the instruction at `offset`. Closures get one more line
+	/// per captured upvalue.
+	pub fn disassemble_instruction(&self, offset: usize) -> String {
+		use Op::*;
+
+		let line = self.lines[offset];
+		let line = match offset.checked_sub(1).map(|prev| self.lines[prev]) {
+			Some(prev) if prev == line => "   |".to_owned(),
+			_ => format!("{line:4}"),
+		};
+		let op = unsafe { self.bytecode[offset].op };
+		let byte = |index: usize| unsafe { self.bytecode[offset + index].byte };
+		let constant = |index: usize| self.constants[byte(index) as usize];
+		let name = format!("{op:?}");
+
+		let args = match op {
+			Class | Constant | DefineGlobal | GetGlobal | GetProperty
+			| GetSuper | Method | SetGlobal | SetProperty => {
+				format!("{:4} '{}'", byte(1), constant(1))
+			},
+			Call | GetLocal | GetUpvalue | SetLocal | SetUpvalue => {
+				format!("{:4}", byte(1))
+			},
+			Invoke | SuperInvoke => {
+				format!("({} args) {:4} '{}'", byte(2), byte(1), constant(1))
+			},
+			Jump | JumpIfFalse | Loop => {
+				let jump = (byte(1) as usize) << 8 | byte(2) as usize;
+				let target = match op {
+					Loop => (offset + 3).wrapping_sub(jump),
+					_ => offset + 3 + jump,
+				};
+				format!("{offset:4} -> {target}")
+			},
+			Closure => {
+				let mut args = format!("{:4} {}", byte(1), constant(1));
+				for upvalue in (2..self.instruction_len(offset)).step_by(2) {
+					let kind = if byte(upvalue) > 0 {
+						"local"
+					} else {
+						"upvalue"
+					};
+					args += &format!(
+						"\n{:04}    |                     {kind} {}",
+						offset + upvalue,
+						byte(upvalue + 1),
+					);
+				}
+				args
+			},
+			_ => return format!("{offset:04} {line} {name}"),
+		};
+		format!("{offset:04} {line} {name:<16} {args}")
+	}
+
+	/// Compares the instructions and constants of two chunks, descending into
+	/// nested functions. Line information is ignored.
+	pub fn same_code(&self, other: &Chunk) -> bool {
+		let same_bytes = self.bytecode.len() == other.bytecode.len()
+			&& self
+				.bytecode
+				.iter()
+				.zip(other.bytecode.iter())
+				.all(|(l, r)| unsafe { l.byte == r.byte });
+
+		same_bytes
+			&& self.constants.len() == other.constants.len()
+			&& self.constants.iter().zip(other.constants.iter()).all(
+				|(l, r)| match (
+					l.as_casted_obj::<ObjFunction>(),
+					r.as_casted_obj::<ObjFunction>(),
+				) {
+					(Some(l), Some(r)) => {
+						l.arity == r.arity
+							&& l.upvalue_count == r.upvalue_count
+							&& l.chunk.same_code(&r.chunk)
+					},
+					_ => l == r,
+				},
+			)
+	}
 }
 
-impl Trace for Chunk {}
+impl Trace for Chunk {
+	fn trace(&self, marker: &mut Marker) {
+		self.constants.trace(marker);
+		self.caches.trace(marker);
+	}
+}
@@ -1,49 +1,105 @@
 mod compile_declaration;
 mod compile_expression;
 mod compile_statement;
-mod compiler_root;
-mod parser;
-mod scanner;
+pub(crate) mod parser;
+pub(crate) mod scanner;
 mod token;
 mod token_kind;
 
-use drop_bomb::DropBomb;
-
 use self::compile_expression::Precedence;
-use self::compiler_root::CompilerRoot;
+pub use self::parser::CompileError;
 use self::parser::Parser;
-use self::token::Token;
-use self::token_kind::TokenKind;
+pub use self::token::Token;
+pub use self::token_kind::TokenKind;
 use crate::chunk::Bytecode;
+use crate::chunk::Chunk;
 use crate::chunk::Op;
-use crate::mem::GcPtr;
+use crate::mem::GarbageCollector;
 use crate::mem::GcRef;
-use crate::mem::InlineVec;
+use crate::obj::LocalInfo;
 use crate::obj::ObjFunction;
 use crate::obj::ObjString;
 use crate::value::Value;
+use crate::vm::InlineCache;
 
 type Result<T = (), E = ()> = std::result::Result<T, E>;
 
-const MAX_UPVALUES: usize = u8::MAX as _;
-
-scoped_tls::scoped_thread_local!(static CURRENT: Compiler);
+pub(crate) const MAX_UPVALUES: usize = u8::MAX as _;
+const MAX_LOCALS: usize = u8::MAX as _;
+const MAX_ARGS: usize = u8::MAX as _;
 
 struct ConstId(u8);
 
-pub struct Compiler<'enclosing, 'source> {
-	root:   CompilerRoot<'enclosing, 'source>,
-	errors: Vec<eyre::Report>,
+/// Compiles `source` straight from its tokens to bytecode, returning the
+/// function that runs it as a script.
+pub fn compile(
+	gc: &GarbageCollector,
+	source: &str,
+) -> Result<GcRef<ObjFunction>, Vec<eyre::Report>> {
+	compile_script(gc, source, |compiler| {
+		while compiler.check_eat(TokenKind::Eof).is_none() {
+			compiler.declaration();
+		}
+	})
+}
 
-	function:   GcPtr<ObjFunction>,
-	fn_kind:    FunctionKind,
-	superclass: Option<bool>,
+/// Compiles `source`, which must be a single expression, returning the
+/// function that runs it as a script and returns its value.
+pub fn compile_expression(
+	gc: &GarbageCollector,
+	source: &str,
+) -> Result<GcRef<ObjFunction>, Vec<eyre::Report>> {
+	compile_script(gc, source, |compiler| {
+		if compiler.expression().is_ok()
+			&& compiler
+				.consume(TokenKind::Eof, "Expect end of expression.")
+				.is_ok()
+		{
+			compiler.emit_op(Op::Return);
+		}
+	})
+}
 
-	locals:      InlineVec<{ u8::MAX as _ }, Local<'source>>,
-	upvalues:    InlineVec<MAX_UPVALUES, Upvalue>,
-	scope_depth: usize,
+fn compile_script<'source>(
+	gc: &'source GarbageCollector,
+	source: &'source str,
+	body: impl FnOnce(&mut Compiler<'source>),
+) -> Result<GcRef<ObjFunction>, Vec<eyre::Report>> {
+	let mut compiler = Compiler {
+		parser: Parser::new(source),
+		errors: Vec::new(),
+		gc,
+		functions: Vec::new(),
+		classes: Vec::new(),
+	};
+
+	compiler.begin_function(FunctionKind::Script);
+	body(&mut compiler);
+	let (function, _) = compiler.end_function();
+
+	if compiler.errors.is_empty() {
+		Ok(function)
+	} else {
+		Err(compiler.errors)
+	}
+}
 
-	bomb: DropBomb,
+struct Compiler<'source> {
+	parser:    Parser<'source>,
+	errors:    Vec<eyre::Report>,
+	gc:        &'source GarbageCollector,
+	/// The functions being compiled, innermost last.
+	functions: Vec<FunctionCompiler<'source>>,
+	/// Whether each class being compiled has a superclass, innermost last.
+	classes:   Vec<bool>,
+}
+
+struct FunctionCompiler<'source> {
+	function:    GcRef<ObjFunction>,
+	kind:        FunctionKind,
+	locals:      Vec<Local<'source>>,
+	upvalues:    Vec<Upvalue>,
+	scope_depth: usize,
 }
 
 #[derive(Clone, Copy, PartialEq, Eq)]
@@ -54,127 +110,88 @@ pub enum FunctionKind {
 	Script,
 }
 
-struct Local<'token> {
-	name:        Token<'token>,
+struct Local<'source> {
+	name:        Token<'source>,
 	depth:       Option<usize>,
 	is_captured: bool,
 }
 
-#[derive(Clone)]
+#[derive(Clone, Copy)]
 struct Upvalue {
 	index:    u8,
 	is_local: bool,
 }
 
-impl<'source> Compiler<'static, 'source> {
-	pub fn new(source: &'source str) -> Self {
-		let parser = Parser::new(source);
-		Compiler::init(CompilerRoot::Parser(parser), FunctionKind::Script)
-	}
-}
-
-impl<'enclosing, 'source> Compiler<'enclosing, 'source> {
-	unsafe fn alloc_safe<T, F: FnOnce() -> T>(&self, f: F) -> T {
-		todo!();
-		CURRENT.set(self, f)
-	}
-
-	fn child<'child: 'enclosing>(
-		&'child mut self,
-		fn_kind: FunctionKind,
-	) -> Compiler<'child, 'source> {
-		let root = CompilerRoot::Compiler(self);
-		Compiler::init(root, fn_kind)
-	}
-
-	fn finish(
-		mut self,
-	) -> Result<
-		(GcRef<ObjFunction>, InlineVec<MAX_UPVALUES, Upvalue>),
-		Vec<eyre::Report>,
-	> {
-		if self.errors.is_empty() {
-			self.emit_return();
-			self.bomb.defuse();
-			Ok((self.function.into(), self.upvalues))
-		} else {
-			self.bomb.defuse();
-			Err(self.errors)
+impl<'source> Compiler<'source> {
+	/// Starts compiling a function named by the previous token, or the
+	/// script.
+	fn begin_function(&mut self, kind: FunctionKind) {
+		let mut function = ObjFunction::new(self.gc);
+		// nothing else refers to the function until `end_function`
+		self.gc.push_root(function.downcast());
+		if kind != FunctionKind::Script {
+			let name = &self.parser.previous.text;
+			function.name = Some(ObjString::new(self.gc, name.as_ref()));
 		}
-	}
 
-	fn init(
-		root: CompilerRoot<'enclosing, 'source>,
-		fn_kind: FunctionKind,
-	) -> Self {
-		let mut compiler = Self {
-			root,
-			fn_kind,
-			scope_depth: 0,
-			superclass: None,
-			errors: Vec::new(),
-			function: unsafe { GcPtr::null() },
-			locals: InlineVec::new(),
-			upvalues: InlineVec::new(),
-			bomb: DropBomb::new("Compiler must be `finish`ed to handle errors"),
+		let local_name = match kind {
+			FunctionKind::Function => "",
+			_ => "this",
 		};
-
-		compiler.function =
-			GcPtr::new(unsafe { compiler.alloc_safe(ObjFunction::new) });
-		if fn_kind != FunctionKind::Script {
-			compiler.function.name = Some(unsafe {
-				compiler.alloc_safe(|| {
-					ObjString::new(compiler.parser().previous.text.as_ref())
-				})
+		// a script's slot 0 holds its closure, which isn't a variable
+		if matches!(kind, FunctionKind::Method | FunctionKind::Initializer) {
+			function.locals.push(LocalInfo {
+				name:  ObjString::new(self.gc, local_name),
+				slot:  0,
+				start: 0,
+				end:   usize::MAX,
 			});
 		}
-
-		let local_name = (fn_kind == FunctionKind::Function)
-			.then_some("")
-			.unwrap_or("this");
-		compiler.locals.push(Local {
-			name:        Token::synthetic(local_name),
-			depth:       Some(0),
-			is_captured: false,
+		self.functions.push(FunctionCompiler {
+			function,
+			kind,
+			locals: vec![Local {
+				name:        Token::synthetic(local_name),
+				depth:       Some(0),
+				is_captured: false,
+			}],
+			upvalues: Vec::new(),
+			scope_depth: 0,
 		});
+	}
 
-		compiler
+	fn end_function(&mut self) -> (GcRef<ObjFunction>, Vec<Upvalue>) {
+		self.emit_return();
+		self.gc.pop_root();
+		let current = self.functions.pop().unwrap();
+		(current.function, current.upvalues)
 	}
 
-	fn trace_current(&self) {
-		todo!()
+	fn current(&self) -> &FunctionCompiler<'source> {
+		self.functions.last().unwrap()
 	}
-}
 
-impl<'enclosing, 'source> Compiler<'enclosing, 'source> {
-	fn parser(&self) -> &Parser<'source> {
-		let mut root = &self.root;
-		loop {
-			match root {
-				CompilerRoot::Compiler(compiler) => root = &compiler.root,
-				CompilerRoot::Parser(parser) => return &parser,
-			}
-		}
+	fn current_mut(&mut self) -> &mut FunctionCompiler<'source> {
+		self.functions.last_mut().unwrap()
 	}
 
-	fn parser_mut(&mut self) -> &mut Parser<'source> {
-		let mut root = &mut self.root;
-		loop {
-			match root {
-				CompilerRoot::Compiler(compiler) => root = &mut compiler.root,
-				CompilerRoot::Parser(parser) => return &mut parser,
-			}
-		}
+	fn chunk(&mut self) -> &mut Chunk {
+		&mut self.current_mut().function.chunk
 	}
 }
 
-impl<'enclosing, 'source> Compiler<'enclosing, 'source> {
+impl<'source> Compiler<'source> {
 	fn advance(&mut self) -> Token<'source> {
-		self.parser_mut().advance(&mut self.errors)
+		self.parser.advance(&mut self.errors);
+		self.parser.previous.clone()
+	}
+
+	fn check(&self, kind: TokenKind) -> bool {
+		self.parser.check(kind)
 	}
 
 	fn check_eat(&mut self, kind: TokenKind) -> Option<Token<'source>> {
-		self.parser_mut().check_eat(kind, &mut self.errors)
+		self.check(kind).then(|| self.advance())
 	}
 
 	fn consume(
@@ -182,189 +199,326 @@ impl<'enclosing, 'source> Compiler<'enclosing, 'source> {
 		kind: TokenKind,
 		msg: impl AsRef<str>,
 	) -> Result<Token<'source>> {
-		self.parser_mut().consume(kind, msg, &mut self.errors)
+		self.parser.consume(kind, msg, &mut self.errors)?;
+		Ok(self.parser.previous.clone())
 	}
 
 	fn error<T>(&mut self, msg: impl AsRef<str>) -> Result<T> {
-		self.errors.push(self.parser().error(msg));
+		self.errors.push(self.parser.error(msg));
 		Err(())
 	}
 
-	fn error_at<T>(&mut self, tok: Token, msg: impl AsRef<str>) -> Result<T> {
-		self.errors.push(parser::error_at(tok, msg));
-		Err(())
+	/// Reports an error that doesn't stop the statement from compiling.
+	fn report(&mut self, msg: impl AsRef<str>) {
+		self.errors.push(self.parser.error(msg));
 	}
 
-	fn error_at_current<T>(&mut self, msg: impl AsRef<str>) -> Result<T> {
-		self.errors.push(self.parser().error_at_current(msg));
-		Err(())
+	/// Reports an error at `tok` that doesn't stop the statement from
+	/// compiling.
+	fn report_at(&mut self, tok: &Token, msg: impl AsRef<str>) {
+		self.errors.push(parser::error_at(tok.clone(), msg));
+	}
+
+	/// Skips to the start of the next statement after an error.
+	fn synchronize(&mut self) {
+		while !self.check(TokenKind::Eof) {
+			if self.parser.previous.kind == TokenKind::Semicolon {
+				return;
+			}
+
+			if matches!(
+				self.parser.current.kind,
+				TokenKind::Class
+					| TokenKind::Fun
+					| TokenKind::Var
+					| TokenKind::For
+					| TokenKind::If | TokenKind::While
+					| TokenKind::Print
+					| TokenKind::Return
+			) {
+				return;
+			}
+
+			self.advance();
+		}
 	}
 }
 
-impl<'enclosing, 'source> Compiler<'enclosing, 'source> {
-	fn emit_byte(&mut self, byte: Bytecode) {
-		let line = self.parser().previous.line;
-		self.function.chunk.push(byte, line)
+impl<'source> Compiler<'source> {
+	fn emit_byte(&mut self, byte: u8) {
+		let line = self.parser.previous.line;
+		self.chunk().push(Bytecode { byte }, line);
+	}
+
+	fn emit_op(&mut self, op: Op) {
+		let line = self.parser.previous.line;
+		self.chunk().push(Bytecode { op }, line);
 	}
 
-	fn emit_bytes(&mut self, byte1: Bytecode, byte2: Bytecode) {
-		self.emit_byte(byte1);
-		self.emit_byte(byte2);
+	fn emit_ops(&mut self, op1: Op, op2: Op) {
+		self.emit_op(op1);
+		self.emit_op(op2);
+	}
+
+	fn emit_op_arg(&mut self, op: Op, arg: u8) {
+		self.emit_op(op);
+		self.emit_byte(arg);
 	}
 
 	fn emit_jump(&mut self, op: Op) -> usize {
-		self.emit_byte(Bytecode { op });
-		let offset = self.function.chunk.bytecode.len() - 2;
-		self.emit_bytes(Bytecode { byte: 0xFF }, Bytecode { byte: 0xFF });
-		offset
+		self.emit_op(op);
+		self.emit_byte(0xFF);
+		self.emit_byte(0xFF);
+		self.chunk().bytecode.len() - 2
 	}
 
-	fn emit_loop(&mut self, loop_start: usize) -> Result {
-		self.emit_byte(Bytecode { op: Op::Loop });
+	fn emit_loop(&mut self, loop_start: usize) {
+		self.emit_op(Op::Loop);
 
-		let offset = self.function.chunk.bytecode.len() - loop_start + 2;
+		let offset = self.chunk().bytecode.len() - loop_start + 2;
 		if offset > u16::MAX as _ {
-			return self.error("Loop body too large.");
+			self.report("Loop body too large.");
 		}
 
-		self.emit_bytes(
-			Bytecode {
-				byte: ((offset >> 8) & 0xFF) as _,
-			},
-			Bytecode {
-				byte: (offset & 0xFF) as _,
-			},
-		);
-		Ok(())
+		self.emit_byte(((offset >> 8) & 0xFF) as _);
+		self.emit_byte((offset & 0xFF) as _);
 	}
 
 	fn emit_return(&mut self) {
-		match self.fn_kind {
-			FunctionKind::Initializer => self
-				.emit_bytes(Bytecode { op: Op::GetLocal }, Bytecode {
-					byte: 0,
-				}),
-			_ => self.emit_byte(Bytecode { op: Op::Nil }),
+		match self.current().kind {
+			FunctionKind::Initializer => self.emit_op_arg(Op::GetLocal, 0),
+			_ => self.emit_op(Op::Nil),
+		}
+		self.emit_op(Op::Return);
+	}
+
+	fn identifier_constant(&mut self, name: &Token) -> ConstId {
+		let string = ObjString::new(self.gc, name.text.as_ref());
+		self.make_constant(string.value())
+	}
+
+	fn make_constant(&mut self, value: Value) -> ConstId {
+		let constants = &mut self.chunk().constants;
+		let Ok(const_id) = constants.len().try_into() else {
+			self.report("Too many constants in one chunk.");
+			return ConstId(0);
+		};
+		constants.push(value);
+		ConstId(const_id)
+	}
+
+	fn make_cache(&mut self) -> u8 {
+		let caches = &mut self.chunk().caches;
+		let Ok(cache_id) = caches.len().try_into() else {
+			self.report("Too many property accesses in one chunk.");
+			return 0;
+		};
+		caches.push(InlineCache::default());
+		cache_id
+	}
+
+	fn patch_jump(&mut self, offset: usize) {
+		let jump = self.chunk().bytecode.len() - offset - 2;
+		if jump > u16::MAX as _ {
+			self.report("Too much code to jump over.");
 		}
-		self.emit_byte(Bytecode { op: Op::Return });
+
+		let bytecode = &mut self.chunk().bytecode;
+		bytecode[offset] = Bytecode {
+			byte: ((jump >> 8) & 0xFF) as _,
+		};
+		bytecode[offset + 1] = Bytecode {
+			byte: (jump & 0xFF) as _,
+		};
 	}
 }
 
-impl<'enclosing, 'source> Compiler<'enclosing, 'source> {
-	fn add_local(&mut self, name: Token<'source>) -> Result {
-		if self.locals.is_full() {
+impl<'source> Compiler<'source> {
+	fn add_local(&mut self, name: Token<'source>) {
+		if self.current().locals.len() == MAX_LOCALS {
 			return self
-				.error_at(name, "Too many local variables in function.");
+				.report_at(&name, "Too many local variables in function.");
 		}
 
-		self.locals.push(Local {
+		self.current_mut().locals.push(Local {
 			name,
 			depth: None,
 			is_captured: false,
 		});
-		Ok(())
+	}
+
+	fn add_upvalue(
+		&mut self,
+		depth: usize,
+		name: &Token,
+		index: u8,
+		is_local: bool,
+	) -> u8 {
+		let upvalue = Upvalue { index, is_local };
+		let current = &mut self.functions[depth];
+		if let Some(existing) = current
+			.upvalues
+			.iter()
+			.position(|uv| uv.index == index && uv.is_local == is_local)
+		{
+			return existing as _;
+		}
+
+		if current.upvalues.len() == MAX_UPVALUES {
+			self.report("Too many closure variables in function.");
+			return 0;
+		}
+
+		current.upvalues.push(upvalue);
+		current.function.upvalue_count += 1;
+		let name = ObjString::new(self.gc, name.text.as_ref());
+		current.function.upvalue_names.push(name);
+		(current.upvalues.len() - 1) as _
 	}
 
 	fn begin_scope(&mut self) {
-		self.scope_depth += 1;
+		self.current_mut().scope_depth += 1;
 	}
 
-	fn declare_variable(&mut self) -> Result {
-		if self.scope_depth == 0 {
-			return Ok(());
+	fn declare_variable(&mut self, name: Token<'source>) {
+		let current = self.current();
+		if current.scope_depth == 0 {
+			return;
 		}
 
-		let name = self.parser().previous;
-		for local in self.locals.iter() {
-			if let Some(depth) = local.depth && depth < self.scope_depth {
-				break;
-			}
-
-			if local.name.text == name.text {
-				return self.error_at(
-					name,
-					format!(
-						"Variable {} already defined in this scope.",
-						name.text
-					),
-				);
-			}
+		let duplicate = current
+			.locals
+			.iter()
+			.rev()
+			.take_while(|local| {
+				local.depth.is_none_or(|depth| depth >= current.scope_depth)
+			})
+			.any(|local| local.name.text == name.text);
+		if duplicate {
+			self.report_at(
+				&name,
+				"Already a variable with this name in this scope.",
+			);
 		}
 
-		self.add_local(name)
+		self.add_local(name);
 	}
 
-	fn define_variable(&mut self, ConstId(byte): ConstId) {
-		if self.scope_depth > 0 {
+	fn define_variable(&mut self, ConstId(global): ConstId) {
+		if self.current().scope_depth > 0 {
 			self.mark_initialized();
+			self.record_local();
 		} else {
-			self.emit_bytes(
-				Bytecode {
-					op: Op::DefineGlobal,
-				},
-				Bytecode { byte },
-			);
+			self.emit_op_arg(Op::DefineGlobal, global);
 		}
 	}
 
 	fn end_scope(&mut self) {
-		self.scope_depth -= 1;
+		self.current_mut().scope_depth -= 1;
 
-		while let Some(local) = self.locals.last()
-				&& let Some(depth) = local.depth
-				&& depth > self.scope_depth {
-			self.emit_byte(Bytecode {
-				op: if local.is_captured { Op::CloseUpvalue } else { Op::Pop }
-			});
-			self.locals.pop();
+		loop {
+			let current = self.current();
+			let Some(local) = current.locals.last() else {
+				break;
+			};
+			if !local.depth.is_some_and(|depth| depth > current.scope_depth) {
+				break;
+			}
+
+			let op = if local.is_captured {
+				Op::CloseUpvalue
+			} else {
+				Op::Pop
+			};
+			self.emit_op(op);
+
+			let current = self.current_mut();
+			let slot = current.locals.len() - 1;
+			let end = current.function.chunk.bytecode.len();
+			if let Some(info) = current
+				.function
+				.locals
+				.iter_mut()
+				.rev()
+				.find(|info| info.slot as usize == slot)
+			{
+				info.end = end;
+			}
+			current.locals.pop();
 		}
 	}
 
-	fn identifier_constant(&mut self, token: Token) -> Result<ConstId> {
-		let string = unsafe { self.alloc_safe(|| ObjString::new(token.text)) };
-		self.make_constant(Value::Obj(string.downcast()))
-	}
+	fn mark_initialized(&mut self) {
+		let current = self.current_mut();
+		if current.scope_depth == 0 {
+			return;
+		}
 
-	fn make_constant(&mut self, value: Value) -> Result<ConstId> {
-		let Ok(const_id) = self.function.chunk.constants.len().try_into() else {
-			return self.error("Too many constants in one chunk.");
+		let depth = current.scope_depth;
+		current.locals.last_mut().unwrap().depth = Some(depth);
+	}
+
+	/// Records where the newest local lives for debuggers. Its scope starts
+	/// once its initializer has run, which for a function is after its
+	/// closure is made, though it's marked initialized before.
+	fn record_local(&mut self) {
+		let gc = self.gc;
+		let current = self.current_mut();
+		let local = current.locals.last().unwrap();
+		let info = LocalInfo {
+			name:  ObjString::new(gc, local.name.text.as_ref()),
+			slot:  (current.locals.len() - 1) as _,
+			start: current.function.chunk.bytecode.len(),
+			end:   usize::MAX,
 		};
-		self.function.chunk.constants.push(value);
-		Ok(ConstId(const_id))
+		current.function.locals.push(info);
 	}
 
-	fn mark_initialized(&mut self) {
-		if self.scope_depth > 0 {
-			let local_ii = self.locals.len() - 1;
-			let mut local = &mut self.locals[local_ii];
-			local.depth = Some(self.scope_depth);
+	/// Consumes a variable's name and declares it, returning the constant
+	/// that names it if it's a global.
+	fn parse_variable(&mut self, msg: &str) -> Result<ConstId> {
+		let name = self.consume(TokenKind::Identifier, msg)?;
+		self.declare_variable(name.clone());
+		if self.current().scope_depth > 0 {
+			Ok(ConstId(0))
+		} else {
+			Ok(self.identifier_constant(&name))
 		}
 	}
 
-	fn resolve_local(&mut self, name: Token) -> Result<Option<usize>> {
-		for (ii, local) in self.locals.iter().enumerate() {
-			if local.name.text == name.text {
-				if local.depth.is_none() {
-					return self.error_at(
-						name,
-						"Can't read local variable in its own initializer.",
-					);
-				}
+	fn resolve_local(&mut self, depth: usize, name: &Token) -> Option<u8> {
+		let (slot, local) = self.functions[depth]
+			.locals
+			.iter()
+			.enumerate()
+			.rev()
+			.find(|(_, local)| local.name.text == name.text)?;
 
-				return Ok(Some(ii));
-			}
+		if local.depth.is_none() {
+			self.report_at(
+				name,
+				"Can't read local variable in its own initializer.",
+			);
+		}
+		Some(slot as _)
+	}
+
+	fn resolve_upvalue(&mut self, depth: usize, name: &Token) -> Option<u8> {
+		let enclosing = depth.checked_sub(1)?;
+
+		if let Some(local) = self.resolve_local(enclosing, name) {
+			self.functions[enclosing].locals[local as usize].is_captured = true;
+			return Some(self.add_upvalue(depth, name, local, true));
 		}
 
-		Ok(None)
+		let upvalue = self.resolve_upvalue(enclosing, name)?;
+		Some(self.add_upvalue(depth, name, upvalue, false))
 	}
 }
 
-impl<'enclosing, 'source: 'enclosing> Compiler<'enclosing, 'source> {
-	fn block(&mut self) -> Result<()> {
-		while !matches!(
-			self.parser().current.kind,
-			TokenKind::RBrace | TokenKind::Eof
-		) {
+impl<'source> Compiler<'source> {
+	fn block(&mut self) -> Result {
+		while !self.check(TokenKind::RBrace) && !self.check(TokenKind::Eof) {
 			self.declaration();
 		}
 
@@ -373,34 +527,18 @@ impl<'enclosing, 'source: 'enclosing> Compiler<'enclosing, 'source> {
 	}
 
 	fn declaration(&mut self) {
-		let parse_fn = match self.parser().current.kind {
-			TokenKind::Class => Self::class_declaration,
-			TokenKind::Fun => Self::fun_declaration,
-			TokenKind::Var => Self::var_declaration,
-			_ => Self::statement,
+		let res = if self.check_eat(TokenKind::Class).is_some() {
+			self.class_declaration()
+		} else if self.check_eat(TokenKind::Fun).is_some() {
+			self.fun_declaration()
+		} else if self.check_eat(TokenKind::Var).is_some() {
+			self.var_declaration()
+		} else {
+			self.statement()
 		};
-		self.advance();
-
-		if parse_fn(self).is_err() {
-			let parser = self.parser_mut();
-			while parser.current.kind != TokenKind::Eof {
-				if parser.previous.kind == TokenKind::Semicolon {
-					return;
-				}
 
-				if matches!(
-					parser.current.kind,
-					TokenKind::Class
-						| TokenKind::Fun | TokenKind::Var
-						| TokenKind::For | TokenKind::If
-						| TokenKind::While | TokenKind::Print
-						| TokenKind::Return
-				) {
-					return;
-				}
-
-				parser.advance(&mut self.errors);
-			}
+		if res.is_err() {
+			self.synchronize();
 		}
 	}
 
@@ -408,124 +546,94 @@ impl<'enclosing, 'source: 'enclosing> Compiler<'enclosing, 'source> {
 		self.parse_precedence(Precedence::Assignment)
 	}
 
-	fn function(&mut self, fn_kind: FunctionKind) -> Result {
-		let mut compiler = self.child(fn_kind);
-		compiler.begin_scope();
-
-		if compiler
-			.consume(TokenKind::LParen, "Expect '(' after function name.")
-			.is_err()
-		{
-			compiler.bomb.defuse();
-			self.errors.extend(compiler.errors);
-			return Err(compiler.errors);
+	/// Compiles the function whose name was just consumed, and emits the
+	/// closure that makes it.
+	fn function(&mut self, kind: FunctionKind) -> Result {
+		self.begin_function(kind);
+		self.begin_scope();
+		let res = self.function_body();
+		let (function, upvalues) = self.end_function();
+		res?;
+
+		let ConstId(constant) = self.make_constant(function.value());
+		self.emit_op_arg(Op::Closure, constant);
+		for upvalue in upvalues {
+			self.emit_byte(u8::from(upvalue.is_local));
+			self.emit_byte(upvalue.index);
 		}
+		Ok(())
+	}
 
-		if !compiler.parser().check(TokenKind::RParen) {
-			let params_ok = loop {
-				compiler.function.arity += 1;
-				if compiler.function.arity > 255 {
-					compiler.error_at_current::<()>(
+	fn function_body(&mut self) -> Result {
+		self.consume(TokenKind::LParen, "Expect '(' after function name.")?;
+		if !self.check(TokenKind::RParen) {
+			loop {
+				if self.current().function.arity == MAX_ARGS {
+					let error = self.parser.error_at_current(
 						"Can't have more than 255 parameters.",
 					);
-					break false;
+					self.errors.push(error);
 				}
+				self.current_mut().function.arity += 1;
+				let constant = self.parse_variable("Expect parameter name.")?;
+				self.define_variable(constant);
 
-				let var_id =
-					match compiler.parse_variable("Expect parameter name.") {
-						Ok(constant) => constant,
-						Err(error) => break false,
-					};
-				compiler.define_variable(var_id);
-
-				if compiler.check_eat(TokenKind::Comma).is_none() {
-					break true;
-				}
-			};
-			let ok = params_ok && compiler.parser().check(TokenKind::RParen);
-
-			if !ok {
-				while !compiler.parser().check(TokenKind::RParen) {
-					compiler.error_at_current::<()>(
-						"Expect ')' after function parameters.",
-					);
-					compiler.advance();
+				if self.check_eat(TokenKind::Comma).is_none() {
+					break;
 				}
 			}
 		}
 
-		compiler.advance(); // RParen
-
-		if compiler
-			.consume(TokenKind::LBrace, "Expect '{' before function body.")
-			.is_err()
-		{
-			compiler.bomb.defuse();
-			return Err(compiler.errors);
-		}
-
-		if compiler.block().is_err() {
-			compiler.bomb.defuse();
-			return Err(compiler.errors);
-		}
-
-		compiler.finish().map(|(function, upvalues)| {
-			let ConstId(byte) = self.make_constant(function.value())?;
-			self.emit_bytes(Bytecode { op: Op::Closure }, Bytecode { byte });
-			for upvalue in upvalues {
-				let local_byte = u8::from(upvalue.is_local);
-				self.emit_bytes(Bytecode { byte: local_byte }, Bytecode {
-					byte: upvalue.index,
-				});
-			}
-			Ok(())
-		})
+		self.consume(TokenKind::RParen, "Expect ')' after parameters.")?;
+		self.consume(TokenKind::LBrace, "Expect '{' before function body.")?;
+		self.block()
 	}
 
 	fn method(&mut self) -> Result {
-		let name = self.consume(TokenKind::Identifier, "Expect class name.")?;
-		let ConstId(constant) = self.identifier_constant(name)?;
+		let name =
+			self.consume(TokenKind::Identifier, "Expect method name.")?;
+		let ConstId(constant) = self.identifier_constant(&name);
 
 		let kind = if name.text == "init" {
 			FunctionKind::Initializer
 		} else {
 			FunctionKind::Method
 		};
-		self.function(kind)
-			.map_err(|errs| self.errors.extend(errs))??;
-
-		self.emit_bytes(Bytecode { op: Op::Method }, Bytecode {
-			byte: constant,
-		});
+		self.function(kind)?;
+		self.emit_op_arg(Op::Method, constant);
 		Ok(())
 	}
 
-	fn parse_variable(&mut self, error_message: &str) -> Result<ConstId> {
-		let name = self.consume(TokenKind::Identifier, error_message)?;
-		self.declare_variable()?;
-		if self.scope_depth > 0 {
-			Ok(ConstId(0))
-		} else {
-			self.identifier_constant(name)
-		}
-	}
-
-	fn statement(&mut self) -> Result<()> {
-		let parse_fn = match self.parser().current.kind {
-			TokenKind::LBrace => |p: &mut Self| {
-				p.begin_scope();
-				p.block()?;
-				Ok(p.end_scope())
+	fn statement(&mut self) -> Result {
+		match self.parser.current.kind {
+			TokenKind::For => {
+				self.advance();
+				self.for_statement()
 			},
-			TokenKind::For => Self::for_statement,
-			TokenKind::If => Self::if_statement,
-			TokenKind::Print => Self::print_statement,
-			TokenKind::Return => Self::return_statement,
-			TokenKind::While => Self::while_statement,
-			_ => Self::expression_statement,
-		};
-
-		self.advance();
-		parse_fn(self)
+			TokenKind::If => {
+				self.advance();
+				self.if_statement()
+			},
+			TokenKind::LBrace => {
+				self.advance();
+				self.begin_scope();
+				let res = self.block();
+				self.end_scope();
+				res
+			},
+			TokenKind::Print => {
+				self.advance();
+				self.print_statement()
+			},
+			TokenKind::Return => {
+				self.advance();
+				self.return_statement()
+			},
+			TokenKind::While => {
+				self.advance();
+				self.while_statement()
+			},
+			_ => self.expression_statement(),
+		}
 	}
 }
@@ -1,49 +1,21 @@
 use super::*;
 
-impl<'enclosing, 'source: 'enclosing> Compiler<'enclosing, 'source> {
+impl<'source> Compiler<'source> {
 	pub(super) fn class_declaration(&mut self) -> Result {
 		let class_name =
 			self.consume(TokenKind::Identifier, "Expect class name.")?;
-		let name_constant = self.identifier_constant(class_name)?;
-		self.declare_variable()?;
+		let ConstId(name_constant) = self.identifier_constant(&class_name);
+		self.declare_variable(class_name.clone());
 
-		self.emit_bytes(Bytecode { op: Op::Class }, Bytecode {
-			byte: name_constant.0,
-		});
-		self.define_variable(name_constant);
+		self.emit_op_arg(Op::Class, name_constant);
+		self.define_variable(ConstId(name_constant));
 
-		let prev_superclass = self.superclass.take();
-		self.superclass = Some(false);
-		if self.check_eat(TokenKind::Less).is_some() {
-			self.consume(TokenKind::Identifier, "Expect superclass name.")?;
-			self.variable(false);
-			if self.parser().previous.text == class_name.text {
-				return self.error("A class can't inherit from itself.");
-			}
-
-			self.begin_scope();
-			self.add_local(Token::synthetic("super"))?;
-			self.define_variable(ConstId(0));
-
-			self.named_variable(class_name, false);
-			self.emit_byte(Bytecode { op: Op::Inherit });
-			self.superclass = Some(true);
-		}
-
-		self.named_variable(class_name, false);
-		self.consume(TokenKind::LBrace, "Expect '{' before class body.")?;
-		while !self.parser().check(TokenKind::RBrace)
-			&& !self.parser().check(TokenKind::Eof)
-		{
-			self.method();
-		}
-		self.consume(TokenKind::RBrace, "Expect '}' after class body.")?;
-
-		self.emit_byte(Bytecode { op: Op::Pop });
-		if self.superclass.unwrap() {
+		self.classes.push(false);
+		let res = self.class_body(&class_name);
+		if self.classes.pop().unwrap() {
 			self.end_scope();
 		}
-		Ok(self.superclass = prev_superclass)
+		res
 	}
 
 	pub(super) fn fun_declaration(&mut self) -> Result {
@@ -56,10 +28,10 @@ impl<'enclosing, 'source: 'enclosing> Compiler<'enclosing, 'source> {
 
 	pub(super) fn var_declaration(&mut self) -> Result {
 		let global = self.parse_variable("Expect variable name.")?;
-		if self.check_eat(TokenKind::Equal) {
+		if self.check_eat(TokenKind::Equal).is_some() {
 			self.expression()?;
 		} else {
-			self.emit_byte(Bytecode { op: Op::Nil });
+			self.emit_op(Op::Nil);
 		}
 		self.consume(
 			TokenKind::Semicolon,
@@ -70,3 +42,43 @@ impl<'enclosing, 'source: 'enclosing> Compiler<'enclosing, 'source> {
 		Ok(())
 	}
 }
+
+impl<'source> Compiler<'source> {
+	/// Compiles a class's superclass clause and methods, leaving the scope
+	/// that binds `super` open for `class_declaration` to end.
+	fn class_body(&mut self, class_name: &Token<'source>) -> Result {
+		if self.check_eat(TokenKind::Less).is_some() {
+			let superclass =
+				self.consume(TokenKind::Identifier, "Expect superclass name.")?;
+			self.variable(false)?;
+			if superclass.text == class_name.text {
+				self.report_at(
+					&superclass,
+					"A class can't inherit from itself.",
+				);
+			}
+
+			self.begin_scope();
+			self.current_mut().locals.push(Local {
+				name:        Token::synthetic("super"),
+				depth:       None,
+				is_captured: false,
+			});
+			self.define_variable(ConstId(0));
+
+			self.named_variable(class_name, false)?;
+			self.emit_op(Op::Inherit);
+			*self.classes.last_mut().unwrap() = true;
+		}
+
+		self.named_variable(class_name, false)?;
+		self.consume(TokenKind::LBrace, "Expect '{' before class body.")?;
+		while !self.check(TokenKind::RBrace) && !self.check(TokenKind::Eof) {
+			self.method()?;
+		}
+		self.consume(TokenKind::RBrace, "Expect '}' after class body.")?;
+
+		self.emit_op(Op::Pop);
+		Ok(())
+	}
+}
@@ -1,5 +1,6 @@
 use super::*;
 
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
 pub enum Precedence {
 	None,
 	Assignment,
@@ -14,19 +15,328 @@ pub enum Precedence {
 	Primary,
 }
 
-impl<'enclosing, 'source: 'enclosing> Compiler<'enclosing, 'source> {
+impl Precedence {
+	fn next(self) -> Self {
+		match self {
+			Self::None => Self::Assignment,
+			Self::Assignment => Self::Or,
+			Self::Or => Self::And,
+			Self::And => Self::Equality,
+			Self::Equality => Self::Comparison,
+			Self::Comparison => Self::Term,
+			Self::Term => Self::Factor,
+			Self::Factor => Self::Unary,
+			Self::Unary => Self::Call,
+			Self::Call | Self::Primary => Self::Primary,
+		}
+	}
+}
+
+type ParseFn<'source> = fn(&mut Compiler<'source>, bool) -> Result;
+
+struct ParseRule<'source> {
+	prefix:     Option<ParseFn<'source>>,
+	infix:      Option<ParseFn<'source>>,
+	precedence: Precedence,
+}
+
+impl<'source> ParseRule<'source> {
+	fn new(
+		prefix: Option<ParseFn<'source>>,
+		infix: Option<ParseFn<'source>>,
+		precedence: Precedence,
+	) -> Self {
+		Self {
+			prefix,
+			infix,
+			precedence,
+		}
+	}
+}
+
+fn rule<'source>(kind: TokenKind) -> ParseRule<'source> {
+	use Precedence as P;
+
+	match kind {
+		TokenKind::LParen => ParseRule::new(
+			Some(Compiler::grouping),
+			Some(Compiler::call),
+			P::Call,
+		),
+		TokenKind::Dot => ParseRule::new(None, Some(Compiler::dot), P::Call),
+		TokenKind::Minus => ParseRule::new(
+			Some(Compiler::unary),
+			Some(Compiler::binary),
+			P::Term,
+		),
+		TokenKind::Plus => {
+			ParseRule::new(None, Some(Compiler::binary), P::Term)
+		},
+		TokenKind::Slash | TokenKind::Star => {
+			ParseRule::new(None, Some(Compiler::binary), P::Factor)
+		},
+		TokenKind::Bang => ParseRule::new(Some(Compiler::unary), None, P::None),
+		TokenKind::BangEqual | TokenKind::EqualEqual => {
+			ParseRule::new(None, Some(Compiler::binary), P::Equality)
+		},
+		TokenKind::Greater
+		| TokenKind::GreaterEqual
+		| TokenKind::Less
+		| TokenKind::LessEqual => {
+			ParseRule::new(None, Some(Compiler::binary), P::Comparison)
+		},
+		TokenKind::Identifier => {
+			ParseRule::new(Some(Compiler::variable), None, P::None)
+		},
+		TokenKind::String => {
+			ParseRule::new(Some(Compiler::string), None, P::None)
+		},
+		TokenKind::Number => {
+			ParseRule::new(Some(Compiler::number), None, P::None)
+		},
+		TokenKind::And => ParseRule::new(None, Some(Compiler::and), P::And),
+		TokenKind::Or => ParseRule::new(None, Some(Compiler::or), P::Or),
+		TokenKind::False | TokenKind::Nil | TokenKind::True => {
+			ParseRule::new(Some(Compiler::literal), None, P::None)
+		},
+		TokenKind::Super => {
+			ParseRule::new(Some(Compiler::super_), None, P::None)
+		},
+		TokenKind::This => ParseRule::new(Some(Compiler::this), None, P::None),
+		_ => ParseRule::new(None, None, P::None),
+	}
+}
+
+impl<'source> Compiler<'source> {
 	pub(super) fn parse_precedence(
 		&mut self,
 		precedence: Precedence,
-	) -> Result<()> {
-		todo!()
+	) -> Result {
+		self.advance();
+		let Some(prefix) = rule(self.parser.previous.kind).prefix else {
+			return self.error("Expect expression.");
+		};
+
+		let can_assign = precedence <= Precedence::Assignment;
+		prefix(self, can_assign)?;
+
+		while precedence <= rule(self.parser.current.kind).precedence {
+			self.advance();
+			let infix = rule(self.parser.previous.kind).infix.unwrap();
+			infix(self, can_assign)?;
+		}
+
+		if can_assign && self.check_eat(TokenKind::Equal).is_some() {
+			return self.error("Invalid assignment target.");
+		}
+		Ok(())
+	}
+
+	pub(super) fn named_variable(
+		&mut self,
+		name: &Token,
+		can_assign: bool,
+	) -> Result {
+		let depth = self.functions.len() - 1;
+		let (get_op, set_op, arg) =
+			if let Some(slot) = self.resolve_local(depth, name) {
+				(Op::GetLocal, Op::SetLocal, slot)
+			} else if let Some(slot) = self.resolve_upvalue(depth, name) {
+				(Op::GetUpvalue, Op::SetUpvalue, slot)
+			} else {
+				let ConstId(constant) = self.identifier_constant(name);
+				(Op::GetGlobal, Op::SetGlobal, constant)
+			};
+
+		if can_assign && self.check_eat(TokenKind::Equal).is_some() {
+			self.expression()?;
+			self.emit_op_arg(set_op, arg);
+		} else {
+			self.emit_op_arg(get_op, arg);
+		}
+		Ok(())
+	}
+
+	pub(super) fn variable(&mut self, can_assign: bool) -> Result {
+		let name = self.parser.previous.clone();
+		self.named_variable(&name, can_assign)
+	}
+}
+
+impl<'source> Compiler<'source> {
+	fn and(&mut self, _can_assign: bool) -> Result {
+		let end_jump = self.emit_jump(Op::JumpIfFalse);
+		self.emit_op(Op::Pop);
+		self.parse_precedence(Precedence::And)?;
+		self.patch_jump(end_jump);
+		Ok(())
+	}
+
+	fn arguments(&mut self) -> Result<u8> {
+		let mut arg_count = 0_usize;
+		if !self.check(TokenKind::RParen) {
+			loop {
+				self.expression()?;
+				arg_count += 1;
+				if arg_count > MAX_ARGS {
+					self.report("Can't have more than 255 arguments.");
+				}
+
+				if self.check_eat(TokenKind::Comma).is_none() {
+					break;
+				}
+			}
+		}
+
+		self.consume(TokenKind::RParen, "Expect ')' after arguments.")?;
+		Ok(arg_count.min(MAX_ARGS) as _)
+	}
+
+	fn binary(&mut self, _can_assign: bool) -> Result {
+		let op = self.parser.previous.kind;
+		self.parse_precedence(rule(op).precedence.next())?;
+
+		match op {
+			TokenKind::BangEqual => self.emit_ops(Op::Equal, Op::Not),
+			TokenKind::EqualEqual => self.emit_op(Op::Equal),
+			TokenKind::Greater => self.emit_op(Op::Greater),
+			TokenKind::GreaterEqual => self.emit_ops(Op::Less, Op::Not),
+			TokenKind::Less => self.emit_op(Op::Less),
+			TokenKind::LessEqual => self.emit_ops(Op::Greater, Op::Not),
+			TokenKind::Minus => self.emit_op(Op::Subtract),
+			TokenKind::Plus => self.emit_op(Op::Add),
+			TokenKind::Slash => self.emit_op(Op::Divide),
+			TokenKind::Star => self.emit_op(Op::Multiply),
+			_ => unreachable!("not a binary operator"),
+		}
+		Ok(())
+	}
+
+	fn call(&mut self, _can_assign: bool) -> Result {
+		let arg_count = self.arguments()?;
+		self.emit_op_arg(Op::Call, arg_count);
+		Ok(())
+	}
+
+	fn check_super(&mut self, keyword: &Token) {
+		match self.classes.last() {
+			None => {
+				self.report_at(keyword, "Can't use 'super' outside of a class.")
+			},
+			Some(false) => self.report_at(
+				keyword,
+				"Can't use 'super' in a class with no superclass.",
+			),
+			Some(true) => (),
+		}
+	}
+
+	fn dot(&mut self, can_assign: bool) -> Result {
+		let name = self.consume(
+			TokenKind::Identifier,
+			"Expect property name after '.'.",
+		)?;
+		let ConstId(name) = self.identifier_constant(&name);
+
+		if can_assign && self.check_eat(TokenKind::Equal).is_some() {
+			self.expression()?;
+			let cache = self.make_cache();
+			self.emit_op_arg(Op::SetProperty, name);
+			self.emit_byte(cache);
+		} else if self.check_eat(TokenKind::LParen).is_some() {
+			let arg_count = self.arguments()?;
+			let cache = self.make_cache();
+			self.emit_op_arg(Op::Invoke, name);
+			self.emit_byte(arg_count);
+			self.emit_byte(cache);
+		} else {
+			let cache = self.make_cache();
+			self.emit_op_arg(Op::GetProperty, name);
+			self.emit_byte(cache);
+		}
+		Ok(())
+	}
+
+	fn grouping(&mut self, _can_assign: bool) -> Result {
+		self.expression()?;
+		self.consume(TokenKind::RParen, "Expect ')' after expression.")?;
+		Ok(())
 	}
 
-	pub(super) fn named_variable(&mut self, name: Token, can_assign: bool) {
-		todo!()
+	fn literal(&mut self, _can_assign: bool) -> Result {
+		match self.parser.previous.kind {
+			TokenKind::False => self.emit_op(Op::False),
+			TokenKind::Nil => self.emit_op(Op::Nil),
+			TokenKind::True => self.emit_op(Op::True),
+			_ => unreachable!("not a literal"),
+		}
+		Ok(())
 	}
 
-	pub(super) fn variable(&mut self, can_assign: bool) {
-		todo!()
+	fn number(&mut self, _can_assign: bool) -> Result {
+		let number = self.parser.previous.text.parse().unwrap_or(f64::NAN);
+		let ConstId(constant) = self.make_constant(Value::Number(number));
+		self.emit_op_arg(Op::Constant, constant);
+		Ok(())
+	}
+
+	fn or(&mut self, _can_assign: bool) -> Result {
+		let else_jump = self.emit_jump(Op::JumpIfFalse);
+		let end_jump = self.emit_jump(Op::Jump);
+
+		self.patch_jump(else_jump);
+		self.emit_op(Op::Pop);
+
+		self.parse_precedence(Precedence::Or)?;
+		self.patch_jump(end_jump);
+		Ok(())
+	}
+
+	fn string(&mut self, _can_assign: bool) -> Result {
+		let text = &self.parser.previous.text;
+		let string = ObjString::new(self.gc, &text[1..text.len() - 1]);
+		let ConstId(constant) = self.make_constant(string.value());
+		self.emit_op_arg(Op::Constant, constant);
+		Ok(())
+	}
+
+	fn super_(&mut self, _can_assign: bool) -> Result {
+		let keyword = self.parser.previous.clone();
+		self.check_super(&keyword);
+		self.consume(TokenKind::Dot, "Expect '.' after 'super'.")?;
+		let method = self
+			.consume(TokenKind::Identifier, "Expect superclass method name.")?;
+		let ConstId(name) = self.identifier_constant(&method);
+
+		self.named_variable(&Token::synthetic("this"), false)?;
+		if self.check_eat(TokenKind::LParen).is_some() {
+			let arg_count = self.arguments()?;
+			self.named_variable(&Token::synthetic("super"), false)?;
+			self.emit_op_arg(Op::SuperInvoke, name);
+			self.emit_byte(arg_count);
+		} else {
+			self.named_variable(&Token::synthetic("super"), false)?;
+			self.emit_op_arg(Op::GetSuper, name);
+		}
+		Ok(())
+	}
+
+	fn this(&mut self, _can_assign: bool) -> Result {
+		if self.classes.is_empty() {
+			return self.error("Can't use 'this' outside of a class.");
+		}
+		self.variable(false)
+	}
+
+	fn unary(&mut self, _can_assign: bool) -> Result {
+		let op = self.parser.previous.kind;
+		self.parse_precedence(Precedence::Unary)?;
+
+		match op {
+			TokenKind::Bang => self.emit_op(Op::Not),
+			TokenKind::Minus => self.emit_op(Op::Negate),
+			_ => unreachable!("not a unary operator"),
+		}
+		Ok(())
 	}
 }
@@ -1,27 +1,127 @@
 use super::*;
 
-impl<'enclosing, 'source: 'enclosing> Compiler<'enclosing, 'source> {
-	pub(super) fn expression_statement(&mut self) -> Result<()> {
-		todo!()
+impl<'source> Compiler<'source> {
+	pub(super) fn expression_statement(&mut self) -> Result {
+		self.expression()?;
+		self.consume(TokenKind::Semicolon, "Expect ';' after expression.")?;
+		self.emit_op(Op::Pop);
+		Ok(())
 	}
 
-	pub(super) fn for_statement(&mut self) -> Result<()> {
-		todo!()
+	pub(super) fn for_statement(&mut self) -> Result {
+		self.begin_scope();
+		let res = self.for_clauses();
+		self.end_scope();
+		res
 	}
 
-	pub(super) fn if_statement(&mut self) -> Result<()> {
-		todo!()
+	pub(super) fn if_statement(&mut self) -> Result {
+		self.consume(TokenKind::LParen, "Expect '(' after 'if'.")?;
+		self.expression()?;
+		self.consume(TokenKind::RParen, "Expect ')' after condition.")?;
+
+		let then_jump = self.emit_jump(Op::JumpIfFalse);
+		self.emit_op(Op::Pop);
+		self.statement()?;
+
+		let else_jump = self.emit_jump(Op::Jump);
+		self.patch_jump(then_jump);
+		self.emit_op(Op::Pop);
+		if self.check_eat(TokenKind::Else).is_some() {
+			self.statement()?;
+		}
+		self.patch_jump(else_jump);
+		Ok(())
 	}
 
-	pub(super) fn print_statement(&mut self) -> Result<()> {
-		todo!()
+	pub(super) fn print_statement(&mut self) -> Result {
+		self.expression()?;
+		self.consume(TokenKind::Semicolon, "Expect ';' after value.")?;
+		self.emit_op(Op::Print);
+		Ok(())
 	}
 
-	pub(super) fn return_statement(&mut self) -> Result<()> {
-		todo!()
+	pub(super) fn return_statement(&mut self) -> Result {
+		let kind = self.current().kind;
+		if kind == FunctionKind::Script {
+			self.report("Can't return from top-level code.");
+		}
+
+		if self.check_eat(TokenKind::Semicolon).is_some() {
+			self.emit_return();
+			return Ok(());
+		}
+
+		if kind == FunctionKind::Initializer {
+			self.report("Can't return a value from an initializer.");
+		}
+		self.expression()?;
+		self.consume(TokenKind::Semicolon, "Expect ';' after return value.")?;
+		self.emit_op(Op::Return);
+		Ok(())
+	}
+
+	pub(super) fn while_statement(&mut self) -> Result {
+		let loop_start = self.chunk().bytecode.len();
+		self.consume(TokenKind::LParen, "Expect '(' after 'while'.")?;
+		self.expression()?;
+		self.consume(TokenKind::RParen, "Expect ')' after condition.")?;
+
+		let exit_jump = self.emit_jump(Op::JumpIfFalse);
+		self.emit_op(Op::Pop);
+		self.statement()?;
+		self.emit_loop(loop_start);
+
+		self.patch_jump(exit_jump);
+		self.emit_op(Op::Pop);
+		Ok(())
 	}
+}
+
+impl<'source> Compiler<'source> {
+	/// Compiles a `for` loop after its keyword, inside the scope that holds
+	/// its initializer's variable.
+	fn for_clauses(&mut self) -> Result {
+		self.consume(TokenKind::LParen, "Expect '(' after 'for'.")?;
+		if self.check_eat(TokenKind::Semicolon).is_some() {
+			// no initializer
+		} else if self.check_eat(TokenKind::Var).is_some() {
+			self.var_declaration()?;
+		} else {
+			self.expression_statement()?;
+		}
+
+		let mut loop_start = self.chunk().bytecode.len();
+		let mut exit_jump = None;
+		if self.check_eat(TokenKind::Semicolon).is_none() {
+			self.expression()?;
+			self.consume(
+				TokenKind::Semicolon,
+				"Expect ';' after loop condition.",
+			)?;
+
+			exit_jump = Some(self.emit_jump(Op::JumpIfFalse));
+			self.emit_op(Op::Pop);
+		}
+
+		if self.check_eat(TokenKind::RParen).is_none() {
+			let body_jump = self.emit_jump(Op::Jump);
+			let increment_start = self.chunk().bytecode.len();
+			self.expression()?;
+			self.emit_op(Op::Pop);
+			self.consume(TokenKind::RParen, "Expect ')' after for clauses.")?;
+
+			self.emit_loop(loop_start);
+			loop_start = increment_start;
+			self.patch_jump(body_jump);
+		}
 
-	pub(super) fn while_statement(&mut self) -> Result<()> {
-		todo!()
+		self.statement()?;
+		self.emit_loop(loop_start);
+		if let Some(exit_jump) = exit_jump {
+			self.patch_jump(exit_jump);
+			self.emit_op(Op::Pop);
+		}
+		Ok(())
 	}
 }
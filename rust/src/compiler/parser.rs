@@ -21,20 +21,21 @@ impl<'source> Parser<'source> {
 			scanner,
 			current,
 			previous: Token {
-				kind: TokenKind::Sof,
-				text: source[0..=0].into(),
-				line: 1,
+				kind:   TokenKind::Sof,
+				text:   source[..0].into(),
+				line:   1,
+				column: 0,
 			},
 		}
 	}
 
 	pub fn advance(&mut self, errors: &mut Vec<Error>) -> Token<'source> {
-		self.previous = self.current;
+		self.previous = self.current.clone();
 
 		loop {
 			self.current = self.scanner.scan_token();
 			if self.current.kind != TokenKind::Error {
-				return self.current;
+				return self.current.clone();
 			}
 
 			errors.push(self.error_at_current(self.current.text.as_ref()));
@@ -45,14 +46,6 @@ impl<'source> Parser<'source> {
 		self.current.kind == kind
 	}
 
-	pub fn check_eat(
-		&mut self,
-		kind: TokenKind,
-		errors: &mut Vec<Error>,
-	) -> Option<Token<'source>> {
-		self.check(kind).then(|| self.advance(errors))
-	}
-
 	pub fn consume(
 		&mut self,
 		kind: TokenKind,
@@ -68,25 +61,48 @@ impl<'source> Parser<'source> {
 	}
 
 	pub fn error(&self, msg: impl AsRef<str>) -> Error {
-		error_at(self.previous, msg)
+		error_at(self.previous.clone(), msg)
 	}
 
 	pub fn error_at_current(&self, msg: impl AsRef<str>) -> Error {
-		error_at(self.current, msg)
+		error_at(self.current.clone(), msg)
 	}
 }
 
+/// A compile error along with the position of the token it was reported at,
+/// so that tools can point at the offending source. Displays as
+/// `[line N] Error at X: msg`.
+#[derive(Debug, thiserror::Error)]
+#[error("{text}")]
+pub struct CompileError {
+	pub line:    u32,
+	pub column:  u32,
+	pub len:     u32,
+	pub message: String,
+	text:        String,
+}
+
 pub fn error_at(tok: Token, msg: impl AsRef<str>) -> Error {
 	let msg = msg.as_ref();
 	let mut error = String::with_capacity(22 + msg.len());
 
-	write!(error, "[line {}] Error", tok.line);
+	write!(error, "[line {}] Error", tok.line).unwrap();
 	match tok.kind {
 		TokenKind::Eof => write!(error, " at end").unwrap(),
 		TokenKind::Error => (),
 		_ => write!(error, " at {}", tok.text).unwrap(),
 	}
-	write!(error, ": {msg}");
-
-	eyre::Report::msg(error)
+	write!(error, ": {msg}").unwrap();
+
+	let len = match tok.kind {
+		TokenKind::Eof | TokenKind::Error => 0,
+		_ => tok.text.len() as u32,
+	};
+	eyre::Report::new(CompileError {
+		line: tok.line,
+		column: tok.column,
+		len,
+		message: msg.to_owned(),
+		text: error,
+	})
 }
@@ -2,22 +2,40 @@ pub use super::token::*;
 pub use super::token_kind::*;
 
 pub struct Scanner<'source> {
-	start:   &'source str,
-	current: &'source str,
-	line_no: u32,
+	start:         &'source str,
+	current:       &'source str,
+	line_no:       u32,
+	line_start:    &'source str,
+	column:        u32,
+	keep_comments: bool,
 }
 
 impl<'source> Scanner<'source> {
 	pub fn new(source: &'source str) -> Self {
 		Self {
-			start:   source,
-			current: source,
-			line_no: 1,
+			start:         source,
+			current:       source,
+			line_no:       1,
+			line_start:    source,
+			column:        1,
+			keep_comments: false,
+		}
+	}
+
+	/// Like `new`, but line comments are produced as `TokenKind::Comment`
+	/// tokens instead of being skipped.
+	#[cfg(feature = "ast")]
+	pub fn with_comments(source: &'source str) -> Self {
+		Self {
+			keep_comments: true,
+			..Self::new(source)
 		}
 	}
 
 	pub fn scan_token(&mut self) -> Token<'source> {
 		self.skip_whitespace();
+		self.start = self.current;
+		self.column = (self.line_start.len() - self.start.len()) as u32 + 1;
 
 		if self.is_at_end() {
 			return self.make_token(TokenKind::Eof);
@@ -36,6 +54,7 @@ impl<'source> Scanner<'source> {
 			'.' => self.make_token(TokenKind::Dot),
 			'-' => self.make_token(TokenKind::Minus),
 			'+' => self.make_token(TokenKind::Plus),
+			'/' if self.peek() == '/' => self.comment(),
 			'/' => self.make_token(TokenKind::Slash),
 			'*' => self.make_token(TokenKind::Star),
 
@@ -68,9 +87,10 @@ impl<'source> Scanner<'source> {
 impl<'source> Scanner<'source> {
 	fn error_token(&self, msg: &'static str) -> Token<'source> {
 		Token {
-			kind: TokenKind::Error,
-			text: msg.into(),
-			line: self.line_no,
+			kind:   TokenKind::Error,
+			text:   msg.into(),
+			line:   self.line_no,
+			column: self.column,
 		}
 	}
 
@@ -79,6 +99,7 @@ impl<'source> Scanner<'source> {
 			kind,
 			text: (&self.start[..self.start.len() - self.current.len()]).into(),
 			line: self.line_no,
+			column: self.column,
 		};
 		self.start = self.current;
 		res
@@ -89,7 +110,7 @@ impl<'source> Scanner<'source> {
 	fn advance(&mut self) -> char {
 		let res = self.peek();
 		if res != '\0' {
-			self.current = &self.current[1..];
+			self.current = &self.current[res.len_utf8()..];
 		}
 		res
 	}
@@ -100,14 +121,21 @@ impl<'source> Scanner<'source> {
 		kw: &str,
 		kind: TokenKind,
 	) -> TokenKind {
-		if offset + kw.len() == self.start.len() - self.current.len() {
-			if kw == &self.start[offset..offset + kw.len()] {
-				return kind;
-			}
+		if offset + kw.len() == self.start.len() - self.current.len()
+			&& kw == &self.start[offset..offset + kw.len()]
+		{
+			return kind;
 		}
 		TokenKind::Identifier
 	}
 
+	fn comment(&mut self) -> Token<'source> {
+		while self.peek() != '\n' && !self.is_at_end() {
+			self.advance();
+		}
+		self.make_token(TokenKind::Comment)
+	}
+
 	fn identifier_type(&self) -> TokenKind {
 		let mut chars = self
 			.start
@@ -121,7 +149,7 @@ impl<'source> Scanner<'source> {
 			Some('e') => self.check_keyword(1, "lse", TokenKind::Else),
 			Some('f') => match chars.next() {
 				Some('a') => self.check_keyword(2, "lse", TokenKind::False),
-				Some('o') => self.check_keyword(2, "or", TokenKind::For),
+				Some('o') => self.check_keyword(2, "r", TokenKind::For),
 				Some('u') => self.check_keyword(2, "n", TokenKind::Fun),
 				_ => TokenKind::Identifier,
 			},
@@ -150,7 +178,7 @@ impl<'source> Scanner<'source> {
 	}
 
 	fn is_at_end(&self) -> bool {
-		self.current.len() == 0
+		self.current.is_empty()
 	}
 
 	fn match_ch<F, T>(&mut self, expect: char, eval: F) -> Option<T>
@@ -180,7 +208,7 @@ impl<'source> Scanner<'source> {
 			}
 		}
 
-		return self.make_token(TokenKind::Number);
+		self.make_token(TokenKind::Number)
 	}
 
 	fn peek(&self) -> char {
@@ -188,7 +216,7 @@ impl<'source> Scanner<'source> {
 	}
 
 	fn peek_next(&self) -> char {
-		self.current.chars().skip(1).next().unwrap_or('\0')
+		self.current.chars().nth(1).unwrap_or('\0')
 	}
 
 	fn skip_whitespace(&mut self) {
@@ -198,9 +226,12 @@ impl<'source> Scanner<'source> {
 				'\n' => {
 					self.line_no += 1;
 					self.advance();
+					self.line_start = self.current;
 				},
 				'/' => {
-					if self.peek_next() == '/' {
+					if self.peek_next() == '/' && self.keep_comments {
+						return;
+					} else if self.peek_next() == '/' {
 						while self.peek() != '\n' && !self.is_at_end() {
 							self.advance();
 						}
@@ -220,7 +251,10 @@ impl<'source> Scanner<'source> {
 					self.advance();
 					return self.make_token(TokenKind::String);
 				},
-				'\n' => self.line_no += 1,
+				'\n' => {
+					self.line_no += 1;
+					self.line_start = &self.current[1..];
+				},
 				'\\' if self.peek_next() == '"' => {
 					self.advance();
 				},
@@ -237,5 +271,5 @@ fn is_alpha(ch: char) -> bool {
 }
 
 fn is_digit(ch: char) -> bool {
-	matches!(ch, '0'..='9')
+	ch.is_ascii_digit()
 }
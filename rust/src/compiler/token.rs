@@ -4,25 +4,30 @@ use super::token_kind::TokenKind;
 
 #[derive(Clone, Debug)]
 pub struct Token<'source> {
-	pub kind: TokenKind,
-	pub text: Cow<'source, str>,
-	pub line: u32,
+	pub kind:   TokenKind,
+	pub text:   Cow<'source, str>,
+	pub line:   u32,
+	/// 1-based byte offset of the token's first character within its line,
+	/// or 0 for tokens that don't come from the source.
+	pub column: u32,
 }
 
 impl<'source> Token<'source> {
 	pub fn synthetic(text: &'source str) -> Self {
 		Self {
-			text: text.into(),
-			kind: TokenKind::Error,
-			line: 0,
+			text:   text.into(),
+			kind:   TokenKind::Error,
+			line:   0,
+			column: 0,
 		}
 	}
 
 	pub fn to_static(&self) -> Token<'static> {
-		Self {
-			text: self.text.to_owned().into(),
-			kind: self.kind,
-			line: self.line,
+		Token {
+			text:   self.text.clone().into_owned().into(),
+			kind:   self.kind,
+			line:   self.line,
+			column: self.column,
 		}
 	}
 }
@@ -47,6 +47,7 @@ pub enum TokenKind {
 	While,
 
 	// rest
+	Comment,
 	Error,
 	Eof,
 	Sof,
@@ -0,0 +1,267 @@
+use std::collections::BTreeMap;
+use std::io::Write;
+
+use fnv::FnvHashMap;
+
+use crate::chunk::Chunk;
+use crate::chunk::Op;
+use crate::mem::GcRef;
+use crate::obj::ObjFunction;
+use crate::vm::Context;
+use crate::vm::Hook;
+
+/// Records which source lines a program runs. Attach one to a VM with
+/// `Vm::add_hook`, through an `Arc<Mutex<_>>` to read the report once the
+/// program has finished.
+///
+/// Only lines with an instruction that can run count as executable, so code
+/// after a `return` is left out of the report.
+#[derive(Default)]
+pub struct Coverage {
+	functions: Vec<FunctionCoverage>,
+	/// Ids by the address of their function.
+	ids:       FnvHashMap<usize, usize>,
+	/// How often each executable line was entered.
+	lines:     BTreeMap<u32, u64>,
+	/// The line last run in each active call frame.
+	current:   Vec<u32>,
+}
+
+struct FunctionCoverage {
+	/// `None` for the top-level script.
+	name:  Option<String>,
+	line:  u32,
+	calls: u64,
+}
+
+impl Coverage {
+	pub fn new() -> Self {
+		Self::default()
+	}
+
+	/// Writes the report as an lcov tracefile for the script at `path`.
+	pub fn write_lcov(
+		&self,
+		path: &str,
+		out: &mut impl Write,
+	) -> std::io::Result<()> {
+		writeln!(out, "TN:")?;
+		writeln!(out, "SF:{path}")?;
+
+		let mut functions = self
+			.functions
+			.iter()
+			.filter_map(|function| {
+				Some((function.line, function.name.as_ref()?))
+			})
+			.collect::<Vec<_>>();
+		functions.sort();
+		for (line, name) in &functions {
+			writeln!(out, "FN:{line},{name}")?;
+		}
+		let mut hit = 0;
+		for function in &self.functions {
+			if let Some(name) = &function.name {
+				writeln!(out, "FNDA:{},{name}", function.calls)?;
+				hit += (function.calls > 0) as usize;
+			}
+		}
+		writeln!(out, "FNF:{}", functions.len())?;
+		writeln!(out, "FNH:{hit}")?;
+
+		for (line, count) in &self.lines {
+			writeln!(out, "DA:{line},{count}")?;
+		}
+		writeln!(out, "LF:{}", self.lines.len())?;
+		let hit = self.lines.values().filter(|count| **count > 0).count();
+		writeln!(out, "LH:{hit}")?;
+		writeln!(out, "end_of_record")
+	}
+
+	/// Registers `function` and every function nested in it, marking their
+	/// reachable lines as executable.
+	fn add_function(&mut self, function: GcRef<ObjFunction>) -> usize {
+		if let Some(id) = self.ids.get(&function.as_ptr().addr()) {
+			return *id;
+		}
+
+		let reachable = reachable(&function.chunk);
+		let lines = function.chunk.lines.iter().zip(reachable);
+		for (line, _) in lines.filter(|(_, reachable)| *reachable) {
+			self.lines.entry(*line).or_default();
+		}
+
+		let id = self.functions.len();
+		self.ids.insert(function.as_ptr().addr(), id);
+		self.functions.push(FunctionCoverage {
+			name:  function.name.as_ref().map(|name| name.as_str().to_owned()),
+			line:  function.chunk.lines.first().copied().unwrap_or(0),
+			calls: 0,
+		});
+
+		let nested = function
+			.chunk
+			.constants
+			.iter()
+			.filter_map(|constant| constant.as_casted_obj::<ObjFunction>());
+		for nested in nested {
+			self.add_function(nested);
+		}
+		id
+	}
+}
+
+impl Hook for Coverage {
+	fn instruction(&mut self, cx: &Context, _op: Op) -> eyre::Result<()> {
+		let (Some(frame), Some(last)) = (cx.frame(), self.current.last_mut())
+		else {
+			return Ok(());
+		};
+
+		let line = frame.line();
+		if std::mem::replace(last, line) != line {
+			*self.lines.entry(line).or_default() += 1;
+		}
+		Ok(())
+	}
+
+	fn enter(&mut self, cx: &Context) {
+		if let Some(frame) = cx.frame() {
+			let id = self.add_function(frame.function());
+			self.functions[id].calls += 1;
+		}
+		// no line has run yet, and lines start at 1
+		self.current.push(0);
+	}
+
+	fn exit(&mut self, _cx: &Context, _function: GcRef<ObjFunction>) {
+		self.current.pop();
+	}
+}
+
+/// Which offsets of `chunk` start an instruction that can run, following
+/// jumps from the start of the chunk.
+fn reachable(chunk: &Chunk) -> Vec<bool> {
+	let mut reachable = vec![false; chunk.bytecode.len()];
+	let mut pending = vec![0];
+
+	while let Some(mut offset) = pending.pop() {
+		while offset < chunk.bytecode.len() && !reachable[offset] {
+			reachable[offset] = true;
+			let next = offset + chunk.instruction_len(offset);
+
+			let jump = || unsafe {
+				let high = chunk.bytecode[offset + 1].byte as usize;
+				let low = chunk.bytecode[offset + 2].byte as usize;
+				high << 8 | low
+			};
+			offset = match unsafe { chunk.bytecode[offset].op } {
+				Op::Return => break,
+				Op::Jump => next + jump(),
+				Op::JumpIfFalse => {
+					pending.push(next + jump());
+					next
+				},
+				Op::Loop => next - jump(),
+				_ => next,
+			};
+		}
+	}
+	reachable
+}
+
+#[cfg(test)]
+mod tests {
+	use std::sync::Arc;
+	use std::sync::Mutex;
+
+	use super::*;
+	use crate::compiler;
+	use crate::mem::GarbageCollector;
+	use crate::vm::Vm;
+
+	const MAX_FRAMES: usize = 64;
+	const STACK_SIZE: usize = MAX_FRAMES * (u8::MAX as usize + 1);
+
+	fn lcov(source: &str) -> String {
+		let coverage = Arc::new(Mutex::new(Coverage::new()));
+		let mut vm = Vm::<MAX_FRAMES, STACK_SIZE>::new();
+		vm.add_hook(coverage.clone());
+		vm.set_output(std::io::sink());
+		vm.interpret(source).unwrap();
+		drop(vm);
+
+		let mut out = Vec::new();
+		coverage.lock().unwrap().write_lcov("a.lox", &mut out).unwrap();
+		String::from_utf8(out).unwrap()
+	}
+
+	#[test]
+	fn code_after_return_is_unreachable() {
+		let gc = GarbageCollector::default();
+		let script =
+			compiler::compile(&gc, "fun f() {\nreturn 1;\nprint 2;\n}")
+				.unwrap();
+		let f = script
+			.chunk
+			.constants
+			.iter()
+			.find_map(|constant| constant.as_casted_obj::<ObjFunction>())
+			.unwrap();
+
+		let reachable = reachable(&f.chunk);
+		let lines = f.chunk.lines.iter().zip(&reachable);
+		let lines_where = |wanted| {
+			let lines =
+				lines.clone().filter(|(_, reachable)| **reachable == wanted);
+			lines.map(|(line, _)| *line).collect::<Vec<_>>()
+		};
+		assert!(lines_where(true).iter().all(|line| *line == 2));
+		assert!(lines_where(false).contains(&3));
+		// the implicit `return nil` at the end can't run either
+		assert!(lines_where(false).contains(&4));
+	}
+
+	#[test]
+	fn reports_lines_and_functions() {
+		let source = "fun f(n) {
+	if (n > 0) {
+		return 1;
+	}
+	return 2;
+	print 3;
+}
+fun g() {
+	print 4;
+}
+f(1);
+f(0);
+";
+		let lcov = lcov(source);
+		let lines = lcov.lines().collect::<Vec<_>>();
+
+		for expected in [
+			"SF:a.lox",
+			// a function starts at its first instruction
+			"FN:2,f",
+			"FN:9,g",
+			"FNDA:2,f",
+			"FNDA:0,g",
+			"FNF:2",
+			"FNH:1",
+			// entered by both calls
+			"DA:2,2",
+			// only by the first
+			"DA:3,1",
+			// only by the second, which skips to the end of the `if`
+			"DA:4,1",
+			"DA:5,1",
+			// g is never called
+			"DA:9,0",
+			"end_of_record",
+		] {
+			assert!(lines.contains(&expected), "{expected} in\n{lcov}");
+		}
+		assert!(!lcov.contains("DA:6,"), "{lcov}");
+	}
+}
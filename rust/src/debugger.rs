@@ -0,0 +1,429 @@
+mod cli;
+#[cfg(feature = "dap")]
+pub mod dap;
+mod eval;
+
+use std::collections::BTreeSet;
+
+use eyre::Result;
+use fnv::FnvHashMap;
+
+pub use self::cli::Cli;
+pub use self::eval::eval;
+use crate::chunk::Op;
+use crate::mem::GarbageCollector;
+use crate::mem::GcRef;
+use crate::obj::ObjFunction;
+use crate::obj::ObjString;
+use crate::value::Value;
+use crate::vm::Context;
+use crate::vm::Hook;
+
+/// Decides when a running program should pause, and hands control to a
+/// `Frontend` whenever it does. Attach one to a VM with `Vm::add_hook`.
+///
+/// Breakpoints are source lines. Execution pauses at the first instruction
+/// of a breakpoint's line, each time the line is entered from another line or
+/// from another call.
+pub struct Debugger {
+	frontend:    Box<dyn Frontend + Send>,
+	breakpoints: BTreeSet<u32>,
+	mode:        Mode,
+	reason:      Reason,
+	started:     bool,
+	/// The line last executed in each active call frame.
+	lines:       Vec<u32>,
+}
+
+/// Receives control while the program is paused.
+pub trait Frontend {
+	/// Called each time the program pauses. `breakpoints` may be changed
+	/// before returning how execution should continue.
+	fn paused(
+		&mut self,
+		reason: Reason,
+		breakpoints: &mut BTreeSet<u32>,
+		paused: &Paused,
+	) -> Result<Resume>;
+
+	/// Receives everything the program prints while the debugger is
+	/// attached.
+	fn output(&mut self, text: &str) {
+		print!("{text}");
+	}
+}
+
+/// Why the program paused.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Reason {
+	Breakpoint,
+	Entry,
+	Step,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Resume {
+	/// Run until the next breakpoint.
+	Continue,
+	/// Pause at the next line, entering calls.
+	StepIn,
+	/// Pause at the next line of the current call or its callers.
+	StepOver,
+	/// Pause once the current call returns.
+	StepOut,
+	/// Stop the program.
+	Quit,
+}
+
+#[derive(Clone, Copy)]
+enum Mode {
+	Continue,
+	StepIn,
+	StepOver(usize),
+	StepOut(usize),
+}
+
+/// The state of a paused program.
+pub struct Paused<'vm> {
+	/// The active calls, innermost first.
+	pub frames:  Vec<FrameState>,
+	pub globals: &'vm FnvHashMap<GcRef<ObjString>, Value>,
+	/// The VM's heap, for values made while inspecting the program.
+	pub gc:      &'vm GarbageCollector,
+}
+
+/// One active call of a paused program.
+pub struct FrameState {
+	pub function: GcRef<ObjFunction>,
+	pub line:     u32,
+	/// The locals in scope at the paused instruction, outermost first.
+	pub locals:   Vec<(GcRef<ObjString>, Value)>,
+	pub upvalues: Vec<(GcRef<ObjString>, Value)>,
+}
+
+impl Debugger {
+	/// A debugger that pauses before the program's first line.
+	pub fn new(frontend: impl Frontend + Send + 'static) -> Self {
+		Self {
+			frontend:    Box::new(frontend),
+			breakpoints: BTreeSet::new(),
+			mode:        Mode::StepIn,
+			reason:      Reason::Entry,
+			started:     false,
+			lines:       Vec::new(),
+		}
+	}
+
+	/// Whether to pause before the program's first line, or only once it
+	/// reaches a breakpoint.
+	pub fn stop_on_entry(mut self, stop: bool) -> Self {
+		self.mode = if stop { Mode::StepIn } else { Mode::Continue };
+		self
+	}
+
+	pub fn breakpoints(&mut self) -> &mut BTreeSet<u32> {
+		&mut self.breakpoints
+	}
+
+	/// Called before each instruction with the number of active call frames
+	/// and the line of the instruction. Returns whether the program should
+	/// pause there.
+	fn should_pause(&mut self, depth: usize, line: u32) -> bool {
+		self.lines.truncate(depth);
+		let new_line = match self.lines.get_mut(depth.wrapping_sub(1)) {
+			Some(last) => std::mem::replace(last, line) != line,
+			None => {
+				self.lines.resize(depth, line);
+				true
+			},
+		};
+
+		let stepped = match self.mode {
+			Mode::Continue => false,
+			Mode::StepIn => new_line,
+			Mode::StepOver(start) => {
+				depth < start || (depth == start && new_line)
+			},
+			Mode::StepOut(start) => depth < start,
+		};
+
+		self.reason = if new_line && self.breakpoints.contains(&line) {
+			Reason::Breakpoint
+		} else if stepped && !self.started {
+			Reason::Entry
+		} else if stepped {
+			Reason::Step
+		} else {
+			return false;
+		};
+		self.started = true;
+		true
+	}
+
+	/// Hands control to the frontend until it resumes the program.
+	fn pause(&mut self, paused: &Paused) -> Result<()> {
+		let depth = paused.frames.len();
+		let resume =
+			self.frontend
+				.paused(self.reason, &mut self.breakpoints, paused)?;
+		self.mode = match resume {
+			Resume::Continue => Mode::Continue,
+			Resume::StepIn => Mode::StepIn,
+			Resume::StepOver => Mode::StepOver(depth),
+			Resume::StepOut => Mode::StepOut(depth),
+			Resume::Quit => bail!("Program stopped by the debugger."),
+		};
+		Ok(())
+	}
+}
+
+impl Hook for Debugger {
+	fn instruction(&mut self, cx: &Context, _op: Op) -> Result<()> {
+		let Some(frame) = cx.frame() else {
+			return Ok(());
+		};
+
+		if self.should_pause(cx.depth(), frame.line()) {
+			self.pause(&Paused::new(cx))?;
+		}
+		Ok(())
+	}
+
+	fn output(&mut self, text: &str) -> bool {
+		self.frontend.output(text);
+		true
+	}
+}
+
+impl<'vm> Paused<'vm> {
+	fn new(cx: &Context<'vm>) -> Self {
+		let frames = cx
+			.frames()
+			.map(|frame| {
+				let function = frame.function();
+				let offset = frame.offset();
+
+				let locals = function
+					.locals
+					.iter()
+					.filter(|local| local.in_scope(offset))
+					.filter_map(|local| {
+						// a local the stack doesn't reach yet isn't shown
+						Some((local.name, frame.slot(local.slot)?))
+					})
+					.collect();
+
+				let upvalues = function
+					.upvalue_names
+					.iter()
+					.enumerate()
+					.map(|(index, name)| (*name, frame.upvalue(index)))
+					.collect();
+
+				FrameState {
+					function,
+					line: frame.line(),
+					locals,
+					upvalues,
+				}
+			})
+			.collect();
+
+		Self {
+			frames,
+			globals: cx.globals(),
+			gc: cx.gc(),
+		}
+	}
+}
+
+impl FrameState {
+	/// The name shown for this frame's function in backtraces.
+	pub fn name(&self) -> &str {
+		self.function
+			.name
+			.as_ref()
+			.map_or("script", |name| name.as_str())
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use std::collections::VecDeque;
+	use std::sync::Arc;
+	use std::sync::Mutex;
+
+	use super::*;
+	use crate::vm::Vm;
+
+	const MAX_FRAMES: usize = 64;
+	const STACK_SIZE: usize = MAX_FRAMES * (u8::MAX as usize + 1);
+
+	const PROGRAM: &str = "var a = 1;
+fun f(x) {
+	var y = x + a;
+	return y;
+}
+print f(2);
+print a;
+";
+
+	/// What the frontend saw at one pause.
+	#[derive(Debug, PartialEq)]
+	struct Pause {
+		reason: Reason,
+		line:   u32,
+		frames: Vec<String>,
+		locals: Vec<String>,
+	}
+
+	#[derive(Default)]
+	struct Log {
+		pauses: Vec<Pause>,
+		output: String,
+		evals:  Vec<String>,
+	}
+
+	/// Resumes with `resumes` in turn, evaluating `eval` in the innermost
+	/// frame at each pause.
+	struct Script {
+		resumes: VecDeque<Resume>,
+		eval:    Option<&'static str>,
+		log:     Arc<Mutex<Log>>,
+	}
+
+	impl Frontend for Script {
+		fn paused(
+			&mut self,
+			reason: Reason,
+			_breakpoints: &mut BTreeSet<u32>,
+			paused: &Paused,
+		) -> Result<Resume> {
+			let mut log = self.log.lock().unwrap();
+			let frame = &paused.frames[0];
+			log.pauses.push(Pause {
+				reason,
+				line: frame.line,
+				frames: paused.frames.iter().map(|f| f.name().into()).collect(),
+				locals: frame
+					.locals
+					.iter()
+					.map(|(name, value)| format!("{name}={value}"))
+					.collect(),
+			});
+			if let Some(source) = self.eval {
+				let value = eval(source, paused, 0);
+				log.evals.push(match value {
+					Ok(value) => value.to_string(),
+					Err(err) => err.to_string(),
+				});
+			}
+			Ok(self.resumes.pop_front().unwrap_or(Resume::Continue))
+		}
+
+		fn output(&mut self, text: &str) {
+			self.log.lock().unwrap().output.push_str(text);
+		}
+	}
+
+	fn debug(
+		debugger: impl FnOnce(Script) -> Debugger,
+		resumes: &[Resume],
+		eval: Option<&'static str>,
+	) -> (Result<()>, Log) {
+		let log = Arc::new(Mutex::new(Log::default()));
+		let script = Script {
+			resumes: resumes.iter().copied().collect(),
+			eval,
+			log: log.clone(),
+		};
+
+		let mut vm = Vm::<MAX_FRAMES, STACK_SIZE>::new();
+		vm.add_hook(debugger(script));
+		let res = vm.interpret(PROGRAM);
+		drop(vm);
+		let log = Arc::into_inner(log).unwrap().into_inner().unwrap();
+		(res, log)
+	}
+
+	fn lines(log: &Log) -> Vec<(Reason, u32)> {
+		log.pauses
+			.iter()
+			.map(|pause| (pause.reason, pause.line))
+			.collect()
+	}
+
+	#[test]
+	fn pauses_on_entry_and_sends_output_to_the_frontend() {
+		let (res, log) = debug(Debugger::new, &[Resume::Continue], None);
+		res.unwrap();
+		assert_eq!(lines(&log), [(Reason::Entry, 1)]);
+		assert_eq!(log.output, "3\n1\n");
+	}
+
+	#[test]
+	fn pauses_at_breakpoints_with_the_frame_state() {
+		let debugger = |script| {
+			let mut debugger = Debugger::new(script).stop_on_entry(false);
+			debugger.breakpoints().insert(4);
+			debugger
+		};
+		let (res, log) = debug(debugger, &[], Some("y"));
+		res.unwrap();
+
+		assert_eq!(log.pauses, [Pause {
+			reason: Reason::Breakpoint,
+			line:   4,
+			frames: vec!["f".into(), "script".into()],
+			locals: vec!["x=2".into(), "y=3".into()],
+		}]);
+		assert_eq!(log.evals, ["3"]);
+	}
+
+	#[test]
+	fn steps_in_over_and_out() {
+		let resumes = [
+			Resume::StepOver, // from 1
+			Resume::StepOver, // from 5, which defines f
+			Resume::StepIn,   // from 6, into f
+			Resume::StepOut,  // from 3, back to 6
+			Resume::StepOver, // from 6
+			Resume::Continue, // from 7
+		];
+		let (res, log) = debug(Debugger::new, &resumes, None);
+		res.unwrap();
+		assert_eq!(lines(&log), [
+			(Reason::Entry, 1),
+			(Reason::Step, 5),
+			(Reason::Step, 6),
+			(Reason::Step, 3),
+			(Reason::Step, 6),
+			(Reason::Step, 7),
+		]);
+	}
+
+	#[test]
+	fn quit_stops_the_program() {
+		let (res, log) = debug(Debugger::new, &[Resume::Quit], None);
+		let err = res.unwrap_err();
+		assert!(err.to_string().contains("stopped by the debugger"), "{err}");
+		assert_eq!(log.output, "");
+	}
+
+	#[test]
+	fn eval_only_inspects_variables_and_properties() {
+		let debugger = |script| {
+			let mut debugger = Debugger::new(script).stop_on_entry(false);
+			debugger.breakpoints().insert(4);
+			debugger
+		};
+		for source in ["f(1)", "a = 2", "x + y", "-x", "\"text\""] {
+			let (_, log) = debug(debugger, &[], Some(source));
+			assert_eq!(
+				log.evals,
+				["Only variables and their properties can be inspected."],
+				"{source}"
+			);
+		}
+	}
+}
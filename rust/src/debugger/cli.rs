@@ -0,0 +1,168 @@
+use std::collections::BTreeSet;
+use std::io::BufRead;
+use std::io::Write;
+
+use eyre::Result;
+
+use super::eval;
+use super::FrameState;
+use super::Frontend;
+use super::Paused;
+use super::Reason;
+use super::Resume;
+
+const HELP: &str = "\
+Commands:
+  break LINE      (b)  pause whenever LINE is reached
+  delete LINE     (d)  remove the breakpoint on LINE
+  continue        (c)  run until the next breakpoint
+  step            (s)  run to the next line, entering calls
+  next            (n)  run to the next line, stepping over calls
+  finish          (f)  run until the current call returns
+  backtrace       (bt) list the active calls
+  frame N              select call N of the backtrace
+  locals               print the locals of the selected call
+  upvalues             print the upvalues of the selected call
+  globals              print all globals
+  print NAME      (p)  print a variable, or a property like a.b
+  quit            (q)  stop the program";
+
+/// A line-based debugger frontend on stdin and stdout.
+pub struct Cli {
+	lines: Vec<String>,
+	frame: usize,
+}
+
+impl Cli {
+	pub fn new(source: &str) -> Self {
+		Self {
+			lines: source.lines().map(str::to_owned).collect(),
+			frame: 0,
+		}
+	}
+
+	fn show_line(&self, frame: &FrameState) {
+		let text = (frame.line as usize)
+			.checked_sub(1)
+			.and_then(|ii| self.lines.get(ii))
+			.map_or("", String::as_str);
+		println!("{} {:>4} | {}", frame.name(), frame.line, text.trim_end());
+	}
+
+	/// Runs one command. Returns how to resume the program, if the command
+	/// resumes it.
+	fn command(
+		&mut self,
+		line: &str,
+		breakpoints: &mut BTreeSet<u32>,
+		paused: &Paused,
+	) -> Result<Option<Resume>> {
+		let (command, arg) = line
+			.trim()
+			.split_once(char::is_whitespace)
+			.map_or((line.trim(), ""), |(command, arg)| (command, arg.trim()));
+		let frame = &paused.frames[self.frame];
+
+		match command {
+			"" => (),
+			"b" | "break" => {
+				let line = parse_line(arg)?;
+				breakpoints.insert(line);
+				println!("Breakpoint on line {line}.");
+			},
+			"d" | "delete" => {
+				let line = parse_line(arg)?;
+				if !breakpoints.remove(&line) {
+					bail!("No breakpoint on line {line}.");
+				}
+			},
+			"c" | "continue" => return Ok(Some(Resume::Continue)),
+			"s" | "step" => return Ok(Some(Resume::StepIn)),
+			"n" | "next" => return Ok(Some(Resume::StepOver)),
+			"f" | "finish" => return Ok(Some(Resume::StepOut)),
+			"q" | "quit" => return Ok(Some(Resume::Quit)),
+			"bt" | "backtrace" => {
+				for (ii, frame) in paused.frames.iter().enumerate() {
+					let marker = if ii == self.frame { '>' } else { ' ' };
+					println!(
+						"{marker} #{ii} {} [line {}]",
+						frame.name(),
+						frame.line
+					);
+				}
+			},
+			"frame" => {
+				let Some(index) = arg
+					.parse()
+					.ok()
+					.filter(|index| *index < paused.frames.len())
+				else {
+					bail!("Expect a frame number from the backtrace.");
+				};
+				self.frame = index;
+				self.show_line(&paused.frames[index]);
+			},
+			"locals" => {
+				for (name, value) in &frame.locals {
+					println!("{name} = {value}");
+				}
+			},
+			"upvalues" => {
+				for (name, value) in &frame.upvalues {
+					println!("{name} = {value}");
+				}
+			},
+			"globals" => {
+				let mut globals = paused.globals.iter().collect::<Vec<_>>();
+				globals.sort_by_key(|(name, _)| name.as_str());
+				for (name, value) in globals {
+					println!("{name} = {value}");
+				}
+			},
+			"p" | "print" => println!("{}", eval(arg, paused, self.frame)?),
+			"h" | "help" => println!("{HELP}"),
+			_ => bail!("Unknown command '{command}'. Try 'help'."),
+		}
+		Ok(None)
+	}
+}
+
+impl Frontend for Cli {
+	fn paused(
+		&mut self,
+		reason: Reason,
+		breakpoints: &mut BTreeSet<u32>,
+		paused: &Paused,
+	) -> Result<Resume> {
+		if reason == Reason::Breakpoint {
+			println!("Breakpoint on line {}.", paused.frames[0].line);
+		}
+
+		self.frame = 0;
+		self.show_line(&paused.frames[0]);
+
+		let mut stdin = std::io::stdin().lock();
+		loop {
+			print!("(rlox) ");
+			std::io::stdout().flush()?;
+
+			let mut line = String::new();
+			if stdin.read_line(&mut line)? == 0 {
+				return Ok(Resume::Quit);
+			}
+
+			match self.command(&line, breakpoints, paused) {
+				Ok(Some(resume)) => return Ok(resume),
+				Ok(None) => (),
+				Err(err) => eprintln!("{err}"),
+			}
+		}
+	}
+}
+
+fn parse_line(arg: &str) -> Result<u32> {
+	arg.parse()
+		.ok()
+		.filter(|line| *line > 0)
+		.ok_or_else(|| eyre!("Expect a line number."))
+}
@@ -0,0 +1,596 @@
+//! A Debug Adapter Protocol server on top of `Debugger`.
+//!
+//! Requests are only read while the program is paused or not yet running,
+//! so breakpoints set while it runs take effect at the next pause.
+
+use std::collections::BTreeSet;
+use std::io::BufRead;
+use std::io::Write;
+use std::sync::Arc;
+use std::sync::Mutex;
+
+use eyre::Result;
+use serde_json::json;
+use serde_json::Value as Json;
+
+use super::eval;
+use super::Debugger;
+use super::Frontend;
+use super::Paused;
+use super::Reason;
+use super::Resume;
+use crate::mem::HeapBound;
+use crate::obj::ObjInstance;
+use crate::value::Value;
+use crate::wire::read_message;
+use crate::wire::write_message;
+
+/// Lox programs only ever have one thread.
+const THREAD_ID: i64 = 1;
+
+/// A debug session with a client, from `initialize` to `disconnect`.
+pub struct Session {
+	connection:  Arc<Mutex<Connection>>,
+	breakpoints: BTreeSet<u32>,
+}
+
+/// What the client asked to debug.
+pub struct Launch {
+	pub program:       String,
+	pub source:        String,
+	pub stop_on_entry: bool,
+}
+
+struct Connection {
+	input:        Box<dyn BufRead + Send>,
+	output:       Box<dyn Write + Send>,
+	seq:          i64,
+	disconnected: bool,
+}
+
+impl Session {
+	pub fn new(
+		input: impl BufRead + Send + 'static,
+		output: impl Write + Send + 'static,
+	) -> Self {
+		let connection = Connection {
+			input:        Box::new(input),
+			output:       Box::new(output),
+			seq:          0,
+			disconnected: false,
+		};
+		Self {
+			connection:  Arc::new(Mutex::new(connection)),
+			breakpoints: BTreeSet::new(),
+		}
+	}
+
+	/// Answers requests until the client has launched a program and sent
+	/// `configurationDone`. Returns `None` if the client disconnects first.
+	pub fn launch(&mut self) -> Result<Option<Launch>> {
+		let mut connection = self.connection.lock().unwrap();
+		let mut launch = None;
+		let mut configured = false;
+
+		while launch.is_none() || !configured {
+			let Some(request) = connection.read()? else {
+				return Ok(None);
+			};
+			let args = &request["arguments"];
+
+			let body = match command(&request) {
+				"initialize" => {
+					connection.respond(
+						&request,
+						Ok(json!({ "supportsConfigurationDoneRequest": true })),
+					)?;
+					connection.event("initialized", json!({}))?;
+					continue;
+				},
+				"launch" => {
+					let program = args["program"].as_str().unwrap_or_default();
+					std::fs::read_to_string(program)
+						.map(|source| {
+							launch = Some(Launch {
+								program: program.to_owned(),
+								source,
+								stop_on_entry: args["stopOnEntry"]
+									.as_bool()
+									.unwrap_or(false),
+							});
+							Json::Null
+						})
+						.map_err(|err| {
+							eyre!("Could not read file \"{program}\": {err}")
+						})
+				},
+				"configurationDone" => {
+					configured = true;
+					Ok(Json::Null)
+				},
+				"disconnect" => {
+					connection.respond(&request, Ok(Json::Null))?;
+					return Ok(None);
+				},
+				command => common_request(command, args, &mut self.breakpoints),
+			};
+			connection.respond(&request, body)?;
+		}
+
+		Ok(launch)
+	}
+
+	/// A debugger that reports to this session's client.
+	pub fn debugger(&self, launch: &Launch) -> Debugger {
+		let frontend = Dap {
+			connection: self.connection.clone(),
+			program:    launch.program.clone(),
+			handles:    Vec::new(),
+		};
+
+		let mut debugger =
+			Debugger::new(frontend).stop_on_entry(launch.stop_on_entry);
+		debugger.breakpoints().extend(&self.breakpoints);
+		debugger
+	}
+
+	/// Reports how the program ended, then answers requests until the client
+	/// disconnects.
+	pub fn finish(self, res: Result<()>) -> Result<()> {
+		let mut connection = self.connection.lock().unwrap();
+		if connection.disconnected {
+			return Ok(());
+		}
+
+		if let Err(err) = &res {
+			let output =
+				json!({ "category": "stderr", "output": format!("{err}\n") });
+			connection.event("output", output)?;
+		}
+		let exit_code = if res.is_ok() { 0 } else { 70 };
+		connection.event("exited", json!({ "exitCode": exit_code }))?;
+		connection.event("terminated", json!({}))?;
+
+		while let Some(request) = connection.read()? {
+			if command(&request) == "disconnect" {
+				return connection.respond(&request, Ok(Json::Null));
+			}
+			connection
+				.respond(&request, Err(eyre!("The program has terminated.")))?;
+		}
+		Ok(())
+	}
+}
+
+impl Connection {
+	fn read(&mut self) -> Result<Option<Json>> {
+		read_message(&mut self.input)
+	}
+
+	fn send(&mut self, mut message: Json) -> Result<()> {
+		self.seq += 1;
+		message["seq"] = json!(self.seq);
+		write_message(&mut self.output, &message)
+	}
+
+	fn respond(&mut self, request: &Json, body: Result<Json>) -> Result<()> {
+		let mut response = json!({
+			"type": "response",
+			"request_seq": request["seq"],
+			"command": request["command"],
+			"success": body.is_ok(),
+		});
+		match body {
+			Ok(body) => response["body"] = body,
+			Err(err) => response["message"] = json!(err.to_string()),
+		}
+		self.send(response)
+	}
+
+	fn event(&mut self, event: &str, body: Json) -> Result<()> {
+		self.send(json!({ "type": "event", "event": event, "body": body }))
+	}
+}
+
+fn command(request: &Json) -> &str {
+	request["command"].as_str().unwrap_or_default()
+}
+
+/// Answers the requests that are valid whether or not the program is
+/// running.
+fn common_request(
+	command: &str,
+	args: &Json,
+	breakpoints: &mut BTreeSet<u32>,
+) -> Result<Json> {
+	match command {
+		"setBreakpoints" => {
+			let lines = args["breakpoints"]
+				.as_array()
+				.into_iter()
+				.flatten()
+				.filter_map(|breakpoint| breakpoint["line"].as_u64())
+				.map(|line| line as u32)
+				.collect::<Vec<_>>();
+
+			breakpoints.clear();
+			breakpoints.extend(&lines);
+			let verified = lines
+				.iter()
+				.map(|line| json!({ "verified": true, "line": line }))
+				.collect::<Vec<_>>();
+			Ok(json!({ "breakpoints": verified }))
+		},
+		"setExceptionBreakpoints" => Ok(json!({ "breakpoints": [] })),
+		"threads" => Ok(json!({
+			"threads": [{ "id": THREAD_ID, "name": "main" }],
+		})),
+		_ => Err(eyre!("Unsupported request '{command}'.")),
+	}
+}
+
+/// The `Frontend` of a DAP session.
+struct Dap {
+	connection: Arc<Mutex<Connection>>,
+	program:    String,
+	/// What each `variablesReference` handed out during the current pause
+	/// refers to, offset by one.
+	handles:    Vec<Variables>,
+}
+
+#[derive(Clone, Copy)]
+enum Variables {
+	Locals(usize),
+	Upvalues(usize),
+	Globals,
+	Fields(HeapBound<ObjInstance>),
+}
+
+impl Frontend for Dap {
+	fn paused(
+		&mut self,
+		reason: Reason,
+		breakpoints: &mut BTreeSet<u32>,
+		paused: &Paused,
+	) -> Result<Resume> {
+		let connection = self.connection.clone();
+		let mut connection = connection.lock().unwrap();
+		self.handles.clear();
+
+		let reason = match reason {
+			Reason::Breakpoint => "breakpoint",
+			Reason::Entry => "entry",
+			Reason::Step => "step",
+		};
+		connection.event(
+			"stopped",
+			json!({
+				"reason": reason,
+				"threadId": THREAD_ID,
+				"allThreadsStopped": true,
+			}),
+		)?;
+
+		loop {
+			let Some(request) = connection.read()? else {
+				connection.disconnected = true;
+				return Ok(Resume::Quit);
+			};
+			let args = &request["arguments"];
+
+			let resume = match command(&request) {
+				"continue" => Resume::Continue,
+				"next" => Resume::StepOver,
+				"stepIn" => Resume::StepIn,
+				"stepOut" => Resume::StepOut,
+				"disconnect" | "terminate" => {
+					connection.disconnected = true;
+					Resume::Quit
+				},
+				command => {
+					let body = self.request(command, args, breakpoints, paused);
+					connection.respond(&request, body)?;
+					continue;
+				},
+			};
+
+			let body = match resume {
+				Resume::Continue => json!({ "allThreadsContinued": true }),
+				_ => Json::Null,
+			};
+			connection.respond(&request, Ok(body))?;
+			return Ok(resume);
+		}
+	}
+
+	fn output(&mut self, text: &str) {
+		let output = json!({ "category": "stdout", "output": text });
+		// the program can't do anything about a client that went away
+		let _ = self.connection.lock().unwrap().event("output", output);
+	}
+}
+
+impl Dap {
+	fn request(
+		&mut self,
+		command: &str,
+		args: &Json,
+		breakpoints: &mut BTreeSet<u32>,
+		paused: &Paused,
+	) -> Result<Json> {
+		match command {
+			"stackTrace" => {
+				let frames = paused
+					.frames
+					.iter()
+					.enumerate()
+					.map(|(id, frame)| {
+						json!({
+							"id": id,
+							"name": frame.name(),
+							"line": frame.line,
+							"column": 1,
+							"source": { "path": self.program },
+						})
+					})
+					.collect::<Vec<_>>();
+				Ok(json!({
+					"stackFrames": frames,
+					"totalFrames": paused.frames.len(),
+				}))
+			},
+			"scopes" => {
+				let frame = args["frameId"].as_u64().unwrap_or(0) as usize;
+				if frame >= paused.frames.len() {
+					bail!("No frame {frame}.");
+				}
+
+				let scopes = [
+					("Locals", Variables::Locals(frame), false),
+					("Upvalues", Variables::Upvalues(frame), false),
+					("Globals", Variables::Globals, true),
+				]
+				.map(|(name, variables, expensive)| {
+					json!({
+						"name": name,
+						"variablesReference": self.handle(variables),
+						"expensive": expensive,
+					})
+				});
+				Ok(json!({ "scopes": scopes }))
+			},
+			"variables" => {
+				let reference =
+					args["variablesReference"].as_u64().unwrap_or(0);
+				let Some(variables) = (reference as usize)
+					.checked_sub(1)
+					.and_then(|index| self.handles.get(index))
+				else {
+					bail!("Unknown variablesReference {reference}.");
+				};
+
+				let mut variables = match *variables {
+					Variables::Locals(frame) => paused.frames[frame]
+						.locals
+						.iter()
+						.map(|(name, value)| (name.to_string(), *value))
+						.collect(),
+					Variables::Upvalues(frame) => paused.frames[frame]
+						.upvalues
+						.iter()
+						.map(|(name, value)| (name.to_string(), *value))
+						.collect(),
+					Variables::Globals => {
+						let mut globals = paused
+							.globals
+							.iter()
+							.map(|(name, value)| (name.to_string(), *value))
+							.collect::<Vec<_>>();
+						globals.sort_by(|(a, _), (b, _)| a.cmp(b));
+						globals
+					},
+					Variables::Fields(instance) => {
+						let mut fields = instance
+							.get()
+							.fields()
+							.map(|(name, value)| (name.to_string(), value))
+							.collect::<Vec<_>>();
+						fields.sort_by(|(a, _), (b, _)| a.cmp(b));
+						fields
+					},
+				};
+
+				let variables = variables
+					.drain(..)
+					.map(|(name, value)| {
+						json!({
+							"name": name,
+							"value": value.to_string(),
+							"variablesReference": self.value_handle(value),
+						})
+					})
+					.collect::<Vec<_>>();
+				Ok(json!({ "variables": variables }))
+			},
+			"evaluate" => {
+				let expression =
+					args["expression"].as_str().unwrap_or_default();
+				let frame = args["frameId"].as_u64().unwrap_or(0) as usize;
+				let value = eval(expression, paused, frame)?;
+				Ok(json!({
+					"result": value.to_string(),
+					"variablesReference": self.value_handle(value),
+				}))
+			},
+			_ => common_request(command, args, breakpoints),
+		}
+	}
+
+	fn handle(&mut self, variables: Variables) -> usize {
+		self.handles.push(variables);
+		self.handles.len()
+	}
+
+	/// A handle to the fields of `value` if it's an instance, or 0 if it has
+	/// no children.
+	fn value_handle(&mut self, value: Value) -> usize {
+		match value.as_casted_obj::<ObjInstance>() {
+			Some(instance) => {
+				// SAFETY: the frontend is part of the instance's VM
+				let instance = unsafe { HeapBound::new(instance) };
+				self.handle(Variables::Fields(instance))
+			},
+			None => 0,
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use std::io::Cursor;
+	use std::sync::atomic::AtomicUsize;
+	use std::sync::atomic::Ordering;
+
+	use super::*;
+	use crate::vm::OutputBuffer;
+	use crate::vm::Vm;
+
+	const MAX_FRAMES: usize = 64;
+	const STACK_SIZE: usize = MAX_FRAMES * (u8::MAX as usize + 1);
+
+	const PROGRAM: &str = "class P {}
+fun f(x) {
+	var p = P(); p.v = x;
+	print p.v;
+}
+f(7);
+";
+
+	/// Runs a session on `PROGRAM` that sends `requests` in order, and
+	/// returns everything the adapter sent back.
+	fn session(requests: &[(&str, Json)]) -> Vec<Json> {
+		// tests run in parallel, so each needs a file of its own
+		static SESSIONS: AtomicUsize = AtomicUsize::new(0);
+		let session = SESSIONS.fetch_add(1, Ordering::Relaxed);
+		let name = format!("rlox-dap-{}-{session}.lox", std::process::id());
+		let path = std::env::temp_dir().join(name);
+		std::fs::write(&path, PROGRAM).unwrap();
+
+		let mut input = Vec::new();
+		let launch = json!({ "program": path, "stopOnEntry": false });
+		let setup = [
+			("initialize", json!({})),
+			("launch", launch),
+			("setBreakpoints", json!({ "breakpoints": [{ "line": 4 }] })),
+			("configurationDone", json!({})),
+		];
+		let requests = setup.iter().chain(requests);
+		for (seq, (command, arguments)) in requests.enumerate() {
+			let request = json!({
+				"seq": seq + 1,
+				"type": "request",
+				"command": command,
+				"arguments": arguments,
+			});
+			write_message(&mut input, &request).unwrap();
+		}
+
+		let output = OutputBuffer::default();
+		let mut session = Session::new(Cursor::new(input), output.clone());
+		let launch = session.launch().unwrap().unwrap();
+		let mut vm = Vm::<MAX_FRAMES, STACK_SIZE>::new();
+		vm.add_hook(session.debugger(&launch));
+		let res = vm.interpret(&launch.source);
+		session.finish(res).unwrap();
+		std::fs::remove_file(&path).unwrap();
+
+		let output = output.take();
+		let mut output = output.as_bytes();
+		std::iter::from_fn(|| read_message(&mut output).unwrap()).collect()
+	}
+
+	fn response<'a>(messages: &'a [Json], command: &str) -> &'a Json {
+		let response = messages
+			.iter()
+			.find(|message| {
+				message["type"] == "response" && message["command"] == command
+			})
+			.unwrap();
+		assert_eq!(response["success"], true, "{response}");
+		&response["body"]
+	}
+
+	fn events<'a>(messages: &'a [Json], event: &str) -> Vec<&'a Json> {
+		messages
+			.iter()
+			.filter(|message| message["event"] == event)
+			.map(|message| &message["body"])
+			.collect()
+	}
+
+	fn names_and_values(variables: &Json) -> Vec<(&str, &str)> {
+		variables["variables"]
+			.as_array()
+			.unwrap()
+			.iter()
+			.map(|variable| {
+				let name = variable["name"].as_str().unwrap();
+				(name, variable["value"].as_str().unwrap())
+			})
+			.collect()
+	}
+
+	#[test]
+	fn stops_at_a_breakpoint_and_shows_the_frame() {
+		let messages = session(&[
+			("stackTrace", json!({ "threadId": THREAD_ID })),
+			("scopes", json!({ "frameId": 0 })),
+			("variables", json!({ "variablesReference": 1 })),
+			("continue", json!({ "threadId": THREAD_ID })),
+			("disconnect", json!({})),
+		]);
+
+		let stopped = events(&messages, "stopped");
+		assert_eq!(stopped.len(), 1);
+		assert_eq!(stopped[0]["reason"], "breakpoint");
+
+		let frames = &response(&messages, "stackTrace")["stackFrames"];
+		assert_eq!(frames[0]["name"], "f");
+		assert_eq!(frames[0]["line"], 4);
+		assert_eq!(frames[1]["name"], "script");
+
+		let scopes = &response(&messages, "scopes")["scopes"];
+		assert_eq!(scopes[0]["name"], "Locals");
+		assert_eq!(scopes[0]["variablesReference"], 1);
+		let locals = names_and_values(response(&messages, "variables"));
+		assert_eq!(locals, [("x", "7"), ("p", "P instance")]);
+
+		let output = events(&messages, "output");
+		assert_eq!(output[0]["output"], "7\n");
+		assert_eq!(events(&messages, "exited")[0]["exitCode"], 0);
+		assert_eq!(events(&messages, "terminated").len(), 1);
+	}
+
+	#[test]
+	fn evaluates_and_expands_instances() {
+		let messages = session(&[
+			("evaluate", json!({ "expression": "p", "frameId": 0 })),
+			("variables", json!({ "variablesReference": 1 })),
+			("continue", json!({ "threadId": THREAD_ID })),
+			("disconnect", json!({})),
+		]);
+
+		let evaluated = response(&messages, "evaluate");
+		assert_eq!(evaluated["result"], "P instance");
+		assert_eq!(evaluated["variablesReference"], 1);
+		let fields = names_and_values(response(&messages, "variables"));
+		assert_eq!(fields, [("v", "7")]);
+	}
+
+	#[test]
+	fn disconnecting_while_paused_stops_the_program() {
+		let messages = session(&[("disconnect", json!({}))]);
+		assert!(events(&messages, "output").is_empty());
+		assert!(events(&messages, "exited").is_empty());
+	}
+}
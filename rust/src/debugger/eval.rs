@@ -0,0 +1,77 @@
+use eyre::Result;
+
+use super::FrameState;
+use super::Paused;
+use crate::ast;
+use crate::ast::Expr;
+use crate::obj::ObjInstance;
+use crate::value::Value;
+
+/// Evaluates the expression `source` in `frame` of a paused program. Names
+/// resolve to the frame's locals, then its upvalues, then globals.
+///
+/// Only variables, `this` and their properties can be inspected, as in
+/// `point.x`, so that evaluating never runs Lox code, allocates or changes
+/// the program's state. Anything else is rejected.
+pub fn eval(source: &str, paused: &Paused, frame: usize) -> Result<Value> {
+	let expr = match ast::parse_expr(source) {
+		Ok(expr) => expr,
+		Err(errors) => {
+			let errors = errors.iter().map(ToString::to_string);
+			bail!("{}", errors.collect::<Vec<_>>().join("\n"));
+		},
+	};
+
+	let Some(frame) = paused.frames.get(frame) else {
+		bail!("No frame {frame}.");
+	};
+	Evaluator { paused, frame }.expr(&expr)
+}
+
+struct Evaluator<'paused, 'vm> {
+	paused: &'paused Paused<'vm>,
+	frame:  &'paused FrameState,
+}
+
+impl<'paused, 'vm> Evaluator<'paused, 'vm> {
+	fn expr(&self, expr: &Expr) -> Result<Value> {
+		match expr {
+			Expr::Get { object, name } => {
+				let Some(instance) =
+					self.expr(object)?.as_casted_obj::<ObjInstance>()
+				else {
+					bail!("Only instances have properties.");
+				};
+
+				let value = instance
+					.fields()
+					.find(|(field, _)| field.as_str() == name.text)
+					.map(|(_, value)| value);
+				value
+					.ok_or_else(|| eyre!("Undefined property '{}'.", name.text))
+			},
+			Expr::Grouping(inner) => self.expr(inner),
+			Expr::This(token) | Expr::Variable(token) => {
+				self.variable(&token.text)
+			},
+			_ => bail!("Only variables and their properties can be inspected."),
+		}
+	}
+
+	fn variable(&self, name: &str) -> Result<Value> {
+		let frame = self.frame;
+		let mut local = frame.locals.iter().rev().chain(frame.upvalues.iter());
+		if let Some((_, value)) =
+			local.find(|(local, _)| local.as_str() == name)
+		{
+			return Ok(*value);
+		}
+
+		self.paused
+			.globals
+			.iter()
+			.find(|(global, _)| global.as_str() == name)
+			.map(|(_, value)| *value)
+			.ok_or_else(|| eyre!("Undefined variable '{name}'."))
+	}
+}
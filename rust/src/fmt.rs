@@ -0,0 +1,571 @@
+use std::borrow::Cow;
+use std::collections::VecDeque;
+
+use crate::ast;
+use crate::ast::ClassDecl;
+use crate::ast::Expr;
+use crate::ast::FunctionDecl;
+use crate::ast::Stmt;
+use crate::compiler::scanner::Scanner;
+use crate::compiler::Token;
+use crate::compiler::TokenKind;
+
+const MAX_WIDTH: usize = 80;
+const TAB_WIDTH: usize = 4;
+
+/// Formats Lox source in the canonical style: tab indentation, one statement
+/// per line, K&R braces, single spaces around binary operators and calls
+/// wrapped one argument per line once they grow past `MAX_WIDTH` columns.
+/// Comments and single blank lines between statements are kept.
+///
+/// The code is printed from its syntax tree. Comments aren't part of it, so
+/// they're put back by line: one after code on its line stays at the end of
+/// that code's line, and others go before the next statement or closing
+/// brace.
+///
+/// Sources that don't parse are returned as errors instead of being
+/// formatted. `format(format(src)) == format(src)` for any valid `src`.
+pub fn format(source: &str) -> Result<String, ast::Errors> {
+	let program = ast::parse(source)?;
+
+	let mut printer = Printer {
+		comments: comments(source),
+		block_start: true,
+		..Printer::default()
+	};
+	printer.stmts(&program.stmts);
+	printer.comments_before(u32::MAX);
+	Ok(printer.out)
+}
+
+struct Comment<'source> {
+	text:     Cow<'source, str>,
+	line:     u32,
+	/// Whether code comes before it on its line.
+	trailing: bool,
+}
+
+/// Part of a logical line. Calls and parameter lists mark where they can be
+/// wrapped.
+enum Piece<'source> {
+	Text(Cow<'source, str>),
+	Open,
+	Separator,
+	Close,
+}
+
+#[derive(Default)]
+struct Printer<'source> {
+	out:         String,
+	indent:      usize,
+	comments:    VecDeque<Comment<'source>>,
+	/// The logical line being printed, and the last source line it covers.
+	line:        Vec<Piece<'source>>,
+	line_end:    u32,
+	/// The last source line of what's been written, to keep blank lines.
+	last_line:   u32,
+	/// Whether nothing has been written since the last `{`, or at all.
+	block_start: bool,
+}
+
+fn comments(source: &str) -> VecDeque<Comment<'_>> {
+	let mut scanner = Scanner::with_comments(source);
+	let mut comments = VecDeque::new();
+	let mut code_line = 0;
+	loop {
+		let token = scanner.scan_token();
+		match token.kind {
+			TokenKind::Eof => return comments,
+			TokenKind::Comment => comments.push_back(Comment {
+				trailing: token.line == code_line,
+				text:     token.text,
+				line:     token.line,
+			}),
+			_ => code_line = token.line,
+		}
+	}
+}
+
+impl<'source> Printer<'source> {
+	fn text(&mut self, text: impl Into<Cow<'source, str>>) {
+		self.line.push(Piece::Text(text.into()));
+	}
+
+	fn token(&mut self, token: &Token<'source>) {
+		self.line_end = self.line_end.max(token.line);
+		self.text(token.text.clone());
+	}
+
+	/// Writes out the logical line, along with the comments that trail the
+	/// source lines it covers.
+	fn flush(&mut self) {
+		self.flush_with_comments_through(self.line_end);
+	}
+
+	/// Like `flush`, but only takes the comments trailing source lines up to
+	/// `line`.
+	fn flush_with_comments_through(&mut self, line: u32) {
+		if self.line.is_empty() {
+			return;
+		}
+
+		let pieces = std::mem::take(&mut self.line);
+		let mut lines = wrap(&pieces, self.indent);
+
+		let covered = self
+			.comments
+			.iter()
+			.take_while(|comment| comment.line <= line)
+			.count();
+		let (trailing, own_line): (Vec<_>, Vec<_>) = self
+			.comments
+			.drain(..covered)
+			.partition(|comment| comment.trailing);
+		for comment in own_line.into_iter().rev() {
+			self.comments.push_front(comment);
+		}
+		let (_, last) = lines.last_mut().unwrap();
+		for comment in trailing {
+			last.push(' ');
+			last.push_str(&comment.text);
+		}
+
+		for (indent, text) in lines {
+			self.write_line(indent, &text);
+		}
+		self.last_line = self.last_line.max(self.line_end);
+		self.line_end = 0;
+	}
+
+	fn write_line(&mut self, indent: usize, text: &str) {
+		self.out.extend(std::iter::repeat_n('\t', indent));
+		self.out.push_str(text);
+		self.out.push('\n');
+		self.block_start = false;
+	}
+
+	/// Keeps a blank line before what starts at source line `line`, if
+	/// there's one before it in the source.
+	fn blank_line_before(&mut self, line: u32) {
+		if !self.block_start && line > self.last_line + 1 {
+			self.out.push('\n');
+		}
+	}
+
+	/// Writes the comments that start before source line `line` on their own
+	/// lines.
+	fn comments_before(&mut self, line: u32) {
+		while self.comments.front().is_some_and(|c| c.line < line) {
+			let comment = self.comments.pop_front().unwrap();
+			self.blank_line_before(comment.line);
+			self.write_line(self.indent, &comment.text);
+			self.last_line = self.last_line.max(comment.line);
+		}
+	}
+
+	/// Prints `{`, the body on indented lines, and `}`, leaving the closing
+	/// brace's line open for an `else`.
+	fn braced(&mut self, close: &Token<'source>, body: impl FnOnce(&mut Self)) {
+		self.text("{");
+		// a comment on the line the block closes on comes after its `}`
+		self.flush_with_comments_through(self.line_end.min(close.line - 1));
+		self.indent += 1;
+		self.block_start = true;
+		body(self);
+		self.comments_before(close.line);
+		self.indent -= 1;
+		self.block_start = false;
+		self.token(close);
+	}
+
+	fn stmts(&mut self, stmts: &[Stmt<'source>]) {
+		for stmt in stmts {
+			self.comments_before(stmt.line());
+			self.blank_line_before(stmt.line());
+			self.stmt(stmt);
+			self.flush();
+		}
+	}
+
+	/// Prints `stmt` on the current line. Statements nested in a block get
+	/// lines of their own.
+	fn stmt(&mut self, stmt: &Stmt<'source>) {
+		match stmt {
+			Stmt::Block { open, stmts, close } => {
+				self.line_end = self.line_end.max(open.line);
+				self.braced(close, |printer| printer.stmts(stmts));
+			},
+			Stmt::Class(decl) => self.class(decl),
+			Stmt::Expression(expr) => {
+				self.expr(expr);
+				self.text(";");
+			},
+			Stmt::For {
+				keyword,
+				initializer,
+				condition,
+				increment,
+				body,
+			} => {
+				self.token(keyword);
+				self.text(" (");
+				match initializer {
+					Some(initializer) => self.stmt(initializer),
+					None => self.text(";"),
+				}
+				if let Some(condition) = condition {
+					self.text(" ");
+					self.expr(condition);
+				}
+				self.text(";");
+				if let Some(increment) = increment {
+					self.text(" ");
+					self.expr(increment);
+				}
+				self.text(") ");
+				self.stmt(body);
+			},
+			Stmt::Function(decl) => {
+				self.text("fun ");
+				self.function(decl);
+			},
+			Stmt::If {
+				keyword,
+				condition,
+				then_branch,
+				else_branch,
+			} => {
+				self.token(keyword);
+				self.text(" (");
+				self.expr(condition);
+				self.text(") ");
+				self.stmt(then_branch);
+				if let Some(else_branch) = else_branch {
+					// comments before the `else` keep it off the `}`'s line
+					let else_line = else_branch.line();
+					let commented = self
+						.comments
+						.front()
+						.is_some_and(|c| c.line < else_line);
+					let block = matches!(**then_branch, Stmt::Block { .. });
+					if block && !commented {
+						self.text(" ");
+					} else {
+						self.flush();
+						self.comments_before(else_line);
+					}
+					self.text("else ");
+					self.stmt(else_branch);
+				}
+			},
+			Stmt::Print { keyword, value } => {
+				self.token(keyword);
+				self.text(" ");
+				self.expr(value);
+				self.text(";");
+			},
+			Stmt::Return { keyword, value } => {
+				self.token(keyword);
+				if let Some(value) = value {
+					self.text(" ");
+					self.expr(value);
+				}
+				self.text(";");
+			},
+			Stmt::Var { name, initializer } => {
+				self.text("var ");
+				self.token(name);
+				if let Some(initializer) = initializer {
+					self.text(" = ");
+					self.expr(initializer);
+				}
+				self.text(";");
+			},
+			Stmt::While {
+				keyword,
+				condition,
+				body,
+			} => {
+				self.token(keyword);
+				self.text(" (");
+				self.expr(condition);
+				self.text(") ");
+				self.stmt(body);
+			},
+		}
+	}
+
+	fn class(&mut self, decl: &ClassDecl<'source>) {
+		self.text("class ");
+		self.token(&decl.name);
+		if let Some(superclass) = &decl.superclass {
+			self.text(" < ");
+			self.token(superclass);
+		}
+		self.text(" ");
+		self.braced(&decl.close, |printer| {
+			for method in &decl.methods {
+				printer.comments_before(method.name.line);
+				printer.blank_line_before(method.name.line);
+				printer.function(method);
+				printer.flush();
+			}
+		});
+	}
+
+	fn function(&mut self, decl: &FunctionDecl<'source>) {
+		self.token(&decl.name);
+		self.list(&decl.params, |printer, param| printer.token(param));
+		self.text(" ");
+		self.braced(&decl.close, |printer| printer.stmts(&decl.body));
+	}
+
+	/// Prints `items` in parentheses, separated by commas.
+	fn list<T>(&mut self, items: &[T], mut item: impl FnMut(&mut Self, &T)) {
+		if items.is_empty() {
+			return self.text("()");
+		}
+
+		self.line.push(Piece::Open);
+		for (ii, value) in items.iter().enumerate() {
+			if ii > 0 {
+				self.line.push(Piece::Separator);
+			}
+			item(self, value);
+		}
+		self.line.push(Piece::Close);
+	}
+
+	fn expr(&mut self, expr: &Expr<'source>) {
+		match expr {
+			Expr::Assign { name, value } => {
+				self.token(name);
+				self.text(" = ");
+				self.expr(value);
+			},
+			Expr::Binary { left, op, right }
+			| Expr::Logical { left, op, right } => {
+				self.expr(left);
+				self.text(" ");
+				self.token(op);
+				self.text(" ");
+				self.expr(right);
+			},
+			Expr::Call {
+				callee,
+				paren,
+				args,
+			} => {
+				self.expr(callee);
+				self.list(args, Self::expr);
+				self.line_end = self.line_end.max(paren.line);
+			},
+			Expr::Get { object, name } => {
+				self.expr(object);
+				self.text(".");
+				self.token(name);
+			},
+			Expr::Grouping(inner) => {
+				self.text("(");
+				self.expr(inner);
+				self.text(")");
+			},
+			Expr::Literal(token)
+			| Expr::This(token)
+			| Expr::Variable(token) => self.token(token),
+			Expr::Set {
+				object,
+				name,
+				value,
+			} => {
+				self.expr(object);
+				self.text(".");
+				self.token(name);
+				self.text(" = ");
+				self.expr(value);
+			},
+			Expr::Super { keyword, method } => {
+				self.token(keyword);
+				self.text(".");
+				self.token(method);
+			},
+			Expr::Unary { op, right } => {
+				self.token(op);
+				self.expr(right);
+			},
+		}
+	}
+}
+
+fn render(pieces: &[Piece]) -> String {
+	let mut text = String::new();
+	for piece in pieces {
+		text.push_str(match piece {
+			Piece::Text(text) => text,
+			Piece::Open => "(",
+			Piece::Separator => ", ",
+			Piece::Close => ")",
+		});
+	}
+	text.truncate(text.trim_end().len());
+	text
+}
+
+fn width(indent: usize, text: &str) -> usize {
+	indent * TAB_WIDTH + text.chars().count()
+}
+
+/// Splits a logical line that doesn't fit into `MAX_WIDTH` at its first
+/// call or parameter list, placing each item on its own line.
+fn wrap(pieces: &[Piece], indent: usize) -> Vec<(usize, String)> {
+	let text = render(pieces);
+	if width(indent, &text) <= MAX_WIDTH {
+		return vec![(indent, text)];
+	}
+
+	let Some(open) = pieces.iter().position(|p| matches!(p, Piece::Open))
+	else {
+		return vec![(indent, text)];
+	};
+
+	let mut lines = vec![(indent, render(&pieces[..=open]))];
+	let mut depth = 0usize;
+	let mut start = open + 1;
+	for (ii, piece) in pieces.iter().enumerate().skip(open + 1) {
+		match piece {
+			Piece::Open => depth += 1,
+			Piece::Separator if depth == 0 => {
+				lines.extend(wrap(&pieces[start..=ii], indent + 1));
+				start = ii + 1;
+			},
+			Piece::Close if depth == 0 => {
+				lines.extend(wrap(&pieces[start..ii], indent + 1));
+				lines.extend(wrap(&pieces[ii..], indent));
+				break;
+			},
+			Piece::Close => depth -= 1,
+			_ => (),
+		}
+	}
+	lines
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn lines(lines: &[&str]) -> String {
+		lines.iter().map(|line| format!("{line}\n")).collect()
+	}
+
+	#[test]
+	fn keeps_comments() {
+		let source = lines(&[
+			"// greeting",
+			"var a=1; // one",
+			"",
+			"",
+			"{ // block",
+			"// inside",
+			"print a;}",
+			"// end",
+		]);
+		let formatted = format(&source).unwrap();
+		assert_eq!(
+			formatted,
+			lines(&[
+				"// greeting",
+				"var a = 1; // one",
+				"",
+				"{ // block",
+				"\t// inside",
+				"\tprint a;",
+				"}",
+				"// end",
+			])
+		);
+		assert_eq!(format(&formatted).unwrap(), formatted);
+	}
+
+	#[test]
+	fn keeps_comments_after_closing_braces() {
+		let source = lines(&[
+			"class A {",
+			"m() {} // empty",
+			"n() {}",
+			"}",
+			"if (a) {",
+			"print 1;",
+			"} // then",
+			"else {",
+			"print 2;",
+			"}",
+			"if (a) print 1;",
+			"// otherwise",
+			"else print 2;",
+		]);
+		let formatted = format(&source).unwrap();
+		assert_eq!(
+			formatted,
+			lines(&[
+				"class A {",
+				"\tm() {",
+				"\t} // empty",
+				"\tn() {",
+				"\t}",
+				"}",
+				"if (a) {",
+				"\tprint 1;",
+				"} // then",
+				"else {",
+				"\tprint 2;",
+				"}",
+				"if (a) print 1;",
+				"// otherwise",
+				"else print 2;",
+			])
+		);
+		assert_eq!(format(&formatted).unwrap(), formatted);
+	}
+
+	#[test]
+	fn wraps_long_calls() {
+		let source = lines(&[
+			"print some_long_function_name(first_argument, second_argument,",
+			"third_argument(nested));",
+		]);
+		let formatted = format(&source).unwrap();
+		assert_eq!(
+			formatted,
+			lines(&[
+				"print some_long_function_name(",
+				"\tfirst_argument,",
+				"\tsecond_argument,",
+				"\tthird_argument(nested)",
+				");",
+			])
+		);
+		assert_eq!(format(&formatted).unwrap(), formatted);
+	}
+
+	#[test]
+	fn idempotent_on_examples() {
+		// examples that don't parse: this one misses a semicolon
+		const INVALID: &[&str] = &["reassign_upvalue.lox"];
+
+		let dir = concat!(env!("CARGO_MANIFEST_DIR"), "/../lox_programs");
+		for entry in std::fs::read_dir(dir).unwrap() {
+			let path = entry.unwrap().path();
+			let source = std::fs::read_to_string(&path).unwrap();
+			let name = path.file_name().unwrap().to_str().unwrap();
+			if INVALID.contains(&name) {
+				assert!(format(&source).is_err(), "{name} parses");
+				continue;
+			}
+
+			let formatted = format(&source)
+				.unwrap_or_else(|_| panic!("{name} doesn't parse"));
+			assert_eq!(format(&formatted).unwrap(), formatted, "{name}");
+		}
+	}
+}
@@ -1,21 +1,38 @@
-#![feature(allocator_api)]
 #![feature(build_hasher_simple_hash_one)]
 #![feature(let_chains)]
 #![feature(maybe_uninit_uninit_array)]
 #![feature(maybe_uninit_slice)]
-#![feature(new_uninit)]
 #![feature(nonnull_slice_from_raw_parts)]
 #![feature(result_option_inspect)]
 #![feature(strict_provenance)]
 #![feature(string_leak)]
-#![feature(trait_alias)]
 
 #[macro_use]
 extern crate eyre;
 
+#[cfg(feature = "ast")]
+pub mod ast;
 pub mod chunk;
 pub mod compiler;
+#[cfg(feature = "coverage")]
+pub mod coverage;
+#[cfg(feature = "debugger")]
+pub mod debugger;
+#[cfg(feature = "ast")]
+pub mod fmt;
+#[cfg(feature = "ast")]
+pub mod lint;
+#[cfg(feature = "lsp")]
+pub mod lsp;
 pub mod mem;
 pub mod obj;
+#[cfg(feature = "profiler")]
+pub mod profiler;
+#[cfg(feature = "repl")]
+pub mod repl;
+#[cfg(feature = "trace")]
+pub mod trace;
 pub mod value;
 pub mod vm;
+#[cfg(any(feature = "dap", feature = "lsp"))]
+mod wire;
@@ -0,0 +1,590 @@
+use std::collections::HashSet;
+use std::fmt::Display;
+use std::str::FromStr;
+
+use crate::ast::ClassDecl;
+use crate::ast::Expr;
+use crate::ast::FunctionDecl;
+use crate::ast::Program;
+use crate::ast::Stmt;
+use crate::compiler::FunctionKind;
+use crate::compiler::Token;
+
+macro_rules! lint_codes {
+	($($code:ident => $name:literal,)*) => {
+		#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+		pub enum LintCode {
+			$($code,)*
+		}
+
+		impl LintCode {
+			pub const ALL: &'static [LintCode] = &[$(LintCode::$code,)*];
+
+			pub fn name(self) -> &'static str {
+				match self {
+					$(LintCode::$code => $name,)*
+				}
+			}
+		}
+
+		impl FromStr for LintCode {
+			type Err = eyre::Report;
+
+			fn from_str(name: &str) -> Result<Self, Self::Err> {
+				match name {
+					$($name => Ok(LintCode::$code),)*
+					_ => Err(eyre!("Unknown lint '{name}'.")),
+				}
+			}
+		}
+	};
+}
+
+lint_codes! {
+	ReturnAtTopLevel => "return-at-top-level",
+	SelfInitializer => "self-initializer",
+	ShadowedVariable => "shadowed-variable",
+	SuperWithoutSuperclass => "super-without-superclass",
+	ThisOutsideMethod => "this-outside-method",
+	UndeclaredGlobal => "undeclared-global",
+	UnreachableCode => "unreachable-code",
+	UnusedLocal => "unused-local",
+	UnusedParameter => "unused-parameter",
+}
+
+impl Display for LintCode {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		self.name().fmt(f)
+	}
+}
+
+#[derive(Clone, Debug)]
+pub struct Warning {
+	pub code:    LintCode,
+	pub line:    u32,
+	pub message: String,
+}
+
+impl Display for Warning {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		write!(
+			f,
+			"[line {}] Warning[{}]: {}",
+			self.line, self.code, self.message
+		)
+	}
+}
+
+/// Which lints are reported. Every lint is enabled by default.
+#[derive(Clone, Debug, Default)]
+pub struct LintConfig {
+	disabled: HashSet<LintCode>,
+}
+
+impl LintConfig {
+	pub fn allow(&mut self, code: LintCode) -> &mut Self {
+		self.disabled.insert(code);
+		self
+	}
+
+	pub fn deny(&mut self, code: LintCode) -> &mut Self {
+		self.disabled.remove(&code);
+		self
+	}
+
+	pub fn is_enabled(&self, code: LintCode) -> bool {
+		!self.disabled.contains(&code)
+	}
+}
+
+/// Resolves every name in `program` and reports suspicious code. Unlike
+/// compile errors, warnings never stop a program from running.
+pub fn lint(program: &Program, config: &LintConfig) -> Vec<Warning> {
+	let mut linter = Linter {
+		config,
+		globals: HashSet::new(),
+		functions: Vec::new(),
+		classes: Vec::new(),
+		warnings: Vec::new(),
+	};
+
+	for stmt in &program.stmts {
+		match stmt {
+			Stmt::Class(decl) => _ = linter.globals.insert(&*decl.name.text),
+			Stmt::Function(decl) => _ = linter.globals.insert(&*decl.name.text),
+			Stmt::Var { name, .. } => _ = linter.globals.insert(&*name.text),
+			_ => (),
+		}
+	}
+
+	linter.functions.push(FunctionScope {
+		kind:   FunctionKind::Script,
+		scopes: Vec::new(),
+	});
+	linter.stmts(&program.stmts);
+	linter.warnings.sort_by_key(|warning| warning.line);
+	linter.warnings
+}
+
+struct Linter<'ast, 'config> {
+	config:    &'config LintConfig,
+	globals:   HashSet<&'ast str>,
+	functions: Vec<FunctionScope<'ast>>,
+	classes:   Vec<bool>,
+	warnings:  Vec<Warning>,
+}
+
+struct FunctionScope<'ast> {
+	kind:   FunctionKind,
+	scopes: Vec<Vec<Binding<'ast>>>,
+}
+
+struct Binding<'ast> {
+	name:    &'ast Token<'ast>,
+	kind:    BindingKind,
+	defined: bool,
+	used:    bool,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum BindingKind {
+	Local,
+	Parameter,
+}
+
+impl<'ast, 'config> Linter<'ast, 'config> {
+	fn warn(&mut self, code: LintCode, line: u32, message: String) {
+		if self.config.is_enabled(code) {
+			self.warnings.push(Warning {
+				code,
+				line,
+				message,
+			});
+		}
+	}
+
+	fn scopes(&mut self) -> &mut Vec<Vec<Binding<'ast>>> {
+		&mut self.functions.last_mut().unwrap().scopes
+	}
+
+	fn begin_scope(&mut self) {
+		self.scopes().push(Vec::new());
+	}
+
+	fn end_scope(&mut self) {
+		let scope = self.scopes().pop().unwrap();
+		for binding in scope {
+			if binding.used || binding.name.text.starts_with('_') {
+				continue;
+			}
+
+			let (code, what) = match binding.kind {
+				BindingKind::Local => (LintCode::UnusedLocal, "Local variable"),
+				BindingKind::Parameter => {
+					(LintCode::UnusedParameter, "Parameter")
+				},
+			};
+			self.warn(
+				code,
+				binding.name.line,
+				format!("{what} '{}' is never read.", binding.name.text),
+			);
+		}
+	}
+
+	fn declare(&mut self, name: &'ast Token<'ast>, kind: BindingKind) {
+		if self.scopes().is_empty() {
+			return;
+		}
+
+		let shadows = self
+			.functions
+			.iter()
+			.flat_map(|function| function.scopes.iter())
+			.flatten()
+			.any(|binding| binding.name.text == name.text);
+		if shadows {
+			self.warn(
+				LintCode::ShadowedVariable,
+				name.line,
+				format!("'{}' shadows a variable of the same name.", name.text),
+			);
+		}
+
+		self.scopes().last_mut().unwrap().push(Binding {
+			name,
+			kind,
+			defined: false,
+			used: false,
+		});
+	}
+
+	fn define(&mut self) {
+		if let Some(binding) =
+			self.scopes().last_mut().and_then(|scope| scope.last_mut())
+		{
+			binding.defined = true;
+		}
+	}
+
+	fn read(&mut self, name: &Token) {
+		let binding = self
+			.functions
+			.iter_mut()
+			.rev()
+			.flat_map(|function| function.scopes.iter_mut().rev())
+			.find_map(|scope| {
+				scope
+					.iter_mut()
+					.rev()
+					.find(|binding| binding.name.text == name.text)
+			});
+
+		let Some(binding) = binding else {
+			return;
+		};
+		binding.used = true;
+
+		if !binding.defined {
+			self.warn(
+				LintCode::SelfInitializer,
+				name.line,
+				format!("'{}' is read in its own initializer.", name.text),
+			);
+		}
+	}
+
+	fn assign(&mut self, name: &Token) {
+		let is_local = self
+			.functions
+			.iter()
+			.flat_map(|function| function.scopes.iter())
+			.flatten()
+			.any(|binding| binding.name.text == name.text);
+
+		if !is_local && !self.globals.contains(name.text.as_ref()) {
+			self.warn(
+				LintCode::UndeclaredGlobal,
+				name.line,
+				format!(
+					"Assignment to undeclared global variable '{}'.",
+					name.text
+				),
+			);
+		}
+	}
+}
+
+impl<'ast, 'config> Linter<'ast, 'config> {
+	fn stmts(&mut self, stmts: &'ast [Stmt<'ast>]) {
+		let mut reported = false;
+		let mut terminated = false;
+		for stmt in stmts {
+			if terminated && !reported {
+				self.warn(
+					LintCode::UnreachableCode,
+					stmt.line(),
+					"Unreachable code.".to_owned(),
+				);
+				reported = true;
+			}
+
+			self.stmt(stmt);
+			terminated |= always_returns(stmt);
+		}
+	}
+
+	fn stmt(&mut self, stmt: &'ast Stmt<'ast>) {
+		match stmt {
+			Stmt::Block { stmts, .. } => {
+				self.begin_scope();
+				self.stmts(stmts);
+				self.end_scope();
+			},
+			Stmt::Class(decl) => self.class(decl),
+			Stmt::Expression(expr) => self.expr(expr),
+			Stmt::For {
+				initializer,
+				condition,
+				increment,
+				body,
+				..
+			} => {
+				self.begin_scope();
+				if let Some(initializer) = initializer {
+					self.stmt(initializer);
+				}
+				condition.iter().for_each(|condition| self.expr(condition));
+				increment.iter().for_each(|increment| self.expr(increment));
+				self.stmt(body);
+				self.end_scope();
+			},
+			Stmt::Function(decl) => {
+				self.declare(&decl.name, BindingKind::Local);
+				self.define();
+				self.function(decl, FunctionKind::Function);
+			},
+			Stmt::If {
+				condition,
+				then_branch,
+				else_branch,
+				..
+			} => {
+				self.expr(condition);
+				self.stmt(then_branch);
+				if let Some(else_branch) = else_branch {
+					self.stmt(else_branch);
+				}
+			},
+			Stmt::Print { value, .. } => self.expr(value),
+			Stmt::Return { keyword, value } => {
+				if self.functions.last().unwrap().kind == FunctionKind::Script {
+					self.warn(
+						LintCode::ReturnAtTopLevel,
+						keyword.line,
+						"Can't return from top-level code.".to_owned(),
+					);
+				}
+				value.iter().for_each(|value| self.expr(value));
+			},
+			Stmt::Var { name, initializer } => {
+				self.declare(name, BindingKind::Local);
+				initializer.iter().for_each(|value| self.expr(value));
+				self.define();
+			},
+			Stmt::While {
+				condition, body, ..
+			} => {
+				self.expr(condition);
+				self.stmt(body);
+			},
+		}
+	}
+
+	fn class(&mut self, decl: &'ast ClassDecl<'ast>) {
+		self.declare(&decl.name, BindingKind::Local);
+		self.define();
+
+		if let Some(superclass) = &decl.superclass {
+			self.read(superclass);
+		}
+
+		self.classes.push(decl.superclass.is_some());
+		for method in &decl.methods {
+			let kind = if method.name.text == "init" {
+				FunctionKind::Initializer
+			} else {
+				FunctionKind::Method
+			};
+			self.function(method, kind);
+		}
+		self.classes.pop();
+	}
+
+	fn function(&mut self, decl: &'ast FunctionDecl<'ast>, kind: FunctionKind) {
+		self.functions.push(FunctionScope {
+			kind,
+			scopes: vec![Vec::new()],
+		});
+
+		for param in &decl.params {
+			self.declare(param, BindingKind::Parameter);
+			self.define();
+		}
+		self.stmts(&decl.body);
+
+		self.end_scope();
+		self.functions.pop();
+	}
+
+	fn expr(&mut self, expr: &'ast Expr<'ast>) {
+		match expr {
+			Expr::Assign { name, value } => {
+				self.expr(value);
+				self.assign(name);
+			},
+			Expr::Binary { left, right, .. }
+			| Expr::Logical { left, right, .. } => {
+				self.expr(left);
+				self.expr(right);
+			},
+			Expr::Call { callee, args, .. } => {
+				self.expr(callee);
+				args.iter().for_each(|arg| self.expr(arg));
+			},
+			Expr::Get { object, .. } => self.expr(object),
+			Expr::Grouping(inner) => self.expr(inner),
+			Expr::Literal(_) => (),
+			Expr::Set { object, value, .. } => {
+				self.expr(value);
+				self.expr(object);
+			},
+			Expr::Super { keyword, .. } => match self.classes.last() {
+				Some(true) => (),
+				Some(false) => self.warn(
+					LintCode::SuperWithoutSuperclass,
+					keyword.line,
+					"Can't use 'super' in a class with no superclass."
+						.to_owned(),
+				),
+				None => self.warn(
+					LintCode::SuperWithoutSuperclass,
+					keyword.line,
+					"Can't use 'super' outside of a class.".to_owned(),
+				),
+			},
+			Expr::This(keyword) => {
+				if self.classes.is_empty() {
+					self.warn(
+						LintCode::ThisOutsideMethod,
+						keyword.line,
+						"Can't use 'this' outside of a class.".to_owned(),
+					);
+				}
+			},
+			Expr::Unary { right, .. } => self.expr(right),
+			Expr::Variable(name) => self.read(name),
+		}
+	}
+}
+
+fn always_returns(stmt: &Stmt) -> bool {
+	match stmt {
+		Stmt::Return { .. } => true,
+		Stmt::Block { stmts, .. } => stmts.iter().any(always_returns),
+		Stmt::If {
+			then_branch,
+			else_branch: Some(else_branch),
+			..
+		} => always_returns(then_branch) && always_returns(else_branch),
+		_ => false,
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::ast;
+
+	fn codes(source: &str, config: &LintConfig) -> Vec<LintCode> {
+		let program = ast::parse(source).unwrap();
+		lint(&program, config)
+			.iter()
+			.map(|warning| warning.code)
+			.collect()
+	}
+
+	/// Checks that `bad` gets exactly the warning `code` and `good`, its
+	/// fixed version, gets none.
+	fn check(code: LintCode, bad: &str, good: &str) {
+		let config = LintConfig::default();
+		assert_eq!(codes(bad, &config), [code], "{bad}");
+		assert_eq!(codes(good, &config), [], "{good}");
+	}
+
+	#[test]
+	fn return_at_top_level() {
+		check(
+			LintCode::ReturnAtTopLevel,
+			"return;",
+			"fun f() { return; } f();",
+		);
+	}
+
+	#[test]
+	fn self_initializer() {
+		check(
+			LintCode::SelfInitializer,
+			"var a = 1; { var a = a; print a; }",
+			"var a = 1; { var b = a; print b; }",
+		);
+	}
+
+	#[test]
+	fn shadowed_variable() {
+		check(
+			LintCode::ShadowedVariable,
+			"{ var a = 1; { var a = 2; print a; } print a; }",
+			"{ var a = 1; { var b = 2; print b; } print a; }",
+		);
+	}
+
+	#[test]
+	fn super_without_superclass() {
+		check(
+			LintCode::SuperWithoutSuperclass,
+			"class A { f() { super.f(); } }",
+			"class B {} class A < B { f() { super.f(); } }",
+		);
+	}
+
+	#[test]
+	fn this_outside_method() {
+		check(
+			LintCode::ThisOutsideMethod,
+			"print this;",
+			"class A { f() { print this; } }",
+		);
+	}
+
+	#[test]
+	fn undeclared_global() {
+		check(LintCode::UndeclaredGlobal, "a = 1;", "var a; a = 1;");
+	}
+
+	#[test]
+	fn unreachable_code() {
+		check(
+			LintCode::UnreachableCode,
+			"fun f() { return 1; print 2; } f();",
+			"fun f() { print 2; return 1; } f();",
+		);
+	}
+
+	#[test]
+	fn unused_local() {
+		check(
+			LintCode::UnusedLocal,
+			"{ var a = 1; }",
+			"{ var a = 1; print a; var _b = 2; }",
+		);
+	}
+
+	#[test]
+	fn unused_parameter() {
+		check(
+			LintCode::UnusedParameter,
+			"fun f(a) {} f(1);",
+			"fun f(a, _b) { print a; } f(1, 2);",
+		);
+	}
+
+	#[test]
+	fn allow_disables_a_lint_and_deny_enables_it_again() {
+		let source = "{ var a = 1; } a = 2;";
+		let mut config = LintConfig::default();
+		assert_eq!(codes(source, &config), [
+			LintCode::UnusedLocal,
+			LintCode::UndeclaredGlobal
+		]);
+
+		config.allow(LintCode::UnusedLocal);
+		assert!(!config.is_enabled(LintCode::UnusedLocal));
+		assert_eq!(codes(source, &config), [LintCode::UndeclaredGlobal]);
+
+		config.deny(LintCode::UnusedLocal);
+		assert!(config.is_enabled(LintCode::UnusedLocal));
+		assert_eq!(codes(source, &config), [
+			LintCode::UnusedLocal,
+			LintCode::UndeclaredGlobal
+		]);
+	}
+
+	#[test]
+	fn codes_round_trip_through_their_names() {
+		for &code in LintCode::ALL {
+			assert_eq!(code.name().parse::<LintCode>().unwrap(), code);
+		}
+		assert!("no-such-lint".parse::<LintCode>().is_err());
+	}
+}
@@ -0,0 +1,545 @@
+mod index;
+
+use std::collections::HashMap;
+use std::io::BufRead;
+use std::io::Write;
+
+use eyre::Result;
+use serde_json::json;
+use serde_json::Value as Json;
+
+use self::index::Index;
+use self::index::Outline;
+use self::index::Span;
+use self::index::SymbolKind;
+use crate::ast;
+use crate::compiler::CompileError;
+use crate::lint;
+use crate::mem::GarbageCollector;
+use crate::wire::read_message;
+use crate::wire::write_message;
+
+const METHOD_NOT_FOUND: i64 = -32601;
+const INVALID_PARAMS: i64 = -32602;
+
+/// Serves the Language Server Protocol over `input` and `output` until the
+/// client sends `exit`. Documents are re-parsed on every change, and
+/// diagnostics are published for both compile errors and lint warnings.
+///
+/// Columns count UTF-8 bytes if the client offers that encoding in
+/// `initialize`, and LSP's default UTF-16 code units otherwise.
+pub fn serve(mut input: impl BufRead, mut output: impl Write) -> Result<()> {
+	let mut server = Server::default();
+
+	while let Some(message) = read_message(&mut input)? {
+		let method = message["method"].as_str().unwrap_or_default();
+		let params = &message["params"];
+
+		let Some(id) = message.get("id") else {
+			if method == "exit" {
+				break;
+			}
+
+			for notification in server.notify(method, params) {
+				write_message(&mut output, &notification)?;
+			}
+			continue;
+		};
+
+		let response = match server.request(method, params) {
+			Ok(result) => {
+				json!({ "jsonrpc": "2.0", "id": id, "result": result })
+			},
+			Err((code, message)) => json!({
+				"jsonrpc": "2.0",
+				"id": id,
+				"error": { "code": code, "message": message },
+			}),
+		};
+		write_message(&mut output, &response)?;
+	}
+
+	Ok(())
+}
+
+#[derive(Default)]
+struct Server {
+	/// The last version of each open document that parsed.
+	documents: HashMap<String, Document>,
+	encoding:  Encoding,
+}
+
+/// A document's text, and the index built from it.
+#[derive(Default)]
+struct Document {
+	text:  String,
+	index: Index,
+}
+
+/// How the client counts the columns of positions.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+enum Encoding {
+	/// Bytes, as spans count them.
+	Utf8,
+	/// Code units, what LSP assumes unless both sides agree on another.
+	#[default]
+	Utf16,
+}
+
+type Response = std::result::Result<Json, (i64, String)>;
+
+impl Server {
+	fn request(&mut self, method: &str, params: &Json) -> Response {
+		match method {
+			"initialize" => Ok(self.initialize(params)),
+			"shutdown" => Ok(Json::Null),
+			"textDocument/definition" => self.definition(params),
+			"textDocument/references" => self.references(params),
+			"textDocument/hover" => self.hover(params),
+			"textDocument/documentSymbol" => self.document_symbol(params),
+			_ => Err((METHOD_NOT_FOUND, format!("Unhandled method {method}."))),
+		}
+	}
+
+	fn notify(&mut self, method: &str, params: &Json) -> Vec<Json> {
+		let uri = params["textDocument"]["uri"].as_str().unwrap_or_default();
+		let text = match method {
+			"textDocument/didOpen" => &params["textDocument"]["text"],
+			// we only advertise full syncs, so the last change is the
+			// whole document
+			"textDocument/didChange" => &params["contentChanges"][0]["text"],
+			"textDocument/didClose" => {
+				self.documents.remove(uri);
+				return vec![publish_diagnostics(uri, Vec::new())];
+			},
+			_ => return Vec::new(),
+		};
+		let Some(text) = text.as_str() else {
+			return Vec::new();
+		};
+
+		let encoding = self.encoding;
+		let diagnostic =
+			|error: &eyre::Report| error_diagnostic(error, text, encoding);
+		let diagnostics = match ast::parse(text) {
+			Ok(program) => {
+				let document = Document {
+					text:  text.to_owned(),
+					index: Index::new(&program),
+				};
+				self.documents.insert(uri.to_owned(), document);
+				// lowering reports what parsing can't, such as duplicate
+				// locals and returning a value from an initializer
+				let gc = GarbageCollector::default();
+				let errors = ast::lower(&gc, &program).err();
+				let errors = errors.unwrap_or_default();
+				let warnings =
+					lint::lint(&program, &lint::LintConfig::default());
+				errors
+					.iter()
+					.map(diagnostic)
+					.chain(warnings.iter().map(warning_diagnostic))
+					.collect()
+			},
+			Err(errors) => {
+				self.documents.entry(uri.to_owned()).or_default();
+				errors.iter().map(diagnostic).collect()
+			},
+		};
+		vec![publish_diagnostics(uri, diagnostics)]
+	}
+}
+
+impl Server {
+	/// Answers `initialize`, settling on UTF-8 columns if the client
+	/// supports them.
+	fn initialize(&mut self, params: &Json) -> Json {
+		let encodings = &params["capabilities"]["general"]["positionEncodings"];
+		let encodings = encodings.as_array().map(Vec::as_slice);
+		if encodings.unwrap_or_default().contains(&json!("utf-8")) {
+			self.encoding = Encoding::Utf8;
+		}
+
+		json!({
+			"capabilities": {
+				"positionEncoding": self.encoding.name(),
+				"textDocumentSync": 1,
+				"definitionProvider": true,
+				"referencesProvider": true,
+				"hoverProvider": true,
+				"documentSymbolProvider": true,
+			},
+			"serverInfo": {
+				"name": "rlox",
+				"version": env!("CARGO_PKG_VERSION"),
+			},
+		})
+	}
+
+	/// The document `params` is about, and the line and byte column of its
+	/// position, 1-based like spans.
+	fn position(
+		&self,
+		params: &Json,
+	) -> Result<(&Document, u32, u32), (i64, String)> {
+		let uri = params["textDocument"]["uri"].as_str().unwrap_or_default();
+		let Some(document) = self.documents.get(uri) else {
+			return Err((INVALID_PARAMS, format!("Unknown document {uri}.")));
+		};
+
+		let position = &params["position"];
+		let (Some(line), Some(character)) =
+			(position["line"].as_u64(), position["character"].as_u64())
+		else {
+			return Err((INVALID_PARAMS, "Missing position.".to_owned()));
+		};
+
+		let text = line_text(&document.text, line as u32 + 1);
+		let column = self.encoding.byte_of(text, character as u32);
+		Ok((document, line as u32 + 1, column + 1))
+	}
+
+	fn definition(&self, params: &Json) -> Response {
+		let uri = &params["textDocument"]["uri"];
+		let (document, line, column) = self.position(params)?;
+
+		let Some(symbol) = document.index.symbol_at(line, column) else {
+			return Ok(Json::Null);
+		};
+		let locations = symbol
+			.defs
+			.iter()
+			.map(|span| self.location(uri, document, *span))
+			.collect();
+		Ok(Json::Array(locations))
+	}
+
+	fn references(&self, params: &Json) -> Response {
+		let uri = &params["textDocument"]["uri"];
+		let (document, line, column) = self.position(params)?;
+		let include_declaration = params["context"]["includeDeclaration"]
+			.as_bool()
+			.unwrap_or(true);
+
+		let Some(symbol) = document.index.symbol_at(line, column) else {
+			return Ok(Json::Null);
+		};
+		let defs = symbol.defs.iter().filter(|_| include_declaration);
+		let locations = defs
+			.chain(&symbol.refs)
+			.map(|span| self.location(uri, document, *span))
+			.collect();
+		Ok(Json::Array(locations))
+	}
+
+	fn hover(&self, params: &Json) -> Response {
+		let (document, line, column) = self.position(params)?;
+		let index = &document.index;
+
+		let (Some(symbol), Some(span)) =
+			(index.symbol_at(line, column), index.span_at(line, column))
+		else {
+			return Ok(Json::Null);
+		};
+
+		let value = if symbol.hover.is_empty() {
+			// only globals and properties can be used without a declaration
+			let what = match symbol.kind {
+				SymbolKind::Method => "Property",
+				_ => "Global",
+			};
+			format!(
+				"```lox\n{}\n```\n\n{what} not declared in this file.",
+				symbol.name
+			)
+		} else {
+			symbol.hover.join("\n\n---\n\n")
+		};
+		Ok(json!({
+			"contents": { "kind": "markdown", "value": value },
+			"range": self.range(document, span),
+		}))
+	}
+
+	fn document_symbol(&self, params: &Json) -> Response {
+		let uri = params["textDocument"]["uri"].as_str().unwrap_or_default();
+		let Some(document) = self.documents.get(uri) else {
+			return Err((INVALID_PARAMS, format!("Unknown document {uri}.")));
+		};
+
+		let outline = document.index.outline.iter();
+		let symbol =
+			|outline| document_symbol(outline, &document.text, self.encoding);
+		Ok(outline.map(symbol).collect())
+	}
+
+	fn range(&self, document: &Document, span: Span) -> Json {
+		range(span, &document.text, self.encoding)
+	}
+
+	fn location(&self, uri: &Json, document: &Document, span: Span) -> Json {
+		json!({ "uri": uri, "range": self.range(document, span) })
+	}
+}
+
+impl Encoding {
+	fn name(self) -> &'static str {
+		match self {
+			Encoding::Utf8 => "utf-8",
+			Encoding::Utf16 => "utf-16",
+		}
+	}
+
+	/// The column of byte offset `byte` into `line`. Offsets past the end
+	/// of the line count as one column per byte.
+	fn column_of(self, line: &str, byte: u32) -> u32 {
+		if self == Encoding::Utf8 {
+			return byte;
+		}
+		let (head, past_end) = split_at_byte(line, byte as usize);
+		(head.encode_utf16().count() + past_end) as u32
+	}
+
+	/// The byte offset into `line` of column `column`, the inverse of
+	/// `column_of`. A column inside a character is taken as that character's
+	/// start.
+	fn byte_of(self, line: &str, column: u32) -> u32 {
+		if self == Encoding::Utf8 {
+			return column;
+		}
+		let mut units = 0;
+		for (byte, char) in line.char_indices() {
+			units += char.len_utf16() as u32;
+			if units > column {
+				return byte as u32;
+			}
+		}
+		line.len() as u32 + column - units
+	}
+}
+
+/// `line` up to byte offset `byte`, extended to the end of a character it
+/// falls inside, and how many bytes `byte` is past the end of `line`.
+fn split_at_byte(line: &str, byte: usize) -> (&str, usize) {
+	let end = (byte..=line.len())
+		.find(|end| line.is_char_boundary(*end))
+		.unwrap_or(line.len());
+	(&line[..end], byte.saturating_sub(line.len()))
+}
+
+/// The text of 1-based line `line` of `text`, without its line break.
+fn line_text(text: &str, line: u32) -> &str {
+	let line = text.split('\n').nth(line.saturating_sub(1) as usize);
+	line.unwrap_or_default().trim_end_matches('\r')
+}
+
+fn range(span: Span, text: &str, encoding: Encoding) -> Json {
+	let line = line_text(text, span.line);
+	let start = span.column.saturating_sub(1);
+	let end = encoding.column_of(line, start + span.len);
+	let start = encoding.column_of(line, start);
+	let line = span.line.saturating_sub(1);
+	json!({
+		"start": { "line": line, "character": start },
+		"end": { "line": line, "character": end },
+	})
+}
+
+fn line_range(line: u32) -> Json {
+	let line = line.saturating_sub(1);
+	json!({
+		"start": { "line": line, "character": 0 },
+		"end": { "line": line + 1, "character": 0 },
+	})
+}
+
+fn document_symbol(outline: &Outline, text: &str, encoding: Encoding) -> Json {
+	let kind = match outline.kind {
+		SymbolKind::Class => 5,
+		SymbolKind::Method => 6,
+		SymbolKind::Function => 12,
+		SymbolKind::Variable | SymbolKind::Parameter => 13,
+	};
+
+	let start = range(outline.start, text, encoding);
+	let end = range(outline.end, text, encoding);
+	let children = outline
+		.children
+		.iter()
+		.map(|child| document_symbol(child, text, encoding))
+		.collect::<Vec<_>>();
+	json!({
+		"name": outline.name,
+		"kind": kind,
+		"range": { "start": start["start"], "end": end["end"] },
+		"selectionRange": start,
+		"children": children,
+	})
+}
+
+fn error_diagnostic(
+	error: &eyre::Report,
+	text: &str,
+	encoding: Encoding,
+) -> Json {
+	let Some(error) = error.downcast_ref::<CompileError>() else {
+		return json!({
+			"range": line_range(1),
+			"severity": 1,
+			"source": "rlox",
+			"message": error.to_string(),
+		});
+	};
+
+	// errors at the end of the source or from the scanner have no text of
+	// their own, so underline the character they were found at instead
+	let span = Span {
+		line:   error.line,
+		column: error.column,
+		len:    error.len.max(1),
+	};
+	json!({
+		"range": range(span, text, encoding),
+		"severity": 1,
+		"source": "rlox",
+		"message": error.message,
+	})
+}
+
+fn warning_diagnostic(warning: &lint::Warning) -> Json {
+	json!({
+		"range": line_range(warning.line),
+		"severity": 2,
+		"source": "rlox",
+		"code": warning.code.name(),
+		"message": warning.message,
+	})
+}
+
+fn publish_diagnostics(uri: &str, diagnostics: Vec<Json>) -> Json {
+	json!({
+		"jsonrpc": "2.0",
+		"method": "textDocument/publishDiagnostics",
+		"params": { "uri": uri, "diagnostics": diagnostics },
+	})
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn diagnostics(text: &str) -> Vec<(i64, String)> {
+		let document = json!({ "uri": "file:///a.lox", "text": text });
+		let params = json!({ "textDocument": document });
+		let notifications =
+			Server::default().notify("textDocument/didOpen", &params);
+		let diagnostics = &notifications[0]["params"]["diagnostics"];
+		diagnostics
+			.as_array()
+			.unwrap()
+			.iter()
+			.map(|diagnostic| {
+				let severity = diagnostic["severity"].as_i64().unwrap();
+				let message = diagnostic["message"].as_str().unwrap();
+				(severity, message.to_owned())
+			})
+			.collect()
+	}
+
+	fn errors(text: &str) -> Vec<String> {
+		let diagnostics = diagnostics(text).into_iter();
+		let errors = diagnostics.filter(|(severity, _)| *severity == 1);
+		errors.map(|(_, message)| message).collect()
+	}
+
+	#[test]
+	fn non_ascii_text() {
+		let text = "// café\nvar s = \"naïve\";\nprint s;\n";
+		assert_eq!(errors(text), Vec::<String>::new());
+		assert_eq!(errors("var é = 1;")[0], "Unexpected character.");
+	}
+
+	#[test]
+	fn lowering_errors() {
+		assert_eq!(errors("class A < A {}"), [
+			"A class can't inherit from itself."
+		]);
+		assert_eq!(errors("class A { init() { return 1; } }"), [
+			"Can't return a value from an initializer."
+		]);
+		assert_eq!(errors("{ var a = 1; var a = 2; }"), [
+			"Already a variable with this name in this scope."
+		]);
+	}
+
+	/// Opens `text` on a server that agreed to `encodings`, and returns it
+	/// with the encoding it settled on and its diagnostics.
+	fn open(encodings: Json, text: &str) -> (Server, Json, Json) {
+		let mut server = Server::default();
+		let capabilities =
+			json!({ "general": { "positionEncodings": encodings } });
+		let result = server
+			.request("initialize", &json!({ "capabilities": capabilities }))
+			.unwrap();
+		let document = json!({ "uri": "file:///a.lox", "text": text });
+		let params = json!({ "textDocument": document });
+		let mut notifications = server.notify("textDocument/didOpen", &params);
+		let diagnostics = notifications[0]["params"]["diagnostics"].take();
+		let encoding = result["capabilities"]["positionEncoding"].clone();
+		(server, encoding, diagnostics)
+	}
+
+	/// The start and end columns of the references to the name at
+	/// `character` on the first line.
+	fn references(server: &mut Server, character: u32) -> Vec<(u64, u64)> {
+		let params = json!({
+			"textDocument": { "uri": "file:///a.lox" },
+			"position": { "line": 0, "character": character },
+		});
+		let locations = server.request("textDocument/references", &params);
+		let locations = locations.unwrap();
+		let columns = locations.as_array().unwrap().iter().map(|location| {
+			let range = &location["range"];
+			let start = range["start"]["character"].as_u64().unwrap();
+			(start, range["end"]["character"].as_u64().unwrap())
+		});
+		columns.collect()
+	}
+
+	#[test]
+	fn columns_count_utf16_code_units_by_default() {
+		// the emoji is four bytes and two code units, the ï two and one
+		let text = "var s = \"ï😀\"; print s;";
+		let (mut server, encoding, _) = open(json!(["utf-16"]), text);
+		assert_eq!(encoding, "utf-16");
+		assert_eq!(references(&mut server, 21), [(4, 5), (21, 22)]);
+
+		let (_, _, diagnostics) = open(json!(null), "print \"😀\" + é;");
+		assert_eq!(diagnostics[0]["message"], "Unexpected character.");
+		assert_eq!(diagnostics[0]["range"]["start"]["character"], 13);
+		assert_eq!(diagnostics[0]["range"]["end"]["character"], 14);
+	}
+
+	#[test]
+	fn columns_count_bytes_if_the_client_agrees() {
+		let text = "var s = \"ï😀\"; print s;";
+		let (mut server, encoding, _) = open(json!(["utf-8", "utf-16"]), text);
+		assert_eq!(encoding, "utf-8");
+		assert_eq!(references(&mut server, 24), [(4, 5), (24, 25)]);
+	}
+
+	#[test]
+	fn hover_says_what_an_undeclared_name_is() {
+		let (mut server, ..) = open(json!(null), "print a.b;");
+		let mut hover = |character| {
+			let params = json!({
+				"textDocument": { "uri": "file:///a.lox" },
+				"position": { "line": 0, "character": character },
+			});
+			let hover = server.request("textDocument/hover", &params).unwrap();
+			hover["contents"]["value"].as_str().unwrap().to_owned()
+		};
+		assert!(hover(6).ends_with("Global not declared in this file."));
+		assert!(hover(8).ends_with("Property not declared in this file."));
+	}
+}
@@ -0,0 +1,429 @@
+use std::collections::HashMap;
+
+use crate::ast::ClassDecl;
+use crate::ast::Expr;
+use crate::ast::FunctionDecl;
+use crate::ast::Program;
+use crate::ast::Stmt;
+use crate::compiler::Token;
+
+/// Where a token sits in the source. Lines and columns are 1-based, as in
+/// `Token`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Span {
+	pub line:   u32,
+	pub column: u32,
+	pub len:    u32,
+}
+
+impl Span {
+	fn of(token: &Token) -> Self {
+		Self {
+			line:   token.line,
+			column: token.column,
+			len:    token.text.len() as u32,
+		}
+	}
+
+	fn contains(&self, line: u32, column: u32) -> bool {
+		self.line == line
+			&& (self.column..=self.column + self.len).contains(&column)
+	}
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SymbolKind {
+	Class,
+	Function,
+	Method,
+	Parameter,
+	Variable,
+}
+
+/// Every declaration and use of one name. Globals and methods are matched by
+/// name alone, since Lox resolves them at runtime; each local gets a symbol
+/// of its own.
+#[derive(Debug)]
+pub struct Symbol {
+	pub name:  String,
+	pub kind:  SymbolKind,
+	pub defs:  Vec<Span>,
+	pub refs:  Vec<Span>,
+	/// Markdown describing each of `defs`.
+	pub hover: Vec<String>,
+}
+
+/// A top-level declaration, for the document outline.
+#[derive(Debug)]
+pub struct Outline {
+	pub name:     String,
+	pub kind:     SymbolKind,
+	pub start:    Span,
+	pub end:      Span,
+	pub children: Vec<Outline>,
+}
+
+/// The symbols of one document, built from its AST. It owns all of its text
+/// so that it can outlive the source it was built from, which lets the
+/// server keep answering queries while the user is mid-edit and the source
+/// doesn't parse.
+#[derive(Debug, Default)]
+pub struct Index {
+	pub symbols: Vec<Symbol>,
+	pub outline: Vec<Outline>,
+	occurrences: Vec<(Span, usize)>,
+}
+
+impl Index {
+	pub fn new(program: &Program) -> Self {
+		let mut builder = Builder {
+			index:      Index::default(),
+			globals:    HashMap::new(),
+			properties: HashMap::new(),
+			scopes:     Vec::new(),
+		};
+
+		for stmt in &program.stmts {
+			let (name, kind, hover) = match stmt {
+				Stmt::Class(decl) => {
+					(&decl.name, SymbolKind::Class, class_hover(decl))
+				},
+				Stmt::Function(decl) => {
+					(&decl.name, SymbolKind::Function, function_hover(decl, ""))
+				},
+				Stmt::Var { name, .. } => (
+					name,
+					SymbolKind::Variable,
+					code(&format!("var {}", name.text)),
+				),
+				_ => continue,
+			};
+
+			let symbol = builder.global(name, kind);
+			builder.def(symbol, name, hover);
+			if let Some(outline) = outline(stmt) {
+				builder.index.outline.push(outline);
+			}
+		}
+
+		builder.stmts(&program.stmts);
+		builder.index
+	}
+
+	/// The symbol whose name covers `line` and `column`, if any.
+	pub fn symbol_at(&self, line: u32, column: u32) -> Option<&Symbol> {
+		self.occurrences
+			.iter()
+			.find(|(span, _)| span.contains(line, column))
+			.map(|(_, symbol)| &self.symbols[*symbol])
+	}
+
+	/// The span of the name under `line` and `column`, if any.
+	pub fn span_at(&self, line: u32, column: u32) -> Option<Span> {
+		self.occurrences
+			.iter()
+			.map(|(span, _)| *span)
+			.find(|span| span.contains(line, column))
+	}
+}
+
+struct Builder<'ast> {
+	index:      Index,
+	globals:    HashMap<&'ast str, usize>,
+	properties: HashMap<&'ast str, usize>,
+	scopes:     Vec<Vec<(&'ast str, usize)>>,
+}
+
+impl<'ast> Builder<'ast> {
+	fn symbol(&mut self, name: &str, kind: SymbolKind) -> usize {
+		self.index.symbols.push(Symbol {
+			name: name.to_owned(),
+			kind,
+			defs: Vec::new(),
+			refs: Vec::new(),
+			hover: Vec::new(),
+		});
+		self.index.symbols.len() - 1
+	}
+
+	fn global(&mut self, name: &'ast Token<'ast>, kind: SymbolKind) -> usize {
+		if let Some(symbol) = self.globals.get(&*name.text) {
+			return *symbol;
+		}
+
+		let symbol = self.symbol(&name.text, kind);
+		self.globals.insert(&name.text, symbol);
+		symbol
+	}
+
+	fn property(&mut self, name: &'ast Token<'ast>) -> usize {
+		if let Some(symbol) = self.properties.get(&*name.text) {
+			return *symbol;
+		}
+
+		let symbol = self.symbol(&name.text, SymbolKind::Method);
+		self.properties.insert(&name.text, symbol);
+		symbol
+	}
+
+	fn def(&mut self, symbol: usize, name: &Token, hover: String) {
+		let span = Span::of(name);
+		if self.index.occurrences.iter().any(|(prev, _)| *prev == span) {
+			return;
+		}
+
+		let symbol_ref = &mut self.index.symbols[symbol];
+		symbol_ref.defs.push(span);
+		symbol_ref.hover.push(hover);
+		self.index.occurrences.push((span, symbol));
+	}
+
+	fn reference(&mut self, symbol: usize, name: &Token) {
+		let span = Span::of(name);
+		self.index.symbols[symbol].refs.push(span);
+		self.index.occurrences.push((span, symbol));
+	}
+
+	/// Declares `name` in the innermost scope, or as a global at the top
+	/// level.
+	fn declare(
+		&mut self,
+		name: &'ast Token<'ast>,
+		kind: SymbolKind,
+		hover: String,
+	) {
+		let symbol = match self.scopes.last() {
+			Some(_) => self.symbol(&name.text, kind),
+			None => self.global(name, kind),
+		};
+		self.def(symbol, name, hover);
+
+		if let Some(scope) = self.scopes.last_mut() {
+			scope.push((&name.text, symbol));
+		}
+	}
+
+	fn resolve(&mut self, name: &'ast Token<'ast>) {
+		let local = self.scopes.iter().rev().find_map(|scope| {
+			scope
+				.iter()
+				.rev()
+				.find(|(local, _)| *local == name.text)
+				.map(|(_, symbol)| *symbol)
+		});
+
+		let symbol = match local {
+			Some(symbol) => symbol,
+			None => self.global(name, SymbolKind::Variable),
+		};
+		self.reference(symbol, name);
+	}
+}
+
+impl<'ast> Builder<'ast> {
+	fn stmts(&mut self, stmts: &'ast [Stmt<'ast>]) {
+		stmts.iter().for_each(|stmt| self.stmt(stmt));
+	}
+
+	fn stmt(&mut self, stmt: &'ast Stmt<'ast>) {
+		match stmt {
+			Stmt::Block { stmts, .. } => {
+				self.scopes.push(Vec::new());
+				self.stmts(stmts);
+				self.scopes.pop();
+			},
+			Stmt::Class(decl) => self.class(decl),
+			Stmt::Expression(expr) | Stmt::Print { value: expr, .. } => {
+				self.expr(expr)
+			},
+			Stmt::For {
+				initializer,
+				condition,
+				increment,
+				body,
+				..
+			} => {
+				self.scopes.push(Vec::new());
+				if let Some(initializer) = initializer {
+					self.stmt(initializer);
+				}
+				condition.iter().for_each(|condition| self.expr(condition));
+				increment.iter().for_each(|increment| self.expr(increment));
+				self.stmt(body);
+				self.scopes.pop();
+			},
+			Stmt::Function(decl) => {
+				let hover = function_hover(decl, "");
+				self.declare(&decl.name, SymbolKind::Function, hover);
+				self.function(decl);
+			},
+			Stmt::If {
+				condition,
+				then_branch,
+				else_branch,
+				..
+			} => {
+				self.expr(condition);
+				self.stmt(then_branch);
+				if let Some(else_branch) = else_branch {
+					self.stmt(else_branch);
+				}
+			},
+			Stmt::Return { value, .. } => {
+				value.iter().for_each(|value| self.expr(value))
+			},
+			Stmt::Var { name, initializer } => {
+				initializer.iter().for_each(|value| self.expr(value));
+				let hover = code(&format!("var {}", name.text));
+				self.declare(name, SymbolKind::Variable, hover);
+			},
+			Stmt::While {
+				condition, body, ..
+			} => {
+				self.expr(condition);
+				self.stmt(body);
+			},
+		}
+	}
+
+	fn class(&mut self, decl: &'ast ClassDecl<'ast>) {
+		self.declare(&decl.name, SymbolKind::Class, class_hover(decl));
+		if let Some(superclass) = &decl.superclass {
+			self.resolve(superclass);
+		}
+
+		for method in &decl.methods {
+			let symbol = self.property(&method.name);
+			let owner = format!("{}.", decl.name.text);
+			self.def(symbol, &method.name, function_hover(method, &owner));
+			self.function(method);
+		}
+	}
+
+	fn function(&mut self, decl: &'ast FunctionDecl<'ast>) {
+		self.scopes.push(Vec::new());
+		for param in &decl.params {
+			let hover = code(&format!("(parameter) {}", param.text));
+			self.declare(param, SymbolKind::Parameter, hover);
+		}
+		self.stmts(&decl.body);
+		self.scopes.pop();
+	}
+
+	fn expr(&mut self, expr: &'ast Expr<'ast>) {
+		match expr {
+			Expr::Assign { name, value } => {
+				self.expr(value);
+				self.resolve(name);
+			},
+			Expr::Binary { left, right, .. }
+			| Expr::Logical { left, right, .. } => {
+				self.expr(left);
+				self.expr(right);
+			},
+			Expr::Call { callee, args, .. } => {
+				self.expr(callee);
+				args.iter().for_each(|arg| self.expr(arg));
+			},
+			Expr::Get { object, name } => {
+				self.expr(object);
+				let symbol = self.property(name);
+				self.reference(symbol, name);
+			},
+			Expr::Grouping(inner) => self.expr(inner),
+			Expr::Literal(_) | Expr::This(_) => (),
+			Expr::Set {
+				object,
+				name,
+				value,
+			} => {
+				self.expr(value);
+				self.expr(object);
+				let symbol = self.property(name);
+				self.reference(symbol, name);
+			},
+			Expr::Super { method, .. } => {
+				let symbol = self.property(method);
+				self.reference(symbol, method);
+			},
+			Expr::Unary { right, .. } => self.expr(right),
+			Expr::Variable(name) => self.resolve(name),
+		}
+	}
+}
+
+fn code(text: &str) -> String {
+	format!("```lox\n{text}\n```")
+}
+
+fn signature(decl: &FunctionDecl) -> String {
+	let params = decl
+		.params
+		.iter()
+		.map(|param| param.text.as_ref())
+		.collect::<Vec<_>>();
+	format!("{}({})", decl.name.text, params.join(", "))
+}
+
+fn function_hover(decl: &FunctionDecl, owner: &str) -> String {
+	let arity = match decl.params.len() {
+		1 => "1 argument".to_owned(),
+		len => format!("{len} arguments"),
+	};
+	format!(
+		"{}\n\nTakes {arity}.",
+		code(&format!("fun {owner}{}", signature(decl)))
+	)
+}
+
+fn class_hover(decl: &ClassDecl) -> String {
+	let mut header = format!("class {}", decl.name.text);
+	if let Some(superclass) = &decl.superclass {
+		header.push_str(&format!(" < {}", superclass.text));
+	}
+
+	let methods = decl
+		.methods
+		.iter()
+		.map(|method| format!("- `{}`", signature(method)))
+		.collect::<Vec<_>>();
+	if methods.is_empty() {
+		code(&header)
+	} else {
+		format!("{}\n\nMethods:\n{}", code(&header), methods.join("\n"))
+	}
+}
+
+fn outline(stmt: &Stmt) -> Option<Outline> {
+	let (name, kind, close, children) = match stmt {
+		Stmt::Class(decl) => {
+			let methods = decl
+				.methods
+				.iter()
+				.map(|method| Outline {
+					name:     method.name.text.to_string(),
+					kind:     SymbolKind::Method,
+					start:    Span::of(&method.name),
+					end:      Span::of(&method.close),
+					children: Vec::new(),
+				})
+				.collect();
+			(&decl.name, SymbolKind::Class, &decl.close, methods)
+		},
+		Stmt::Function(decl) => {
+			(&decl.name, SymbolKind::Function, &decl.close, Vec::new())
+		},
+		Stmt::Var { name, .. } => {
+			(name, SymbolKind::Variable, name, Vec::new())
+		},
+		_ => return None,
+	};
+
+	Some(Outline {
+		name: name.text.to_string(),
+		kind,
+		start: Span::of(name),
+		end: Span::of(close),
+		children,
+	})
+}
@@ -1,3 +1,404 @@
-fn main() {
-	println!("Hello, world!");
+use std::process::ExitCode;
+
+use rlox::vm::Vm;
+
+const MAX_FRAMES: usize = 64;
+const STACK_SIZE: usize = MAX_FRAMES * (u8::MAX as usize + 1);
+
+const USAGE: &str = "\
+Usage: rlox [path]
+       rlox --coverage out.info path
+       rlox dap
+       rlox debug path
+       rlox fmt [--check] [paths...]
+       rlox lint [--allow code]... paths...
+       rlox lsp
+       rlox profile [--folded out.folded] path
+       rlox trace [--function name] [--lines from-to] out.jsonl path";
+
+fn main() -> ExitCode {
+	let args = std::env::args().skip(1).collect::<Vec<_>>();
+	let args = args.iter().map(String::as_str).collect::<Vec<_>>();
+
+	match args.as_slice() {
+		#[cfg(feature = "coverage")]
+		["--coverage", out, path] => coverage(path, out),
+		#[cfg(feature = "dap")]
+		["dap"] => dap(),
+		#[cfg(feature = "debugger")]
+		["debug", path] => debug(path),
+		#[cfg(feature = "ast")]
+		["fmt", paths @ ..] => fmt(paths),
+		#[cfg(feature = "ast")]
+		["lint", args @ ..] => lint(args),
+		#[cfg(feature = "lsp")]
+		["lsp"] => lsp(),
+		#[cfg(feature = "profiler")]
+		["profile", "--folded", folded, path] => profile(path, Some(folded)),
+		#[cfg(feature = "profiler")]
+		["profile", path] => profile(path, None),
+		#[cfg(feature = "trace")]
+		["trace", args @ ..] => trace(args),
+		#[cfg(feature = "repl")]
+		[] => repl(),
+		[path] if !path.starts_with('-') => run_file(path),
+		_ => {
+			eprintln!("{USAGE}");
+			ExitCode::from(64)
+		},
+	}
+}
+
+fn read_file(path: &str) -> Result<String, ExitCode> {
+	std::fs::read_to_string(path).map_err(|err| {
+		eprintln!("Could not read file \"{path}\": {err}");
+		ExitCode::from(74)
+	})
+}
+
+fn run_file(path: &str) -> ExitCode {
+	let source = match read_file(path) {
+		Ok(source) => source,
+		Err(code) => return code,
+	};
+
+	let mut vm = Vm::<MAX_FRAMES, STACK_SIZE>::new();
+	match vm.interpret(&source) {
+		Ok(()) => ExitCode::SUCCESS,
+		Err(err) => {
+			eprintln!("{err}");
+			ExitCode::from(70)
+		},
+	}
+}
+
+#[cfg(feature = "coverage")]
+fn coverage(path: &str, out: &str) -> ExitCode {
+	use std::sync::Arc;
+	use std::sync::Mutex;
+
+	use rlox::coverage::Coverage;
+
+	let source = match read_file(path) {
+		Ok(source) => source,
+		Err(code) => return code,
+	};
+
+	let mut vm = Vm::<MAX_FRAMES, STACK_SIZE>::new();
+	let coverage = Arc::new(Mutex::new(Coverage::new()));
+	vm.add_hook(coverage.clone());
+	let code = match vm.interpret(&source) {
+		Ok(()) => ExitCode::SUCCESS,
+		Err(err) => {
+			eprintln!("{err}");
+			ExitCode::from(70)
+		},
+	};
+
+	let coverage = coverage.lock().unwrap();
+	let res = std::fs::File::create(out)
+		.map(std::io::BufWriter::new)
+		.and_then(|mut file| coverage.write_lcov(path, &mut file));
+	match res {
+		Ok(()) => code,
+		Err(err) => {
+			eprintln!("Could not write file \"{out}\": {err}");
+			ExitCode::from(74)
+		},
+	}
+}
+
+#[cfg(feature = "dap")]
+fn dap() -> ExitCode {
+	use rlox::debugger::dap::Session;
+
+	let stdin = std::io::BufReader::new(std::io::stdin());
+	let stdout = std::io::stdout();
+	let mut session = Session::new(stdin, stdout);
+	let res = session.launch().and_then(|launch| {
+		let Some(launch) = launch else {
+			return Ok(());
+		};
+
+		let mut vm = Vm::<MAX_FRAMES, STACK_SIZE>::new();
+		vm.add_hook(session.debugger(&launch));
+		let res = vm.interpret(&launch.source);
+		session.finish(res)
+	});
+
+	match res {
+		Ok(()) => ExitCode::SUCCESS,
+		Err(err) => {
+			eprintln!("{err}");
+			ExitCode::from(74)
+		},
+	}
+}
+
+#[cfg(feature = "debugger")]
+fn debug(path: &str) -> ExitCode {
+	use rlox::debugger::Cli;
+	use rlox::debugger::Debugger;
+
+	let source = match read_file(path) {
+		Ok(source) => source,
+		Err(code) => return code,
+	};
+
+	let mut vm = Vm::<MAX_FRAMES, STACK_SIZE>::new();
+	vm.add_hook(Debugger::new(Cli::new(&source)));
+	match vm.interpret(&source) {
+		Ok(()) => ExitCode::SUCCESS,
+		Err(err) => {
+			eprintln!("{err}");
+			ExitCode::from(70)
+		},
+	}
+}
+
+#[cfg(feature = "profiler")]
+fn profile(path: &str, folded: Option<&str>) -> ExitCode {
+	use std::sync::Arc;
+	use std::sync::Mutex;
+
+	use rlox::profiler::Profiler;
+
+	let source = match read_file(path) {
+		Ok(source) => source,
+		Err(code) => return code,
+	};
+
+	let mut vm = Vm::<MAX_FRAMES, STACK_SIZE>::new();
+	let profiler = Arc::new(Mutex::new(Profiler::new()));
+	vm.add_hook(profiler.clone());
+	let mut code = match vm.interpret(&source) {
+		Ok(()) => ExitCode::SUCCESS,
+		Err(err) => {
+			eprintln!("{err}");
+			ExitCode::from(70)
+		},
+	};
+
+	let profiler = profiler.lock().unwrap();
+	eprintln!();
+	if let Err(err) = profiler.write_summary(&mut std::io::stderr()) {
+		eprintln!("Could not write profile: {err}");
+		code = ExitCode::from(74);
+	}
+	if let Some(folded) = folded {
+		let res = std::fs::File::create(folded)
+			.map(std::io::BufWriter::new)
+			.and_then(|mut file| profiler.write_folded(&mut file));
+		if let Err(err) = res {
+			eprintln!("Could not write file \"{folded}\": {err}");
+			code = ExitCode::from(74);
+		}
+	}
+	code
+}
+
+#[cfg(feature = "repl")]
+fn repl() -> ExitCode {
+	let mut vm = Vm::<MAX_FRAMES, STACK_SIZE>::new();
+	match rlox::repl::run(&mut vm) {
+		Ok(()) => ExitCode::SUCCESS,
+		Err(err) => {
+			eprintln!("{err}");
+			ExitCode::from(74)
+		},
+	}
+}
+
+#[cfg(feature = "trace")]
+fn trace(mut args: &[&str]) -> ExitCode {
+	use std::sync::Arc;
+	use std::sync::Mutex;
+
+	use rlox::trace::Tracer;
+
+	let mut function = None;
+	let mut lines = None;
+	let (out, path) = loop {
+		match args {
+			["--function", name, rest @ ..] => {
+				function = Some(*name);
+				args = rest;
+			},
+			["--lines", range, rest @ ..] => {
+				let range = range.split_once('-').and_then(|(from, to)| {
+					Some(from.parse().ok()?..=to.parse().ok()?)
+				});
+				let Some(range) = range else {
+					eprintln!("Expect a line range like 10-20.");
+					return ExitCode::from(64);
+				};
+				lines = Some(range);
+				args = rest;
+			},
+			[out, path] => break (*out, *path),
+			_ => {
+				eprintln!("{USAGE}");
+				return ExitCode::from(64);
+			},
+		}
+	};
+
+	let source = match read_file(path) {
+		Ok(source) => source,
+		Err(code) => return code,
+	};
+	let file = match std::fs::File::create(out) {
+		Ok(file) => std::io::BufWriter::new(file),
+		Err(err) => {
+			eprintln!("Could not write file \"{out}\": {err}");
+			return ExitCode::from(74);
+		},
+	};
+
+	let mut tracer = Tracer::new(file);
+	if let Some(function) = function {
+		tracer = tracer.function(function);
+	}
+	if let Some(lines) = lines {
+		tracer = tracer.lines(lines);
+	}
+	let tracer = Arc::new(Mutex::new(tracer));
+
+	let mut vm = Vm::<MAX_FRAMES, STACK_SIZE>::new();
+	vm.add_hook(tracer.clone());
+	let code = match vm.interpret(&source) {
+		Ok(()) => ExitCode::SUCCESS,
+		Err(err) => {
+			eprintln!("{err}");
+			ExitCode::from(70)
+		},
+	};
+
+	// a temporary borrow in the tail expression would outlive `tracer`
+	let flushed = tracer.lock().unwrap().flush();
+	match flushed {
+		Ok(()) => code,
+		Err(err) => {
+			eprintln!("Could not write file \"{out}\": {err}");
+			ExitCode::from(74)
+		},
+	}
+}
+
+#[cfg(feature = "ast")]
+fn fmt(args: &[&str]) -> ExitCode {
+	use std::io::Read;
+
+	let check = args.first() == Some(&"--check");
+	let paths = if check { &args[1..] } else { args };
+
+	if paths.is_empty() {
+		let mut source = String::new();
+		if let Err(err) = std::io::stdin().read_to_string(&mut source) {
+			eprintln!("Could not read stdin: {err}");
+			return ExitCode::from(74);
+		}
+
+		return match rlox::fmt::format(&source) {
+			Ok(formatted) if check && formatted != source => ExitCode::FAILURE,
+			Ok(_) if check => ExitCode::SUCCESS,
+			Ok(formatted) => {
+				print!("{formatted}");
+				ExitCode::SUCCESS
+			},
+			Err(errors) => {
+				errors.iter().for_each(|err| eprintln!("{err}"));
+				ExitCode::from(65)
+			},
+		};
+	}
+
+	let mut code = ExitCode::SUCCESS;
+	for path in paths {
+		let source = match read_file(path) {
+			Ok(source) => source,
+			Err(err) => {
+				code = err;
+				continue;
+			},
+		};
+
+		match rlox::fmt::format(&source) {
+			Ok(formatted) if formatted == source => (),
+			Ok(_) if check => {
+				println!("{path}");
+				code = ExitCode::FAILURE;
+			},
+			Ok(formatted) => {
+				if let Err(err) = std::fs::write(path, formatted) {
+					eprintln!("Could not write file \"{path}\": {err}");
+					code = ExitCode::from(74);
+				}
+			},
+			Err(errors) => {
+				errors.iter().for_each(|err| eprintln!("{path}: {err}"));
+				code = ExitCode::from(65);
+			},
+		}
+	}
+	code
+}
+
+#[cfg(feature = "ast")]
+fn lint(mut args: &[&str]) -> ExitCode {
+	let mut config = rlox::lint::LintConfig::default();
+	while let ["--allow", name, rest @ ..] = args {
+		match name.parse() {
+			Ok(lint) => _ = config.allow(lint),
+			Err(err) => {
+				eprintln!("{err}");
+				return ExitCode::from(64);
+			},
+		}
+		args = rest;
+	}
+
+	if args.is_empty() {
+		eprintln!("{USAGE}");
+		return ExitCode::from(64);
+	}
+
+	let mut code = ExitCode::SUCCESS;
+	for path in args {
+		let source = match read_file(path) {
+			Ok(source) => source,
+			Err(err) => {
+				code = err;
+				continue;
+			},
+		};
+
+		match rlox::ast::parse(&source) {
+			Ok(program) => {
+				let warnings = rlox::lint::lint(&program, &config);
+				if !warnings.is_empty() {
+					code = ExitCode::FAILURE;
+				}
+				warnings.iter().for_each(|warn| eprintln!("{path}: {warn}"));
+			},
+			Err(errors) => {
+				errors.iter().for_each(|err| eprintln!("{path}: {err}"));
+				code = ExitCode::from(65);
+			},
+		}
+	}
+	code
+}
+
+#[cfg(feature = "lsp")]
+fn lsp() -> ExitCode {
+	let stdin = std::io::BufReader::new(std::io::stdin());
+	let stdout = std::io::stdout();
+	match rlox::lsp::serve(stdin, stdout) {
+		Ok(()) => ExitCode::SUCCESS,
+		Err(err) => {
+			eprintln!("{err}");
+			ExitCode::from(74)
+		},
+	}
 }
@@ -17,7 +17,19 @@ pub use self::gc_ref::*;
 pub use self::gc_vec::*;
 pub use self::inline_vec::*;
 
-pub trait Trace {}
+/// Something that can refer to objects on a heap.
+pub trait Trace {
+	/// Marks every object this refers to, so that a collection keeps them.
+	fn trace(&self, _marker: &mut Marker) {}
+}
+
+impl<T: Trace> Trace for Option<T> {
+	fn trace(&self, marker: &mut Marker) {
+		if let Some(value) = self {
+			value.trace(marker);
+		}
+	}
+}
 
 macro_rules! trace_primitives {
 	($($prim:ty),* $(,)?) => {
@@ -1,50 +1,314 @@
 use std::borrow::Borrow;
+use std::cell::Cell;
+use std::cell::OnceCell;
 use std::cell::RefCell;
+use std::collections::hash_map::RandomState;
+use std::hash::BuildHasher;
+use std::ptr::NonNull;
+use std::rc::Rc;
 
-use dashmap::DashSet;
+use hashbrown::HashMap;
 
 use super::GcRef;
+use super::Trace;
+use crate::obj::ForeignClass;
 use crate::obj::Obj;
 use crate::obj::ObjString;
 use crate::obj::ObjTy;
+use crate::obj::ObjType;
+use crate::value::List;
 
-scoped_tls::scoped_thread_local!(pub static GC: GarbageCollector);
-
+/// A heap of Lox objects and its string intern table. Each VM owns one, and
+/// every object is allocated through it, so objects of different VMs never
+/// mix. Dropping the collector frees all of its objects.
+#[derive(Default)]
 pub struct GarbageCollector {
-	objects: RefCell<Option<GcRef<Obj>>>,
-	strings: DashSet<GcRef<ObjString>>,
+	objects:   RefCell<Option<GcRef<Obj>>>,
+	/// Boxed so that ropes can intern themselves once flattened, wherever
+	/// the collector has moved since.
+	strings:   Box<StringTable>,
+	/// The class of the lists `Vec`s convert to, built on first use.
+	lists:     OnceCell<Rc<ForeignClass>>,
+	/// Bytes allocated over the collector's lifetime.
+	allocated: Cell<usize>,
+	/// Objects nothing on the heap or in the VM refers to yet, such as the
+	/// functions being compiled.
+	roots:     RefCell<Vec<GcRef<Obj>>>,
+
+	recording: Cell<bool>,
+	events:    RefCell<Vec<GcEvent>>,
+}
+
+/// Every string on a heap but unflattened ropes, keyed by contents. Entries
+/// are weak: a collection drops the strings nothing else reaches.
+pub(crate) type StringTable =
+	RefCell<HashMap<GcRef<ObjString>, (), RandomState>>;
+
+/// The objects a collection has found reachable, but whose references it
+/// hasn't followed yet.
+#[derive(Default)]
+pub struct Marker {
+	gray: Vec<GcRef<Obj>>,
+}
+
+/// What's on the heap after a collection.
+#[derive(Clone, Debug, Default)]
+pub struct HeapStats {
+	pub live:     usize,
+	pub freed:    usize,
+	/// Live objects of each type, most common first.
+	pub by_type:  Vec<(ObjType, usize)>,
+	pub interned: usize,
+}
+
+/// Something the collector did, recorded for VM hooks. Collections are
+/// only run by `Vm::collect_garbage`, which tells the hooks itself.
+#[derive(Clone, Copy, Debug)]
+pub enum GcEvent {
+	Alloc { ty: ObjType, size: usize },
 }
 
 impl GarbageCollector {
+	/// The string with contents `text`, allocated only if no string with
+	/// the same contents is on the heap yet.
 	pub fn intern_string(
 		&self,
 		text: impl Borrow<str> + Into<String>,
 	) -> GcRef<ObjString> {
 		let as_str: &str = text.borrow();
-		if let Some(interned) = self.strings.get(as_str) {
-			return interned.clone();
+		let hash = ObjString::hash_str(as_str);
+		if let Some(interned) = find_interned(&self.strings, hash, as_str) {
+			return interned;
 		}
 
-		let hash = self.strings.hash_usize(&as_str);
-		let text = String::leak(text.into());
+		let string = self.new_object(ObjString::flat(hash, text));
+		self.allocated
+			.set(self.allocated.get() + string.heap_size());
+		self.strings.borrow_mut().insert(string, ());
+		string
+	}
 
-		let mut string = self.new_object::<ObjString>();
-		string.hash = hash;
-		string.text = text;
+	/// A rope of `left` followed by `right`, see `ObjString::concat`.
+	pub fn new_rope(
+		&self,
+		left: GcRef<ObjString>,
+		right: GcRef<ObjString>,
+	) -> GcRef<ObjString> {
+		let strings = NonNull::from(&*self.strings);
+		let string = self.new_object(ObjString::rope(left, right, strings));
+		// charged up front, so doubling a rope runs into the heap limit
+		// before flattening it allocates the text
+		self.allocated
+			.set(self.allocated.get() + string.heap_size());
 		string
 	}
 
-	pub fn new_object<Type: ObjTy>(&self) -> GcRef<Type> {
-		let alloc =
-			Box::leak(unsafe { Box::<Type>::new_zeroed().assume_init() });
-		let mut res = GcRef::new_raw(alloc);
+	/// The class `Vec`s convert to, see `List`.
+	pub(crate) fn list_class(&self) -> Rc<ForeignClass> {
+		let class = self.lists.get_or_init(|| List::class().build(self).into());
+		class.clone()
+	}
 
-		res.ty = Type::OBJ_TYPE;
+	/// Moves `object` onto the heap, where collections can free it.
+	pub fn new_object<Type: ObjTy>(&self, object: Type) -> GcRef<Type> {
+		let alloc = Box::leak(Box::new(object));
+		let mut res = GcRef::new_raw(alloc);
 		{
 			let mut objects = self.objects.borrow_mut();
 			res.next = objects.take();
-			*objects = Some(res.clone().downcast())
+			*objects = Some(res.downcast())
+		}
+
+		let size = std::mem::size_of::<Type>();
+		self.allocated.set(self.allocated.get() + size);
+		if self.recording.get() {
+			self.events.borrow_mut().push(GcEvent::Alloc {
+				ty: Type::OBJ_TYPE,
+				size,
+			});
 		}
 		res
 	}
+
+	/// Keeps `obj` alive until the matching `pop_root`, while nothing else
+	/// refers to it.
+	pub(crate) fn push_root(&self, obj: GcRef<Obj>) {
+		self.roots.borrow_mut().push(obj);
+	}
+
+	pub(crate) fn pop_root(&self) {
+		self.roots.borrow_mut().pop();
+	}
+
+	/// Runs a collection cycle and reports what's left on the heap. Objects
+	/// survive if `mark_roots` marks them, if they're pushed as roots, or if
+	/// a surviving object refers to them.
+	pub(crate) fn collect(
+		&self,
+		mark_roots: impl FnOnce(&mut Marker),
+	) -> HeapStats {
+		let mut marker = Marker::default();
+		self.roots.borrow().iter().for_each(|root| marker.mark(*root));
+		mark_roots(&mut marker);
+		if let Some(lists) = self.lists.get() {
+			lists.trace(&mut marker);
+		}
+		while let Some(obj) = marker.gray.pop() {
+			obj.trace_references(&mut marker);
+		}
+
+		self.remove_white_strings();
+		let mut stats = self.sweep();
+		stats.interned = RefCell::borrow(&self.strings).len();
+		stats
+	}
+
+	/// Drops the strings about to be swept from the intern table, so it
+	/// never holds a freed string.
+	fn remove_white_strings(&self) {
+		self.strings
+			.borrow_mut()
+			.retain(|string, ()| string.is_marked);
+	}
+
+	/// Frees every unmarked object and unmarks the rest for the next cycle.
+	fn sweep(&self) -> HeapStats {
+		let mut stats = HeapStats::default();
+		let mut objects = self.objects.borrow_mut();
+		let mut previous: Option<GcRef<Obj>> = None;
+		let mut next = *objects;
+
+		while let Some(mut obj) = next {
+			next = obj.next;
+			if !obj.is_marked {
+				match &mut previous {
+					Some(previous) => previous.next = next,
+					None => *objects = next,
+				}
+				// SAFETY: the object is unreachable, and was just dropped from
+				// the intern table if it's a string
+				unsafe { obj.free() };
+				stats.freed += 1;
+				continue;
+			}
+
+			obj.is_marked = false;
+			previous = Some(obj);
+			stats.live += 1;
+			match stats.by_type.iter_mut().find(|(ty, _)| *ty == obj.ty) {
+				Some((_, count)) => *count += 1,
+				None => stats.by_type.push((obj.ty, 1)),
+			}
+		}
+		stats.by_type.sort_by(|(_, a), (_, b)| b.cmp(a));
+		stats
+	}
+
+	/// Bytes allocated over the collector's lifetime, including the text of
+	/// strings.
+	pub fn bytes_allocated(&self) -> usize {
+		self.allocated.get()
+	}
+
+	/// Whether to record allocations until they're taken with
+	/// `take_events`.
+	pub fn record_events(&self, record: bool) {
+		self.recording.set(record);
+		if !record {
+			self.events.borrow_mut().clear();
+		}
+	}
+
+	pub fn take_events(&self) -> Vec<GcEvent> {
+		std::mem::take(&mut *self.events.borrow_mut())
+	}
+}
+
+impl Marker {
+	/// Marks `obj` as reachable, and queues it to have its own references
+	/// marked.
+	pub fn mark(&mut self, mut obj: GcRef<Obj>) {
+		if !obj.is_marked {
+			obj.is_marked = true;
+			self.gray.push(obj);
+		}
+	}
+}
+
+/// The string in `table` with contents `text`, which hash to `hash`.
+pub(crate) fn find_interned(
+	table: &StringTable,
+	hash: usize,
+	text: &str,
+) -> Option<GcRef<ObjString>> {
+	let strings = table.borrow();
+	// strings hash as their `hash`, not their contents
+	let table_hash = strings.hasher().hash_one(hash);
+	let interned = strings.raw_entry().from_hash(table_hash, |s| {
+		s.hash() == hash && s.as_str() == text
+	});
+	interned.map(|(interned, ())| *interned)
+}
+
+impl Drop for GarbageCollector {
+	fn drop(&mut self) {
+		let mut next = self.objects.get_mut().take();
+		while let Some(obj) = next {
+			next = obj.next;
+			// SAFETY: every object on the list was allocated by `new_object`,
+			// and nothing can reach it once its collector is gone
+			unsafe { obj.free() };
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::mem::Trace;
+
+	fn is_interned(gc: &GarbageCollector, text: &str) -> bool {
+		find_interned(&gc.strings, ObjString::hash_str(text), text).is_some()
+	}
+
+	#[test]
+	fn collect_frees_unreachable_strings() {
+		let gc = GarbageCollector::default();
+		let kept = ObjString::new(&gc, "kept");
+		ObjString::new(&gc, "dropped");
+
+		let stats = gc.collect(|marker| kept.trace(marker));
+		assert_eq!(stats.freed, 1);
+		assert_eq!(stats.live, 1);
+		assert_eq!(stats.interned, 1);
+		assert!(is_interned(&gc, "kept"));
+		assert!(!is_interned(&gc, "dropped"));
+	}
+
+	#[test]
+	fn ropes_keep_their_pieces() {
+		let gc = GarbageCollector::default();
+		let left = ObjString::new(&gc, "a string too long to be inline, ");
+		let right = ObjString::new(&gc, "and another one");
+		let rope = ObjString::concat(&gc, left, right).unwrap();
+
+		let stats = gc.collect(|marker| rope.trace(marker));
+		assert_eq!(stats.freed, 0);
+		assert_eq!(
+			rope.as_str(),
+			"a string too long to be inline, and another one"
+		);
+	}
+
+	#[test]
+	fn roots_survive_until_popped() {
+		let gc = GarbageCollector::default();
+		let string = ObjString::new(&gc, "rooted");
+		gc.push_root(string.downcast());
+		assert_eq!(gc.collect(|_| {}).freed, 0);
+
+		gc.pop_root();
+		assert_eq!(gc.collect(|_| {}).freed, 1);
+		assert!(!is_interned(&gc, "rooted"));
+	}
 }
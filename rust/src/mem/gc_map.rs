@@ -5,8 +5,11 @@ use std::mem::MaybeUninit;
 use std::ops::Deref;
 use std::ops::DerefMut;
 
+use super::Marker;
 use super::Trace;
 
+// boxed to keep maps one pointer wide in objects
+#[allow(clippy::box_collection)]
 pub struct GcMap<K, V, S = RandomState>(
 	ManuallyDrop<MaybeUninit<Box<HashMap<K, V, S>>>>,
 );
@@ -17,14 +20,6 @@ impl<K, V> GcMap<K, V, RandomState> {
 	}
 }
 
-impl<K, V, S> Clone for GcMap<K, V, S> {
-	fn clone(&self) -> Self {
-		Self(ManuallyDrop::new(MaybeUninit::new(unsafe {
-			self.0.assume_init_read()
-		})))
-	}
-}
-
 impl<K, V, S: Default> Default for GcMap<K, V, S> {
 	fn default() -> Self {
 		Self(ManuallyDrop::new(MaybeUninit::new(Box::new(HashMap::<
@@ -35,6 +30,13 @@ impl<K, V, S: Default> Default for GcMap<K, V, S> {
 	}
 }
 
+impl<K, V, S> Drop for GcMap<K, V, S> {
+	fn drop(&mut self) {
+		// SAFETY: `default` initialized the map, and it's only taken here
+		drop(unsafe { ManuallyDrop::take(&mut self.0).assume_init() });
+	}
+}
+
 impl<K, V, S> Deref for GcMap<K, V, S> {
 	type Target = HashMap<K, V, S>;
 
@@ -49,4 +51,11 @@ impl<K, V, S> DerefMut for GcMap<K, V, S> {
 	}
 }
 
-impl<K: Trace, V: Trace> Trace for GcMap<K, V> {}
+impl<K: Trace, V: Trace> Trace for GcMap<K, V> {
+	fn trace(&self, marker: &mut Marker) {
+		for (key, value) in self.iter() {
+			key.trace(marker);
+			value.trace(marker);
+		}
+	}
+}
@@ -7,6 +7,9 @@ impl<T: Trace> GcPtr<T> {
 		Self(Some(raw))
 	}
 
+	/// # Safety
+	///
+	/// The pointer must be set before it's dereferenced, which panics.
 	pub unsafe fn null() -> Self {
 		Self(None)
 	}
@@ -32,9 +35,9 @@ impl<T: Trace> DerefMut for GcPtr<T> {
 	}
 }
 
-impl<T: Trace> Into<GcRef<T>> for GcPtr<T> {
-	fn into(self) -> GcRef<T> {
-		match self.0 {
+impl<T: Trace> From<GcPtr<T>> for GcRef<T> {
+	fn from(ptr: GcPtr<T>) -> Self {
+		match ptr.0 {
 			Some(res) => res,
 			None => unreachable!("null GC pointer"),
 		}
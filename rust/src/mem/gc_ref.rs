@@ -1,16 +1,30 @@
-use std::fmt::Debug;
 use std::hash::Hash;
 
 use super::*;
+use crate::obj::Obj;
 
+/// A reference to an object on a VM's heap.
+///
+/// It's neither `Send` nor `Sync`: a heap is only ever used by one thread at a
+/// time, and only moves to another thread together with its whole VM.
 #[repr(transparent)]
 pub struct GcRef<T: Trace>(NonNull<T>);
 
+/// A `GcRef` held by something that lives on the same heap as the object, or
+/// in the same VM, so that it moves between threads along with the object.
+pub(crate) struct HeapBound<T: Trace>(GcRef<T>);
+
 impl<T: Trace> GcRef<T> {
 	pub fn new_raw(ptr: &mut T) -> GcRef<T> {
 		Self(ptr.into())
 	}
 
+	/// A reference to an object that's already borrowed, such as `self` in
+	/// the object's methods.
+	pub(crate) fn from_ref(obj: &T) -> GcRef<T> {
+		Self(obj.into())
+	}
+
 	pub fn as_ptr(&self) -> *const T {
 		self.0.as_ptr()
 	}
@@ -18,7 +32,7 @@ impl<T: Trace> GcRef<T> {
 
 impl<T: Trace> Clone for GcRef<T> {
 	fn clone(&self) -> Self {
-		GcRef(self.0)
+		*self
 	}
 }
 
@@ -38,17 +52,9 @@ impl<T: Trace> Deref for GcRef<T> {
 impl<T: Trace> DerefMut for GcRef<T> {
 	fn deref_mut(&mut self) -> &mut Self::Target {
 		unsafe {
-			// TODO:
-			// SAFETY: Ok... so... this *technically* isn't safe. Technically
-			// this exposes a race condition on values that are shared across
-			// threads, which is dangerous.
-			//
-			// HOWEVER: this implementation of Lox is only supposed to be
-			// feature-complete with the clox implementation in Crafting
-			// Interpreters, which doesn't have any concept of threads or any
-			// other async primitive. That means that as long as this `rlox`
-			// implementation doesn't add new features, we don't have an async
-			// race condition!
+			// SAFETY: `GcRef`s can't leave the thread their heap is on, and a
+			// VM runs one instruction at a time, so no other reference to the
+			// object is in use while this one is
 			self.0.as_mut()
 		}
 	}
@@ -56,7 +62,7 @@ impl<T: Trace> DerefMut for GcRef<T> {
 
 impl<T: Display + Trace> Display for GcRef<T> {
 	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-		self.0.fmt(f)
+		self.deref().fmt(f)
 	}
 }
 
@@ -74,4 +80,34 @@ impl<T: Hash + Trace> Hash for GcRef<T> {
 	}
 }
 
-impl<T: Trace> Trace for GcRef<T> {}
+impl<T: Trace> Trace for GcRef<T> {
+	fn trace(&self, marker: &mut Marker) {
+		// every `GcRef` points to an object, which starts with its header
+		marker.mark(GcRef(self.0.cast::<Obj>()));
+	}
+}
+
+impl<T: Trace> HeapBound<T> {
+	/// # Safety
+	/// Whatever holds the result must stay in the VM that owns the object's
+	/// heap, and must not hand the reference out of it.
+	pub(crate) unsafe fn new(gc_ref: GcRef<T>) -> Self {
+		Self(gc_ref)
+	}
+
+	pub(crate) fn get(&self) -> GcRef<T> {
+		self.0
+	}
+}
+
+impl<T: Trace> Clone for HeapBound<T> {
+	fn clone(&self) -> Self {
+		*self
+	}
+}
+
+impl<T: Trace> Copy for HeapBound<T> {}
+
+// SAFETY: the holder moves between threads only together with the object's
+// heap, as `HeapBound::new` requires
+unsafe impl<T: Trace> Send for HeapBound<T> {}
@@ -18,15 +18,6 @@ impl<T: Trace> GcVec<T> {
 	}
 }
 
-impl<T: Trace> Clone for GcVec<T> {
-	fn clone(&self) -> Self {
-		Self {
-			vec:    self.vec,
-			gc_tmp: self.gc_tmp,
-		}
-	}
-}
-
 impl<T: Trace> Default for GcVec<T> {
 	fn default() -> Self {
 		let vec = Box::leak(Box::new(Vec::new())).into();
@@ -35,6 +26,16 @@ impl<T: Trace> Default for GcVec<T> {
 	}
 }
 
+impl<T: Trace> Drop for GcVec<T> {
+	fn drop(&mut self) {
+		// SAFETY: `default` leaked both boxes for this vector alone
+		unsafe {
+			drop(Box::from_raw(self.vec.as_ptr()));
+			drop(Box::from_raw(self.gc_tmp.as_ptr()));
+		}
+	}
+}
+
 impl<T: Trace> Deref for GcVec<T> {
 	type Target = Vec<T>;
 
@@ -49,4 +50,8 @@ impl<T: Trace> DerefMut for GcVec<T> {
 	}
 }
 
-impl<T: Trace> Trace for GcVec<T> {}
+impl<T: Trace> Trace for GcVec<T> {
+	fn trace(&self, marker: &mut Marker) {
+		self.iter().for_each(|value| value.trace(marker));
+	}
+}
@@ -1,7 +1,9 @@
 use std::mem::MaybeUninit;
 use std::ops::Deref;
 use std::ops::DerefMut;
+use std::ptr::NonNull;
 
+use super::Marker;
 use super::Trace;
 
 pub struct InlineVec<const CAPACITY: usize, T> {
@@ -29,6 +31,11 @@ impl<const CAPACITY: usize, T> InlineVec<CAPACITY, T> {
 		self.len == CAPACITY
 	}
 
+	/// The start of the buffer, including the slots past the end.
+	pub fn as_uninit_ptr(&mut self) -> NonNull<MaybeUninit<T>> {
+		NonNull::from(&mut self.buf).cast()
+	}
+
 	pub fn last(&self) -> Option<&T> {
 		if self.len == 0 {
 			return None;
@@ -42,9 +49,10 @@ impl<const CAPACITY: usize, T> InlineVec<CAPACITY, T> {
 		if self.len == 0 {
 			return None;
 		}
-		let slot = &self.buf[self.len];
 		self.len -= 1;
-		Some(unsafe { slot.assume_init_read() })
+		// SAFETY: slots below the old length are initialized, and lowering
+		// the length first means this one won't be read again
+		Some(unsafe { self.buf[self.len].assume_init_read() })
 	}
 
 	pub fn pop_n(&mut self, n: usize) {
@@ -53,7 +61,7 @@ impl<const CAPACITY: usize, T> InlineVec<CAPACITY, T> {
 
 	pub fn push(&mut self, value: T) {
 		assert!(
-			self.len <= CAPACITY,
+			self.len < CAPACITY,
 			"overflow: InlineVec has capacity of {CAPACITY}"
 		);
 		let slot = &mut self.buf[self.len];
@@ -111,7 +119,11 @@ impl<const CAPACITY: usize, T> IntoIterator for InlineVec<CAPACITY, T> {
 	}
 }
 
-impl<const CAPACITY: usize, T: Trace> Trace for InlineVec<CAPACITY, T> {}
+impl<const CAPACITY: usize, T: Trace> Trace for InlineVec<CAPACITY, T> {
+	fn trace(&self, marker: &mut Marker) {
+		self.iter().for_each(|value| value.trace(marker));
+	}
+}
 
 pub struct InlineVecIterator<const CAPACITY: usize, T> {
 	start: usize,
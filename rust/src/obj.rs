@@ -1,8 +1,10 @@
 mod macros;
+#[allow(clippy::module_inception)]
 mod obj;
 mod obj_bound_method;
 mod obj_class;
 mod obj_closure;
+mod obj_foreign;
 mod obj_function;
 mod obj_instance;
 mod obj_native;
@@ -23,8 +25,15 @@ pub use self::obj::Obj;
 pub use self::obj_bound_method::ObjBoundMethod;
 pub use self::obj_class::ObjClass;
 pub use self::obj_closure::ObjClosure;
+pub use self::obj_foreign::ForeignClass;
+pub use self::obj_foreign::ForeignClassBuilder;
+pub use self::obj_foreign::ObjForeign;
+pub use self::obj_function::LocalInfo;
 pub use self::obj_function::ObjFunction;
 pub use self::obj_instance::ObjInstance;
+pub use self::obj_instance::Shape;
+pub use self::obj_native::Arity;
+pub use self::obj_native::NativeFn;
 pub use self::obj_native::ObjNative;
 pub use self::obj_string::ObjString;
 pub use self::obj_upvalue::ObjUpvalue;
@@ -32,11 +41,12 @@ use crate::chunk::Chunk;
 use crate::mem::*;
 use crate::value::Value;
 
-#[derive(Clone, Copy, PartialEq, Eq)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum ObjType {
 	BoundMethod,
 	Class,
 	Closure,
+	Foreign,
 	Function,
 	Instance,
 	Native,
@@ -54,6 +64,7 @@ macros::value_impls!(obj: Obj =>
 	BoundMethod,
 	Class,
 	Closure,
+	Foreign,
 	Function,
 	Instance,
 	Native,
@@ -1,19 +1,37 @@
 use super::*;
 
 #[repr(C)]
+#[allow(clippy::manual_non_exhaustive)]
 pub struct Obj {
 	pub ty:        ObjType,
 	pub next:      Option<GcRef<Obj>>,
+	/// Whether the current collection found the object reachable.
+	pub is_marked: bool,
 	__noconstruct: (),
 }
 
+impl Obj {
+	/// The header of an object of type `Type`, before it's on the heap.
+	pub(super) fn new<Type: ObjTy>() -> Self {
+		Self {
+			ty:            Type::OBJ_TYPE,
+			next:          None,
+			is_marked:     false,
+			__noconstruct: (),
+		}
+	}
+}
+
 impl GcRef<Obj> {
+	/// # Safety
+	///
+	/// The object must be of type `Type`.
 	pub unsafe fn cast_unchecked<Type: ObjTy>(self) -> GcRef<Type> {
-		std::mem::transmute(self)
+		std::mem::transmute::<GcRef<Obj>, GcRef<Type>>(self)
 	}
 
 	pub fn try_cast<Type: ObjTy>(self) -> Option<GcRef<Type>> {
-		if Type::OBJ_TYPE == (*self).ty {
+		if Type::OBJ_TYPE == self.ty {
 			Some(unsafe {
 				// SAFETY: we've type-checked the value through `self.ty` - as
 				// long as the value was correctly initialized (that is,
@@ -24,7 +42,7 @@ impl GcRef<Obj> {
 				//   that only types in `super` are passed as the casted type
 				// - `macros::value_impls!` guarantees that the `impl ObjTy`
 				//   uses the correct enum value for `ObjTy::OBJ_TYPE`
-				std::mem::transmute(self)
+				std::mem::transmute::<GcRef<Obj>, GcRef<Type>>(self)
 			})
 		} else {
 			None
@@ -32,6 +50,57 @@ impl GcRef<Obj> {
 	}
 }
 
+impl GcRef<Obj> {
+	/// Marks the objects this one refers to, by its type.
+	pub(crate) fn trace_references(self, marker: &mut Marker) {
+		fn trace<Type: ObjTy>(obj: GcRef<Obj>, marker: &mut Marker) {
+			// SAFETY: `ty` says which type of object this is
+			let obj = unsafe { obj.cast_unchecked::<Type>() };
+			Type::trace(&*obj, marker);
+		}
+
+		match self.ty {
+			ObjType::BoundMethod => trace::<ObjBoundMethod>(self, marker),
+			ObjType::Class => trace::<ObjClass>(self, marker),
+			ObjType::Closure => trace::<ObjClosure>(self, marker),
+			ObjType::Foreign => trace::<ObjForeign>(self, marker),
+			ObjType::Function => trace::<ObjFunction>(self, marker),
+			ObjType::Instance => trace::<ObjInstance>(self, marker),
+			ObjType::Native => trace::<ObjNative>(self, marker),
+			ObjType::String => trace::<ObjString>(self, marker),
+			ObjType::Upvalue => trace::<ObjUpvalue>(self, marker),
+		}
+	}
+
+	/// Drops the object and frees its memory.
+	///
+	/// # Safety
+	/// The object must have been allocated by `GarbageCollector::new_object`,
+	/// and must never be used again.
+	pub(crate) unsafe fn free(self) {
+		unsafe fn free<Type: ObjTy>(obj: GcRef<Obj>) {
+			unsafe {
+				let obj = obj.cast_unchecked::<Type>().as_ptr().cast_mut();
+				drop(Box::from_raw(obj));
+			}
+		}
+
+		unsafe {
+			match self.ty {
+				ObjType::BoundMethod => free::<ObjBoundMethod>(self),
+				ObjType::Class => free::<ObjClass>(self),
+				ObjType::Closure => free::<ObjClosure>(self),
+				ObjType::Foreign => free::<ObjForeign>(self),
+				ObjType::Function => free::<ObjFunction>(self),
+				ObjType::Instance => free::<ObjInstance>(self),
+				ObjType::Native => free::<ObjNative>(self),
+				ObjType::String => free::<ObjString>(self),
+				ObjType::Upvalue => free::<ObjUpvalue>(self),
+			}
+		}
+	}
+}
+
 impl Display for GcRef<Obj> {
 	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
 		unsafe {
@@ -45,6 +114,9 @@ impl Display for GcRef<Obj> {
 				ObjType::Closure => {
 					write!(f, "{}", self.cast_unchecked::<ObjClosure>())
 				},
+				ObjType::Foreign => {
+					write!(f, "{}", self.cast_unchecked::<ObjForeign>())
+				},
 				ObjType::Function => {
 					write!(f, "{}", self.cast_unchecked::<ObjFunction>())
 				},
@@ -5,22 +5,33 @@ pub struct ObjBoundMethod {
 	pub(super) obj: Obj,
 
 	pub receiver: Value,
-	pub method:   GcRef<ObjClosure>,
+	/// A closure, or a foreign class's method native.
+	pub method:   Value,
 }
 
 impl ObjBoundMethod {
-	pub fn new(receiver: Value, method: GcRef<ObjClosure>) -> GcRef<Self> {
-		let mut res = GC.with(|gc| gc.new_object::<Self>());
-		res.receiver = receiver;
-		res.method = method;
-		res
+	pub fn new(
+		gc: &GarbageCollector,
+		receiver: Value,
+		method: Value,
+	) -> GcRef<Self> {
+		gc.new_object(Self {
+			obj: Obj::new::<Self>(),
+			receiver,
+			method,
+		})
 	}
 }
 
 impl Display for ObjBoundMethod {
 	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-		self.method.function.fmt(f)
+		self.method.fmt(f)
 	}
 }
 
-impl Trace for ObjBoundMethod {}
+impl Trace for ObjBoundMethod {
+	fn trace(&self, marker: &mut Marker) {
+		self.receiver.trace(marker);
+		self.method.trace(marker);
+	}
+}
@@ -1,18 +1,46 @@
+use std::rc::Rc;
+
 use super::*;
 
 #[repr(C)]
 pub struct ObjClass {
 	pub(super) obj: Obj,
 
-	pub name:    GcRef<ObjString>,
-	pub methods: GcMap<GcRef<ObjString>, GcRef<ObjFunction>>,
+	pub name:           GcRef<ObjString>,
+	methods:            GcMap<GcRef<ObjString>, GcRef<ObjClosure>>,
+	/// Changes whenever a method is added, which invalidates what inline
+	/// caches remember about the class.
+	pub(crate) version: u64,
+	/// The shape of the class's instances before they get any fields.
+	pub(crate) shape:   Rc<Shape>,
 }
 
 impl ObjClass {
-	pub fn new(name: GcRef<ObjString>) -> GcRef<Self> {
-		let mut klass = GC.with(|gc| gc.new_object::<Self>());
-		klass.name = name;
-		klass
+	pub fn new(gc: &GarbageCollector, name: GcRef<ObjString>) -> GcRef<Self> {
+		gc.new_object(Self {
+			obj: Obj::new::<Self>(),
+			name,
+			methods: GcMap::default(),
+			version: 0,
+			shape: Shape::root(),
+		})
+	}
+
+	pub fn methods(&self) -> &GcMap<GcRef<ObjString>, GcRef<ObjClosure>> {
+		&self.methods
+	}
+
+	pub fn method(&self, name: GcRef<ObjString>) -> Option<GcRef<ObjClosure>> {
+		self.methods.get(&name).copied()
+	}
+
+	pub fn add_method(
+		&mut self,
+		name: GcRef<ObjString>,
+		method: GcRef<ObjClosure>,
+	) {
+		self.methods.insert(name, method);
+		self.version = self.version.wrapping_add(1);
 	}
 }
 
@@ -22,4 +50,10 @@ impl Display for ObjClass {
 	}
 }
 
-impl Trace for ObjClass {}
+impl Trace for ObjClass {
+	fn trace(&self, marker: &mut Marker) {
+		self.name.trace(marker);
+		self.methods.trace(marker);
+		self.shape.trace(marker);
+	}
+}
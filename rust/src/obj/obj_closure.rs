@@ -9,11 +9,15 @@ pub struct ObjClosure {
 }
 
 impl ObjClosure {
-	pub fn new(function: GcRef<ObjFunction>) -> GcRef<Self> {
-		let mut closure = GC.with(|gc| gc.new_object::<Self>());
-		closure.function = function;
-		closure.upvalues = GcVec::default();
-		closure
+	pub fn new(
+		gc: &GarbageCollector,
+		function: GcRef<ObjFunction>,
+	) -> GcRef<Self> {
+		gc.new_object(Self {
+			obj: Obj::new::<Self>(),
+			function,
+			upvalues: GcVec::default(),
+		})
 	}
 }
 
@@ -23,4 +27,9 @@ impl Display for ObjClosure {
 	}
 }
 
-impl Trace for ObjClosure {}
+impl Trace for ObjClosure {
+	fn trace(&self, marker: &mut Marker) {
+		self.function.trace(marker);
+		self.upvalues.trace(marker);
+	}
+}
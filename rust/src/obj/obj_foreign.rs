@@ -0,0 +1,403 @@
+use std::any::Any;
+use std::any::TypeId;
+use std::fmt::Formatter;
+use std::marker::PhantomData;
+use std::rc::Rc;
+
+use fnv::FnvHashMap;
+
+use super::*;
+use crate::vm::Caller;
+use crate::vm::RuntimeError;
+
+/// A Rust value that scripts use like an instance. What they can do with it
+/// is up to its `ForeignClass`.
+#[repr(C)]
+pub struct ObjForeign {
+	pub(super) obj: Obj,
+
+	pub class: Rc<ForeignClass>,
+	pub value: Box<dyn Any + Send>,
+}
+
+/// The methods, property accessors and display of a Rust type exposed to
+/// Lox. Build one with `ForeignClass::builder` and register it with
+/// `Vm::register_foreign`. The type's `Trace` marks the Lox values it holds,
+/// so that collections keep them.
+pub struct ForeignClass {
+	pub name: String,
+	type_id:  TypeId,
+	/// One native per method, shared by every object of the class. They
+	/// take their receiver in the callee's slot, see `ObjNative::is_method`.
+	methods:  FnvHashMap<String, HeapBound<ObjNative>>,
+	getter:   Option<Box<ForeignGetter>>,
+	setter:   Option<Box<ForeignSetter>>,
+	display:  Option<Box<ForeignDisplay>>,
+	/// Marks what a Rust value refers to, with its type's `Trace`.
+	trace:    fn(&dyn Any, &mut Marker),
+}
+
+type ForeignMethod = dyn Fn(
+		&mut dyn Any,
+		&GarbageCollector,
+		&[Value],
+	) -> Result<Value, RuntimeError>
+	+ Send
+	+ Sync;
+type ForeignGetter =
+	dyn Fn(&dyn Any, &GarbageCollector, &str) -> Option<Value> + Send + Sync;
+type ForeignSetter =
+	dyn Fn(&mut dyn Any, &str, Value) -> Result<(), RuntimeError> + Send + Sync;
+type ForeignDisplay =
+	dyn Fn(&dyn Any, &mut Formatter) -> std::fmt::Result + Send + Sync;
+
+pub struct ForeignClassBuilder<T> {
+	class:   ForeignClass,
+	/// Allocated as natives by `build`.
+	methods: Vec<(String, Arity, Box<ForeignMethod>)>,
+	_type:   PhantomData<fn(T)>,
+}
+
+impl ObjForeign {
+	pub fn new<T: Trace + Send + 'static>(
+		gc: &GarbageCollector,
+		class: Rc<ForeignClass>,
+		value: T,
+	) -> GcRef<Self> {
+		assert!(
+			class.is::<T>(),
+			"foreign class {} is for another type",
+			class.name
+		);
+
+		gc.new_object(Self {
+			obj: Obj::new::<Self>(),
+			class,
+			value: Box::new(value),
+		})
+	}
+
+	pub fn downcast_ref<T: 'static>(&self) -> Option<&T> {
+		self.value.downcast_ref()
+	}
+
+	pub fn downcast_mut<T: 'static>(&mut self) -> Option<&mut T> {
+		self.value.downcast_mut()
+	}
+
+	/// Reads the property `name`: what the class's getter returns for it,
+	/// or else its method `name` bound to this object.
+	pub fn get(
+		gc: &GarbageCollector,
+		this: GcRef<Self>,
+		name: &str,
+	) -> Option<Value> {
+		let value = Self::get_unbound(gc, this, name)?;
+		match value.as_casted_obj::<ObjNative>() {
+			Some(native) if native.is_method => {
+				Some(ObjBoundMethod::new(gc, this.value(), value).value())
+			},
+			_ => Some(value),
+		}
+	}
+
+	/// Like `get`, but a method is its class's native as is, to be called
+	/// with this object in the callee's slot, as `Invoke` leaves it.
+	pub(crate) fn get_unbound(
+		gc: &GarbageCollector,
+		this: GcRef<Self>,
+		name: &str,
+	) -> Option<Value> {
+		let getter = this.class.getter.as_ref();
+		let value = getter.and_then(|get| get(&*this.value, gc, name));
+		value.or_else(|| Some(this.class.methods.get(name)?.get().value()))
+	}
+
+	/// Sets the property `name` through the class's setter.
+	pub fn set(
+		&mut self,
+		name: &str,
+		value: Value,
+	) -> Result<(), RuntimeError> {
+		match &self.class.setter {
+			Some(setter) => setter(&mut *self.value, name, value),
+			None => Err(RuntimeError::new(format!(
+				"Can't set properties on {} instances.",
+				self.class.name
+			))),
+		}
+	}
+}
+
+impl ForeignClass {
+	pub fn builder<T: Trace + Send + 'static>(
+		name: impl Into<String>,
+	) -> ForeignClassBuilder<T> {
+		ForeignClassBuilder {
+			class:   ForeignClass {
+				name:    name.into(),
+				type_id: TypeId::of::<T>(),
+				methods: FnvHashMap::default(),
+				getter:  None,
+				setter:  None,
+				display: None,
+				trace:   |value, marker| {
+					value.downcast_ref::<T>().unwrap().trace(marker);
+				},
+			},
+			methods: Vec::new(),
+			_type:   PhantomData,
+		}
+	}
+
+	/// Whether this class is for Rust values of type `T`.
+	pub fn is<T: 'static>(&self) -> bool {
+		self.type_id == TypeId::of::<T>()
+	}
+
+	pub fn type_id(&self) -> TypeId {
+		self.type_id
+	}
+}
+
+impl<T: Trace + Send + 'static> ForeignClassBuilder<T> {
+	/// Adds the method `name`, which gets the VM's heap along with the
+	/// object and the arguments. The VM checks each call's argument count
+	/// against `arity` before calling it.
+	///
+	/// The object is borrowed mutably for the whole call, and the arguments
+	/// may include it, as in `list.push(list)`. The method may store such an
+	/// argument, but mustn't read the object's value through it, with
+	/// `downcast_ref` or otherwise, as that would alias the borrow.
+	pub fn method(
+		mut self,
+		name: impl Into<String>,
+		arity: Arity,
+		method: impl Fn(
+				&mut T,
+				&GarbageCollector,
+				&[Value],
+			) -> Result<Value, RuntimeError>
+			+ Send
+			+ Sync
+			+ 'static,
+	) -> Self {
+		let method =
+			move |this: &mut dyn Any, gc: &GarbageCollector, args: &[Value]| {
+				method(this.downcast_mut().unwrap(), gc, args)
+			};
+		self.methods.push((name.into(), arity, Box::new(method)));
+		self
+	}
+
+	/// Answers property reads, with the VM's heap to allocate what they
+	/// return. Returning `None` falls back to the method of that name.
+	pub fn getter(
+		mut self,
+		getter: impl Fn(&T, &GarbageCollector, &str) -> Option<Value>
+			+ Send
+			+ Sync
+			+ 'static,
+	) -> Self {
+		self.class.getter = Some(Box::new(move |this, gc, name| {
+			getter(this.downcast_ref().unwrap(), gc, name)
+		}));
+		self
+	}
+
+	/// Answers property writes. Without one, scripts can't set properties.
+	/// Like a method's arguments, the value written may be the object itself.
+	pub fn setter(
+		mut self,
+		setter: impl Fn(&mut T, &str, Value) -> Result<(), RuntimeError>
+			+ Send
+			+ Sync
+			+ 'static,
+	) -> Self {
+		self.class.setter = Some(Box::new(move |this, name, value| {
+			setter(this.downcast_mut().unwrap(), name, value)
+		}));
+		self
+	}
+
+	/// How `print` shows objects of this class, instead of
+	/// `<name> instance`.
+	pub fn display(
+		mut self,
+		display: impl Fn(&T, &mut Formatter) -> std::fmt::Result
+			+ Send
+			+ Sync
+			+ 'static,
+	) -> Self {
+		self.class.display = Some(Box::new(move |this, f| {
+			display(this.downcast_ref().unwrap(), f)
+		}));
+		self
+	}
+
+	/// Finishes the class, allocating its methods on `gc`, which has to be
+	/// the heap its objects are on.
+	pub fn build(mut self, gc: &GarbageCollector) -> ForeignClass {
+		for (name, arity, method) in self.methods {
+			// only ever called on objects of this class. The arguments may
+			// point at `this` too, see `method`.
+			let function = move |caller: &mut dyn Caller, args: &[Value]| {
+				let mut this = args[0].as_casted_obj::<ObjForeign>().unwrap();
+				method(&mut *this.value, caller.gc(), &args[1..])
+			};
+			let native_name = ObjString::new(gc, name.as_str());
+			let mut native =
+				ObjNative::new(gc, native_name, arity, Box::new(function));
+			native.is_method = true;
+			// SAFETY: the class and its objects stay on the native's heap
+			let native = unsafe { HeapBound::new(native) };
+			self.class.methods.insert(name, native);
+		}
+		self.class
+	}
+}
+
+impl Display for ObjForeign {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		match &self.class.display {
+			Some(display) => display(&*self.value, f),
+			None => write!(f, "{} instance", self.class.name),
+		}
+	}
+}
+
+impl Trace for ObjForeign {
+	fn trace(&self, marker: &mut Marker) {
+		(self.class.trace)(&*self.value, marker);
+		self.class.trace(marker);
+	}
+}
+
+impl Trace for ForeignClass {
+	fn trace(&self, marker: &mut Marker) {
+		for native in self.methods.values() {
+			native.get().trace(marker);
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::vm::OutputBuffer;
+	use crate::vm::Vm;
+
+	const MAX_FRAMES: usize = 64;
+	const STACK_SIZE: usize = MAX_FRAMES * (u8::MAX as usize + 1);
+
+	struct Counter {
+		count: f64,
+		label: Value,
+	}
+
+	// SAFETY: the label is on the heap of the VM that owns the counter
+	unsafe impl Send for Counter {}
+
+	impl Trace for Counter {
+		fn trace(&self, marker: &mut Marker) {
+			self.label.trace(marker);
+		}
+	}
+
+	struct Opaque;
+
+	impl Trace for Opaque {}
+
+	fn counter_class() -> ForeignClassBuilder<Counter> {
+		ForeignClass::builder("Counter")
+			.method("add", Arity::Exact(1), |counter: &mut Counter, _, args| {
+				counter.count += args[0].as_number().unwrap_or(0.0);
+				Ok(Value::Number(counter.count))
+			})
+			.getter(|counter, _, name| {
+				(name == "count").then_some(Value::Number(counter.count))
+			})
+			.setter(|counter, name, value| {
+				if name != "label" {
+					let message = format!("Counters have no '{name}'.");
+					return Err(RuntimeError::new(message));
+				}
+				counter.label = value;
+				Ok(())
+			})
+			.display(|counter, f| {
+				write!(f, "{}: {}", counter.label, counter.count)
+			})
+	}
+
+	fn vm() -> (Vm<MAX_FRAMES, STACK_SIZE>, OutputBuffer) {
+		let mut vm = Vm::new();
+		let output = OutputBuffer::default();
+		vm.set_output(output.clone());
+		vm.register_foreign(counter_class());
+		vm.register_foreign(ForeignClass::builder::<Opaque>("Opaque"));
+		(vm, output)
+	}
+
+	#[test]
+	fn scripts_call_methods_and_use_properties() {
+		let (mut vm, output) = vm();
+		let counter = Counter {
+			count: 0.0,
+			label: Value::Nil(),
+		};
+		vm.define_foreign("counter", counter).unwrap();
+		vm.interpret(
+			"fun use(counter) {
+				counter.add(2);
+				var add = counter.add;
+				add(3);
+				counter.label = \"hi\" + \"ts\";
+				print counter;
+				return counter.count;
+			}",
+		)
+		.unwrap();
+
+		let count: f64 = vm.evaluate("use(counter)").unwrap();
+		assert_eq!(count, 5.0);
+		assert_eq!(output.take(), "hits: 5\n");
+
+		// the label is only reachable through the Rust value, which the
+		// global keeps alive
+		vm.collect_garbage();
+		vm.call::<f64>(("counter", "add"), [1.0]).unwrap();
+		let mut globals = vm.globals().iter();
+		let (_, counter) = globals
+			.find(|(name, _)| name.as_str() == "counter")
+			.unwrap();
+		let foreign = counter.as_casted_obj::<ObjForeign>().unwrap();
+		let counter = foreign.downcast_ref::<Counter>().unwrap();
+		assert_eq!(counter.count, 6.0);
+		assert_eq!(counter.label.to_string(), "hits");
+	}
+
+	#[test]
+	fn errors_name_the_property() {
+		let (mut vm, output) = vm();
+		let counter = Counter {
+			count: 0.0,
+			label: Value::Nil(),
+		};
+		vm.define_foreign("counter", counter).unwrap();
+		vm.define_foreign("opaque", Opaque).unwrap();
+
+		let mut error = |source| {
+			let res = vm.evaluate::<Option<f64>>(source);
+			res.err().unwrap().to_string()
+		};
+		assert!(error("counter.size").contains("Undefined property 'size'."));
+		assert!(error("counter.size = 1").contains("Counters have no 'size'."));
+		assert!(error("opaque.size = 1")
+			.contains("Can't set properties on Opaque instances."));
+		assert!(error("counter.add()").contains("Expected 1 argument"));
+		vm.interpret("print opaque;").unwrap();
+		assert_eq!(output.take(), "Opaque instance\n");
+		assert!(vm.define_foreign("byte", 1u8).is_err());
+	}
+}
@@ -8,16 +8,40 @@ pub struct ObjFunction {
 	pub upvalue_count: usize,
 	pub chunk:         Chunk,
 	pub name:          Option<GcRef<ObjString>>,
+
+	// debug info
+	pub locals:        GcVec<LocalInfo>,
+	pub upvalue_names: GcVec<GcRef<ObjString>>,
+}
+
+/// Where a local variable lives while it's in scope, for debuggers.
+pub struct LocalInfo {
+	pub name:  GcRef<ObjString>,
+	pub slot:  u8,
+	/// Offset of the first instruction at which the local is initialized.
+	pub start: usize,
+	/// Offset of the first instruction after the local's scope ends, or
+	/// `usize::MAX` if it's in scope until the function returns.
+	pub end:   usize,
+}
+
+impl LocalInfo {
+	pub fn in_scope(&self, offset: usize) -> bool {
+		(self.start..self.end).contains(&offset)
+	}
 }
 
 impl ObjFunction {
-	pub fn new() -> GcRef<Self> {
-		let mut function = GC.with(|gc| gc.new_object::<Self>());
-		function.arity = 0;
-		function.upvalue_count = 0;
-		function.name = None;
-		function.chunk = Chunk::default();
-		function
+	pub fn new(gc: &GarbageCollector) -> GcRef<Self> {
+		gc.new_object(Self {
+			obj:           Obj::new::<Self>(),
+			arity:         0,
+			upvalue_count: 0,
+			chunk:         Chunk::default(),
+			name:          None,
+			locals:        GcVec::default(),
+			upvalue_names: GcVec::default(),
+		})
 	}
 }
 
@@ -34,4 +58,17 @@ impl Display for ObjFunction {
 	}
 }
 
-impl Trace for ObjFunction {}
+impl Trace for ObjFunction {
+	fn trace(&self, marker: &mut Marker) {
+		self.name.trace(marker);
+		self.chunk.trace(marker);
+		self.locals.trace(marker);
+		self.upvalue_names.trace(marker);
+	}
+}
+
+impl Trace for LocalInfo {
+	fn trace(&self, marker: &mut Marker) {
+		self.name.trace(marker);
+	}
+}
@@ -1,19 +1,126 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+use std::rc::Weak;
+
+use fnv::FnvHashMap;
+
 use super::*;
 
 #[repr(C)]
 pub struct ObjInstance {
 	pub(super) obj: Obj,
 
-	pub klass:  GcRef<ObjClass>,
-	pub fields: GcMap<GcRef<ObjString>, Value>,
+	pub klass:        GcRef<ObjClass>,
+	/// Names the fields in `slots`.
+	pub(crate) shape: Rc<Shape>,
+	/// The values of the fields.
+	pub(crate) slots: GcVec<Value>,
+}
+
+/// The names and order of an instance's fields. Instances of a class that
+/// gain the same fields in the same order share a shape, so inline caches
+/// can remember where a field is by its shape instead of looking it up.
+pub struct Shape {
+	parent:      Option<Rc<Shape>>,
+	/// The field this shape adds to its parent's, in the last slot.
+	name:        Option<GcRef<ObjString>>,
+	field_count: usize,
+	/// Shapes with one more field, held weakly so that shapes no instance
+	/// uses anymore are dropped.
+	transitions: RefCell<FnvHashMap<GcRef<ObjString>, Weak<Shape>>>,
 }
 
 impl ObjInstance {
-	pub fn new(klass: GcRef<ObjClass>) -> GcRef<Self> {
-		let mut instance = GC.with(|gc| gc.new_object::<Self>());
-		instance.klass = klass;
-		instance.fields = GcMap::default();
-		instance
+	pub fn new(gc: &GarbageCollector, klass: GcRef<ObjClass>) -> GcRef<Self> {
+		gc.new_object(Self {
+			obj: Obj::new::<Self>(),
+			klass,
+			shape: klass.shape.clone(),
+			slots: GcVec::default(),
+		})
+	}
+
+	pub fn field(&self, name: GcRef<ObjString>) -> Option<Value> {
+		self.shape.slot(name).map(|slot| self.slots[slot])
+	}
+
+	pub fn set_field(&mut self, name: GcRef<ObjString>, value: Value) {
+		match self.shape.slot(name) {
+			Some(slot) => self.slots[slot] = value,
+			None => {
+				self.shape = self.shape.with(name);
+				self.slots.push(value);
+			},
+		}
+	}
+
+	/// Every field's name and value, in the order they were added.
+	pub fn fields(
+		&self,
+	) -> impl Iterator<Item = (GcRef<ObjString>, Value)> + '_ {
+		self.shape
+			.names()
+			.into_iter()
+			.zip(self.slots.iter().copied())
+	}
+}
+
+impl Shape {
+	/// The shape of instances without fields.
+	pub(crate) fn root() -> Rc<Self> {
+		Rc::new(Self {
+			parent:      None,
+			name:        None,
+			field_count: 0,
+			transitions: RefCell::default(),
+		})
+	}
+
+	pub fn field_count(&self) -> usize {
+		self.field_count
+	}
+
+	/// Where the field `name` is stored, if instances of this shape have it.
+	pub fn slot(&self, name: GcRef<ObjString>) -> Option<usize> {
+		let mut shape = self;
+		loop {
+			if shape.name? == name {
+				return Some(shape.field_count - 1);
+			}
+			shape = shape.parent.as_deref()?;
+		}
+	}
+
+	/// The names of the fields, by slot.
+	pub fn names(&self) -> Vec<GcRef<ObjString>> {
+		let mut names = Vec::with_capacity(self.field_count);
+		let mut shape = self;
+		while let Some(name) = shape.name {
+			names.push(name);
+			let Some(parent) = shape.parent.as_deref() else {
+				break;
+			};
+			shape = parent;
+		}
+		names.reverse();
+		names
+	}
+
+	/// This shape with the field `name` added in a new slot.
+	pub(crate) fn with(self: &Rc<Self>, name: GcRef<ObjString>) -> Rc<Self> {
+		let mut transitions = self.transitions.borrow_mut();
+		if let Some(shape) = transitions.get(&name).and_then(Weak::upgrade) {
+			return shape;
+		}
+
+		let shape = Rc::new(Self {
+			parent:      Some(self.clone()),
+			name:        Some(name),
+			field_count: self.field_count + 1,
+			transitions: RefCell::default(),
+		});
+		transitions.insert(name, Rc::downgrade(&shape));
+		shape
 	}
 }
 
@@ -24,4 +131,25 @@ impl Display for ObjInstance {
 	}
 }
 
-impl Trace for ObjInstance {}
+impl Trace for ObjInstance {
+	fn trace(&self, marker: &mut Marker) {
+		self.klass.trace(marker);
+		self.shape.trace(marker);
+		self.slots.trace(marker);
+	}
+}
+
+/// Marks the names of the fields, and of the fields that transitions add,
+/// which the transition tables compare names to.
+impl Trace for Shape {
+	fn trace(&self, marker: &mut Marker) {
+		let mut shape = Some(self);
+		while let Some(current) = shape {
+			current.name.trace(marker);
+			current.transitions.borrow().keys().for_each(|name| {
+				name.trace(marker);
+			});
+			shape = current.parent.as_deref();
+		}
+	}
+}
@@ -1,19 +1,71 @@
 use super::*;
+use crate::vm::Caller;
+use crate::vm::RuntimeError;
 
-type NativeFn = fn(args: &[Value]) -> Value;
+/// The host function behind a native. It gets the VM calling it, to allocate
+/// the strings and objects it returns or to call back into the program, and
+/// the call's arguments. It may keep state between calls, though values it
+/// keeps aren't traced. It's `Send` so that its VM can move between threads.
+pub type NativeFn = Box<
+	dyn FnMut(&mut dyn Caller, &[Value]) -> Result<Value, RuntimeError>
+		+ Send,
+>;
 
 #[repr(C)]
 pub struct ObjNative {
 	pub(super) obj: Obj,
 
-	pub function: NativeFn,
+	pub name:      GcRef<ObjString>,
+	pub arity:     Arity,
+	pub function:  NativeFn,
+	/// Whether this is a foreign class's method, which gets its receiver as
+	/// the first argument before the `arity` others. It's only called bound
+	/// to a receiver, or by `Invoke` with the receiver in the callee's slot.
+	pub is_method: bool,
+}
+
+/// How many arguments a native takes.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Arity {
+	Exact(u8),
+	/// Variadic, with at least this many arguments.
+	AtLeast(u8),
 }
 
 impl ObjNative {
-	pub fn new(function: NativeFn) -> GcRef<Self> {
-		let mut native = GC.with(|gc| gc.new_object::<Self>());
-		native.function = function;
-		native
+	pub fn new(
+		gc: &GarbageCollector,
+		name: GcRef<ObjString>,
+		arity: Arity,
+		function: NativeFn,
+	) -> GcRef<Self> {
+		gc.new_object(Self {
+			obj: Obj::new::<Self>(),
+			name,
+			arity,
+			function,
+			is_method: false,
+		})
+	}
+}
+
+impl Arity {
+	pub fn accepts(self, arg_count: u8) -> bool {
+		match self {
+			Arity::Exact(arity) => arg_count == arity,
+			Arity::AtLeast(arity) => arg_count >= arity,
+		}
+	}
+}
+
+impl Display for Arity {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		let (prefix, arity) = match *self {
+			Arity::Exact(arity) => ("", arity),
+			Arity::AtLeast(arity) => ("at least ", arity),
+		};
+		let plural = if arity == 1 { "" } else { "s" };
+		write!(f, "{prefix}{arity} argument{plural}")
 	}
 }
 
@@ -23,4 +75,8 @@ impl Display for ObjNative {
 	}
 }
 
-impl Trace for ObjNative {}
+impl Trace for ObjNative {
+	fn trace(&self, marker: &mut Marker) {
+		self.name.trace(marker);
+	}
+}
@@ -1,47 +1,270 @@
 use std::borrow::Borrow;
+use std::cell::Cell;
+use std::cell::UnsafeCell;
+use std::hash::BuildHasher;
+
+use fnv::FnvBuildHasher;
 
 use super::*;
+use crate::mem::find_interned;
+use crate::mem::StringTable;
+use crate::vm::RuntimeError;
 
 #[repr(C)]
 pub struct ObjString {
 	pub(super) obj: Obj,
 
-	pub text: &'static str,
-	pub hash: usize,
+	/// Only ever changes from a rope to its flattened text, see `flatten`.
+	text:     UnsafeCell<Text>,
+	hash:     Cell<usize>,
+	/// Whether this is the intern table's string for its contents, so that
+	/// comparing it to another interned string only takes their addresses.
+	interned: Cell<bool>,
+}
+
+/// Strings up to this many bytes are stored in the object itself.
+const INLINE_LEN: usize = 22;
+
+/// The longest a string can get, in bytes. Ropes make long strings cheap to
+/// build, so without a limit their length would overflow before the heap
+/// ran out.
+pub const MAX_LEN: usize = u32::MAX as usize;
+
+/// A string's bytes, owned by its object so they're freed along with it.
+enum Text {
+	/// Short strings, which then need no allocation of their own.
+	Inline {
+		len:   u8,
+		bytes: [u8; INLINE_LEN],
+	},
+	Heap(Box<str>),
+	/// A concatenation, not copied until its text is first needed.
+	Rope {
+		left:    GcRef<ObjString>,
+		right:   GcRef<ObjString>,
+		len:     usize,
+		/// The intern table of the rope's heap, to add the text to once
+		/// it's flattened.
+		strings: NonNull<StringTable>,
+	},
+	/// A flattened rope whose text was already interned in this string.
+	Forward(GcRef<ObjString>),
 }
 
 impl ObjString {
-	pub fn new(text: impl Borrow<str> + Into<String>) -> GcRef<Self> {
-		GC.with(|gc| gc.intern_string(text))
+	pub fn new(
+		gc: &GarbageCollector,
+		text: impl Borrow<str> + Into<String>,
+	) -> GcRef<Self> {
+		gc.intern_string(text)
 	}
 
+	/// `left` followed by `right`. Unless the result is short, it's a rope
+	/// that copies neither, so building a string piece by piece stays
+	/// linear. Ropes are only interned once they're flattened.
 	pub fn concat(
+		gc: &GarbageCollector,
+		left: GcRef<ObjString>,
+		right: GcRef<ObjString>,
+	) -> Result<GcRef<ObjString>, RuntimeError> {
+		if left.is_empty() {
+			return Ok(right);
+		}
+		if right.is_empty() {
+			return Ok(left);
+		}
+		let len = left.len().checked_add(right.len());
+		let Some(len) = len.filter(|len| *len <= MAX_LEN) else {
+			return Err(RuntimeError::new("String too long."));
+		};
+		if len > INLINE_LEN {
+			return Ok(gc.new_rope(left, right));
+		}
+
+		let (left, right) = (left.as_str(), right.as_str());
+		let mut data = String::with_capacity(len);
+		data += left;
+		data += right;
+		Ok(Self::new(gc, data))
+	}
+
+	/// The hash of a string with contents `text`.
+	pub fn hash_str(text: &str) -> usize {
+		FnvBuildHasher::default().hash_one(text) as usize
+	}
+
+	pub fn hash(&self) -> usize {
+		self.flatten();
+		self.hash.get()
+	}
+
+	pub fn as_str(&self) -> &str {
+		self.flatten();
+		// SAFETY: the text is flat, and flat text is never replaced
+		match unsafe { &*self.text.get() } {
+			// SAFETY: the bytes were copied from a `str` in `flat`
+			Text::Inline { len, bytes } => unsafe {
+				std::str::from_utf8_unchecked(&bytes[..*len as usize])
+			},
+			Text::Heap(text) => text,
+			Text::Forward(interned) => interned.as_str(),
+			Text::Rope { .. } => unreachable!(),
+		}
+	}
+
+	/// Length in bytes, without flattening.
+	pub fn len(&self) -> usize {
+		// SAFETY: nothing else borrows the text while this reads it
+		match unsafe { &*self.text.get() } {
+			Text::Inline { len, .. } => *len as usize,
+			Text::Heap(text) => text.len(),
+			Text::Rope { len, .. } => *len,
+			Text::Forward(interned) => interned.len(),
+		}
+	}
+
+	pub fn is_empty(&self) -> bool {
+		self.len() == 0
+	}
+
+	/// Bytes of text stored outside the object, or that will be once a rope
+	/// is flattened.
+	pub fn heap_size(&self) -> usize {
+		// SAFETY: nothing else borrows the text while this reads it
+		match unsafe { &*self.text.get() } {
+			Text::Inline { .. } | Text::Forward(_) => 0,
+			Text::Heap(text) => text.len(),
+			Text::Rope { len, .. } => *len,
+		}
+	}
+
+	/// A string with contents `text`, to be interned.
+	pub(crate) fn flat(
+		hash: usize,
+		text: impl Borrow<str> + Into<String>,
+	) -> Self {
+		let as_str: &str = text.borrow();
+		let text = if as_str.len() <= INLINE_LEN {
+			let mut bytes = [0; INLINE_LEN];
+			bytes[..as_str.len()].copy_from_slice(as_str.as_bytes());
+			Text::Inline {
+				len: as_str.len() as u8,
+				bytes,
+			}
+		} else {
+			Text::Heap(text.into().into_boxed_str())
+		};
+
+		Self {
+			obj:      Obj::new::<Self>(),
+			text:     UnsafeCell::new(text),
+			hash:     Cell::new(hash),
+			interned: Cell::new(true),
+		}
+	}
+
+	/// The rope of `left` and `right`, to be interned in `strings` once it's
+	/// flattened.
+	pub(crate) fn rope(
 		left: GcRef<ObjString>,
 		right: GcRef<ObjString>,
-	) -> GcRef<ObjString> {
-		let mut data =
-			String::with_capacity(left.text.len() + right.text.len());
-		data += left.text;
-		data += right.text;
-		Self::new(data)
+		strings: NonNull<StringTable>,
+	) -> Self {
+		let len = left.len() + right.len();
+		let text = Text::Rope {
+			left,
+			right,
+			len,
+			strings,
+		};
+		Self {
+			obj:      Obj::new::<Self>(),
+			text:     UnsafeCell::new(text),
+			hash:     Cell::new(0),
+			interned: Cell::new(false),
+		}
+	}
+
+	/// Copies a rope's pieces into one buffer, replacing the rope, and
+	/// interns the result. Ropes nest as deep as a script's loop runs, so
+	/// this walks them without recursing.
+	fn flatten(&self) {
+		// SAFETY: nothing else borrows the text while this reads it
+		let Text::Rope {
+			left,
+			right,
+			len,
+			strings,
+		} = *(unsafe { &*self.text.get() })
+		else {
+			return;
+		};
+
+		let mut text = String::with_capacity(len);
+		let mut pending = vec![right, left];
+		while let Some(piece) = pending.pop() {
+			// SAFETY: as above, and only ropes are read, which are never
+			// flattened in here
+			match unsafe { &*piece.text.get() } {
+				Text::Rope { left, right, .. } => {
+					pending.extend([*right, *left]);
+				},
+				_ => text += piece.as_str(),
+			}
+		}
+
+		let hash = Self::hash_str(&text);
+		self.hash.set(hash);
+		// SAFETY: a rope's heap boxes its intern table, and outlives the rope
+		let strings = unsafe { strings.as_ref() };
+		let interned = find_interned(strings, hash, &text);
+		let flat = match interned {
+			Some(interned) => Text::Forward(interned),
+			None => Text::Heap(text.into_boxed_str()),
+		};
+		// SAFETY: a rope only holds references to other objects, so no
+		// borrow of the old text outlives its replacement
+		unsafe { *self.text.get() = flat };
+
+		if interned.is_none() {
+			// only now that it's flat, as the table hashes its strings
+			self.interned.set(true);
+			strings.borrow_mut().insert(GcRef::from_ref(self), ());
+		}
+	}
+
+	/// The interned string with this one's contents, which is itself unless
+	/// it's a rope whose text was already interned.
+	fn canonical(&self) -> &ObjString {
+		self.flatten();
+		// SAFETY: the text is flat, and flat text is never replaced
+		match unsafe { &*self.text.get() } {
+			Text::Forward(interned) => interned,
+			_ => self,
+		}
 	}
 }
 
 impl Borrow<str> for GcRef<ObjString> {
 	fn borrow(&self) -> &str {
-		&self.text
+		self.as_str()
 	}
 }
 
 impl Display for ObjString {
 	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-		self.text.fmt(f)
+		self.as_str().fmt(f)
 	}
 }
 
 impl PartialEq for ObjString {
 	fn eq(&self, other: &Self) -> bool {
-		self.hash == other.hash
+		let (this, other) = (self.canonical(), other.canonical());
+		if this.interned.get() && other.interned.get() {
+			return std::ptr::eq(this, other);
+		}
+		// equal hashes may still be a collision
+		this.hash() == other.hash() && this.as_str() == other.as_str()
 	}
 }
 
@@ -49,8 +272,164 @@ impl Eq for ObjString {}
 
 impl Hash for ObjString {
 	fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
-		state.write_usize(self.hash);
+		state.write_usize(self.hash());
 	}
 }
 
-impl Trace for ObjString {}
+impl Trace for ObjString {
+	fn trace(&self, marker: &mut Marker) {
+		// SAFETY: nothing else borrows the text while this reads it
+		match unsafe { &*self.text.get() } {
+			Text::Rope { left, right, .. } => {
+				left.trace(marker);
+				right.trace(marker);
+			},
+			Text::Forward(interned) => interned.trace(marker),
+			Text::Inline { .. } | Text::Heap(_) => {},
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	/// Whether `a` and `b` are the same object.
+	fn same(a: GcRef<ObjString>, b: GcRef<ObjString>) -> bool {
+		std::ptr::eq(a.as_ptr(), b.as_ptr())
+	}
+
+	#[test]
+	fn short_text_is_stored_inline() {
+		let gc = GarbageCollector::default();
+		let inline = "x".repeat(INLINE_LEN);
+		let heap = "x".repeat(INLINE_LEN + 1);
+
+		let before = gc.bytes_allocated();
+		let string = ObjString::new(&gc, inline.as_str());
+		assert_eq!(string.as_str(), inline);
+		assert_eq!(string.heap_size(), 0);
+		let size = std::mem::size_of::<ObjString>();
+		assert_eq!(gc.bytes_allocated() - before, size);
+
+		let before = gc.bytes_allocated();
+		let string = ObjString::new(&gc, heap.as_str());
+		assert_eq!(string.as_str(), heap);
+		assert_eq!(string.heap_size(), INLINE_LEN + 1);
+		assert_eq!(gc.bytes_allocated() - before, size + INLINE_LEN + 1);
+	}
+
+	#[test]
+	fn text_keeps_multibyte_characters() {
+		let gc = GarbageCollector::default();
+		let inline = "ünïcödé ✓ ok";
+		let heap = "ünïcödé ✓ ok, and more";
+		assert!(inline.len() <= INLINE_LEN && heap.len() > INLINE_LEN);
+		for text in [inline, heap] {
+			let string = ObjString::new(&gc, text);
+			assert_eq!(string.as_str(), text);
+			assert_eq!(string.len(), text.len());
+			assert_eq!(ObjString::hash(&string), ObjString::hash_str(text));
+		}
+		assert!(ObjString::new(&gc, "").is_empty());
+	}
+
+	#[test]
+	fn equal_text_is_interned_once() {
+		let gc = GarbageCollector::default();
+		let text = "a string too long to be stored inline";
+		let borrowed = ObjString::new(&gc, text);
+		let before = gc.bytes_allocated();
+		let owned = ObjString::new(&gc, text.to_owned());
+		assert!(same(borrowed, owned));
+		assert_eq!(gc.bytes_allocated(), before);
+
+		let stats = gc.collect(|_| {});
+		assert_eq!((stats.freed, stats.interned), (1, 0));
+		let fresh = ObjString::new(&gc, text);
+		assert_eq!(fresh.as_str(), text);
+	}
+
+	fn is_rope(string: GcRef<ObjString>) -> bool {
+		// SAFETY: nothing else borrows the text while this reads it
+		matches!(unsafe { &*string.text.get() }, Text::Rope { .. })
+	}
+
+	#[test]
+	fn short_concatenations_are_copied() {
+		let gc = GarbageCollector::default();
+		let left = ObjString::new(&gc, "con");
+		let right = ObjString::new(&gc, "cat");
+		let string = ObjString::concat(&gc, left, right).unwrap();
+		assert!(!is_rope(string));
+		assert!(same(string, ObjString::new(&gc, "concat")));
+
+		let empty = ObjString::new(&gc, "");
+		assert!(same(ObjString::concat(&gc, empty, left).unwrap(), left));
+		assert!(same(ObjString::concat(&gc, left, empty).unwrap(), left));
+	}
+
+	#[test]
+	fn ropes_flatten_when_read_and_intern_themselves() {
+		let gc = GarbageCollector::default();
+		let left = ObjString::new(&gc, "a string too long to be inline, ");
+		let right = ObjString::new(&gc, "and another one");
+		let rope = ObjString::concat(&gc, left, right).unwrap();
+		let text = "a string too long to be inline, and another one";
+		assert!(is_rope(rope));
+		assert_eq!(rope.len(), text.len());
+		assert_eq!(rope.heap_size(), text.len());
+
+		assert_eq!(rope.as_str(), text);
+		assert!(!is_rope(rope));
+		assert_eq!(ObjString::hash(&rope), ObjString::hash_str(text));
+		assert!(same(ObjString::new(&gc, text), rope));
+	}
+
+	#[test]
+	fn ropes_of_interned_text_forward_to_it() {
+		let gc = GarbageCollector::default();
+		let text = "a string too long to be inline, and another one";
+		let interned = ObjString::new(&gc, text);
+		let left = ObjString::new(&gc, "a string too long to be inline, ");
+		let right = ObjString::new(&gc, "and another one");
+		let rope = ObjString::concat(&gc, left, right).unwrap();
+
+		assert!(*rope == *interned);
+		assert_eq!(rope.heap_size(), 0);
+		assert!(same(ObjString::new(&gc, text), interned));
+
+		// the rope keeps the string it forwards to alive
+		let stats = gc.collect(|marker| rope.trace(marker));
+		assert_eq!(stats.freed, 2);
+		assert_eq!(rope.as_str(), text);
+	}
+
+	#[test]
+	fn deep_ropes_flatten_without_recursing() {
+		let gc = GarbageCollector::default();
+		let piece = ObjString::new(&gc, "piece ");
+		let mut string = ObjString::new(&gc, "");
+		for _ in 0..100_000 {
+			string = ObjString::concat(&gc, string, piece).unwrap();
+		}
+		assert_eq!(string.len(), 600_000);
+		assert!(string.as_str().starts_with("piece piece "));
+		assert!(string.as_str().ends_with("piece "));
+	}
+
+	#[test]
+	fn concatenations_past_the_longest_string_fail() {
+		let gc = GarbageCollector::default();
+		let mut string = ObjString::new(&gc, "a string too long to be inline");
+		let err = loop {
+			match ObjString::concat(&gc, string, string) {
+				Ok(doubled) => string = doubled,
+				Err(err) => break err,
+			}
+		};
+		assert_eq!(err.to_string(), "String too long.");
+		assert!(string.len() <= MAX_LEN);
+		assert!(string.len() * 2 > MAX_LEN);
+	}
+}
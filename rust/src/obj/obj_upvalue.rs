@@ -10,12 +10,13 @@ pub struct ObjUpvalue {
 }
 
 impl ObjUpvalue {
-	pub fn new(slot: NonNull<Value>) -> GcRef<Self> {
-		let mut upvalue = GC.with(|gc| gc.new_object::<Self>());
-		upvalue.location = slot;
-		upvalue.closed = Value::Nil();
-		upvalue.next = None;
-		upvalue
+	pub fn new(gc: &GarbageCollector, slot: NonNull<Value>) -> GcRef<Self> {
+		gc.new_object(Self {
+			obj:      Obj::new::<Self>(),
+			location: slot,
+			closed:   Value::Nil(),
+			next:     None,
+		})
 	}
 }
 
@@ -25,4 +26,10 @@ impl Display for ObjUpvalue {
 	}
 }
 
-impl Trace for ObjUpvalue {}
+impl Trace for ObjUpvalue {
+	/// An open upvalue's variable is on the stack, which is traced as a
+	/// root.
+	fn trace(&self, marker: &mut Marker) {
+		self.closed.trace(marker);
+	}
+}
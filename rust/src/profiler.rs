@@ -0,0 +1,298 @@
+use std::io::Write;
+use std::time::Duration;
+use std::time::Instant;
+
+use fnv::FnvHashMap;
+
+use crate::chunk::Op;
+use crate::mem::GcRef;
+use crate::obj::ObjFunction;
+use crate::vm::Context;
+use crate::vm::Hook;
+
+/// `Print` is the last op.
+const OP_COUNT: usize = Op::Print as usize + 1;
+
+/// Records where a program spends its time. Attach one to a VM with
+/// `Vm::add_hook`, through an `Arc<Mutex<_>>` to read the results once the
+/// program has finished.
+///
+/// Time is split between functions by call frame, so time spent in natives is
+/// counted as self time of the function that called them.
+pub struct Profiler {
+	functions: Vec<FunctionStats>,
+	/// Ids by the address of their function.
+	ids:       FnvHashMap<usize, usize>,
+	/// The function of each active call, outermost first.
+	stack:     Vec<usize>,
+	entered:   Vec<Instant>,
+	/// Self time of each distinct stack of calls, by function id.
+	stacks:    FnvHashMap<Vec<usize>, Duration>,
+	ops:       [Option<(Op, u64)>; OP_COUNT],
+	last:      Option<Instant>,
+}
+
+/// What one function cost over the whole run.
+#[derive(Clone, Debug)]
+pub struct FunctionStats {
+	pub name:       String,
+	pub calls:      u64,
+	/// Time spent running the function's own instructions.
+	pub self_time:  Duration,
+	/// Time from entering the function until it returned, including its
+	/// callees. Recursive calls are only counted once.
+	pub total_time: Duration,
+	active:         u32,
+}
+
+impl Profiler {
+	pub fn new() -> Self {
+		Self {
+			functions: Vec::new(),
+			ids:       FnvHashMap::default(),
+			stack:     Vec::new(),
+			entered:   Vec::new(),
+			stacks:    FnvHashMap::default(),
+			ops:       [None; OP_COUNT],
+			last:      None,
+		}
+	}
+
+	/// Every function that was called, most self time first.
+	pub fn functions(&self) -> Vec<&FunctionStats> {
+		let mut functions = self.functions.iter().collect::<Vec<_>>();
+		functions.sort_by_key(|function| std::cmp::Reverse(function.self_time));
+		functions
+	}
+
+	/// How often each op ran, most frequent first.
+	pub fn ops(&self) -> Vec<(Op, u64)> {
+		let mut ops = self.ops.iter().flatten().copied().collect::<Vec<_>>();
+		ops.sort_by(|(_, a), (_, b)| b.cmp(a));
+		ops
+	}
+
+	/// Writes a table of the functions and ops.
+	pub fn write_summary(&self, out: &mut impl Write) -> std::io::Result<()> {
+		let total = self
+			.functions
+			.iter()
+			.map(|function| function.self_time)
+			.sum::<Duration>()
+			.as_secs_f64()
+			.max(f64::MIN_POSITIVE);
+
+		writeln!(
+			out,
+			"{:<24} {:>10} {:>12} {:>7} {:>12}",
+			"function", "calls", "self (ms)", "self %", "total (ms)"
+		)?;
+		for function in self.functions() {
+			let self_time = function.self_time.as_secs_f64();
+			writeln!(
+				out,
+				"{:<24} {:>10} {:>12.3} {:>6.1}% {:>12.3}",
+				function.name,
+				function.calls,
+				self_time * 1000.0,
+				self_time / total * 100.0,
+				function.total_time.as_secs_f64() * 1000.0,
+			)?;
+		}
+
+		writeln!(out)?;
+		writeln!(out, "{:<24} {:>10}", "op", "count")?;
+		for (op, count) in self.ops() {
+			writeln!(out, "{:<24} {:>10}", format!("{op:?}"), count)?;
+		}
+		Ok(())
+	}
+
+	/// Writes one line per distinct stack of calls, with the stack's self
+	/// time in microseconds, as read by flamegraph tools:
+	///
+	/// ```text
+	/// script;main;fib 1234
+	/// ```
+	pub fn write_folded(&self, out: &mut impl Write) -> std::io::Result<()> {
+		let mut stacks = self
+			.stacks
+			.iter()
+			.map(|(stack, time)| {
+				let names = stack
+					.iter()
+					.map(|id| self.functions[*id].name.as_str())
+					.collect::<Vec<_>>();
+				(names.join(";"), time.as_micros())
+			})
+			.collect::<Vec<_>>();
+		stacks.sort();
+
+		for (stack, micros) in stacks {
+			writeln!(out, "{stack} {micros}")?;
+		}
+		Ok(())
+	}
+
+	/// Charges the time since the last instruction to the innermost call.
+	fn charge(&mut self, now: Instant) {
+		let last = self.last.replace(now);
+		let (Some(last), Some(&function)) = (last, self.stack.last()) else {
+			return;
+		};
+		let elapsed = now - last;
+		self.functions[function].self_time += elapsed;
+
+		match self.stacks.get_mut(self.stack.as_slice()) {
+			Some(time) => *time += elapsed,
+			None => _ = self.stacks.insert(self.stack.clone(), elapsed),
+		}
+	}
+
+	fn push(&mut self, function: GcRef<ObjFunction>, now: Instant) {
+		let id =
+			*self.ids.entry(function.as_ptr().addr()).or_insert_with(|| {
+				self.functions.push(FunctionStats {
+					name:       function
+						.name
+						.as_ref()
+						.map_or("script", |name| name.as_str())
+						.to_owned(),
+					calls:      0,
+					self_time:  Duration::ZERO,
+					total_time: Duration::ZERO,
+					active:     0,
+				});
+				self.functions.len() - 1
+			});
+
+		let stats = &mut self.functions[id];
+		stats.calls += 1;
+		stats.active += 1;
+		self.stack.push(id);
+		self.entered.push(now);
+	}
+
+	fn pop(&mut self, now: Instant) {
+		let (Some(id), Some(entered)) = (self.stack.pop(), self.entered.pop())
+		else {
+			return;
+		};
+		let stats = &mut self.functions[id];
+		stats.active -= 1;
+		if stats.active == 0 {
+			stats.total_time += now - entered;
+		}
+	}
+}
+
+impl Hook for Profiler {
+	fn instruction(&mut self, _cx: &Context, op: Op) -> eyre::Result<()> {
+		self.charge(Instant::now());
+		match &mut self.ops[op as usize] {
+			Some((_, count)) => *count += 1,
+			slot => *slot = Some((op, 1)),
+		}
+		Ok(())
+	}
+
+	fn enter(&mut self, cx: &Context) {
+		let now = Instant::now();
+		self.charge(now);
+		if let Some(frame) = cx.frame() {
+			self.push(frame.function(), now);
+		}
+	}
+
+	fn exit(&mut self, _cx: &Context, _function: GcRef<ObjFunction>) {
+		let now = Instant::now();
+		self.charge(now);
+		self.pop(now);
+		if self.stack.is_empty() {
+			self.last = None;
+		}
+	}
+}
+
+impl Default for Profiler {
+	fn default() -> Self {
+		Self::new()
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use std::sync::Arc;
+	use std::sync::Mutex;
+
+	use super::*;
+	use crate::vm::Vm;
+
+	const MAX_FRAMES: usize = 64;
+	const STACK_SIZE: usize = MAX_FRAMES * (u8::MAX as usize + 1);
+
+	fn profile(source: &str) -> Profiler {
+		let profiler = Arc::new(Mutex::new(Profiler::new()));
+		let mut vm = Vm::<MAX_FRAMES, STACK_SIZE>::new();
+		vm.add_hook(profiler.clone());
+		vm.set_output(std::io::sink());
+		vm.interpret(source).unwrap();
+		drop(vm);
+		Arc::into_inner(profiler).unwrap().into_inner().unwrap()
+	}
+
+	#[test]
+	fn counts_calls_and_ops() {
+		let profiler = profile(
+			"fun fib(n) { if (n < 2) return n; return fib(n - 1) + fib(n - \
+			 2); }
+			print fib(5);",
+		);
+
+		let calls = |name| {
+			let functions = profiler.functions();
+			let function = functions.iter().find(|f| f.name == name).unwrap();
+			function.calls
+		};
+		assert_eq!(calls("script"), 1);
+		assert_eq!(calls("fib"), 15);
+
+		let ops = profiler.ops();
+		let count = |op: Op| {
+			let found = ops.iter().find(|(found, _)| *found as u8 == op as u8);
+			found.unwrap().1
+		};
+		assert_eq!(count(Op::Call), 15);
+		assert_eq!(count(Op::Print), 1);
+		assert!(ops.windows(2).all(|pair| pair[0].1 >= pair[1].1));
+	}
+
+	#[test]
+	fn folds_each_distinct_stack() {
+		let profiler = profile(
+			"fun leaf() { return 1; }
+			fun branch() { return leaf() + leaf(); }
+			fun main() { branch(); leaf(); }
+			main();",
+		);
+
+		let mut folded = Vec::new();
+		profiler.write_folded(&mut folded).unwrap();
+		let folded = String::from_utf8(folded).unwrap();
+		let stacks = folded
+			.lines()
+			.map(|line| {
+				let (stack, micros) = line.rsplit_once(' ').unwrap();
+				assert!(micros.parse::<u128>().is_ok(), "{line}");
+				stack
+			})
+			.collect::<Vec<_>>();
+		assert_eq!(stacks, [
+			"script",
+			"script;main",
+			"script;main;branch",
+			"script;main;branch;leaf",
+			"script;main;leaf",
+		]);
+	}
+}
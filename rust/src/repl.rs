@@ -0,0 +1,369 @@
+use std::borrow::Cow;
+use std::time::Instant;
+
+use eyre::Result;
+use rustyline::completion::Completer;
+use rustyline::error::ReadlineError;
+use rustyline::highlight::Highlighter;
+use rustyline::hint::Hinter;
+use rustyline::history::DefaultHistory;
+use rustyline::validate::ValidationContext;
+use rustyline::validate::ValidationResult;
+use rustyline::validate::Validator;
+use rustyline::Editor;
+
+use crate::ast;
+use crate::compiler::scanner::Scanner;
+use crate::compiler::TokenKind;
+use crate::mem::GcRef;
+use crate::obj::ObjClass;
+use crate::obj::ObjClosure;
+use crate::obj::ObjFunction;
+use crate::vm::Vm;
+
+const KEYWORDS: &[&str] = &[
+	"and", "class", "else", "false", "for", "fun", "if", "nil", "or", "print",
+	"return", "super", "this", "true", "var", "while",
+];
+
+const HELP: &str = "\
+Commands:
+  :dis NAME     disassemble the function, or the methods of the class, NAME
+  :globals      list all globals with their values
+  :type EXPR    show the type of EXPR's value
+  :time EXPR    show EXPR's value and how long it took to compile and run
+  :gc           run a collection and show heap stats
+  :load PATH    run the file at PATH in this session
+  :help         show this list";
+
+/// Reads entries from the terminal and runs them on `vm` until end of input.
+///
+/// An entry keeps going over several lines while it has unclosed braces,
+/// parentheses or strings. Entries share the VM, so globals persist between
+/// them, and an entry that is a single expression prints its value. Entries
+/// starting with `:` are commands; `:help` lists them.
+pub fn run<const MAX_FRAMES: usize, const STACK_SIZE: usize>(
+	vm: &mut Vm<MAX_FRAMES, STACK_SIZE>,
+) -> Result<()> {
+	let mut editor = Editor::<Helper, DefaultHistory>::new()?;
+	editor.set_helper(Some(Helper::default()));
+
+	loop {
+		if let Some(helper) = editor.helper_mut() {
+			let globals = vm.globals().keys();
+			helper.globals =
+				globals.map(|name| name.as_str().to_owned()).collect();
+		}
+
+		let entry = match editor.readline("> ") {
+			Ok(entry) => entry,
+			Err(ReadlineError::Interrupted) => continue,
+			Err(ReadlineError::Eof) => return Ok(()),
+			Err(err) => return Err(err.into()),
+		};
+		if entry.trim().is_empty() {
+			continue;
+		}
+		editor.add_history_entry(entry.trim_end())?;
+
+		if entry.trim_start().starts_with(':') {
+			if let Err(err) = command(vm, entry.trim()) {
+				eprintln!("{err}");
+			}
+			continue;
+		}
+		if let Err(err) = vm.interpret(&auto_print(&entry)) {
+			eprintln!("{err}");
+		}
+	}
+}
+
+fn command<const MAX_FRAMES: usize, const STACK_SIZE: usize>(
+	vm: &mut Vm<MAX_FRAMES, STACK_SIZE>,
+	line: &str,
+) -> Result<()> {
+	let (command, arg) = line
+		.split_once(char::is_whitespace)
+		.map_or((line, ""), |(command, arg)| (command, arg.trim()));
+
+	match command {
+		":dis" => {
+			let Some((_, value)) =
+				vm.globals().iter().find(|(name, _)| name.as_str() == arg)
+			else {
+				bail!("Undefined variable '{arg}'.");
+			};
+
+			if let Some(closure) = value.as_casted_obj::<ObjClosure>() {
+				print!("{}", disassemble(arg, closure.function));
+			} else if let Some(function) = value.as_casted_obj::<ObjFunction>()
+			{
+				print!("{}", disassemble(arg, function));
+			} else if let Some(class) = value.as_casted_obj::<ObjClass>() {
+				let mut methods = class.methods().iter().collect::<Vec<_>>();
+				methods.sort_by_key(|(name, _)| name.as_str());
+				for (name, method) in methods {
+					let name = format!("{arg}.{}", name.as_str());
+					print!("{}", disassemble(&name, method.function));
+				}
+			} else {
+				bail!("'{arg}' is not a function or class.");
+			}
+		},
+		":globals" => {
+			let mut globals = vm.globals().iter().collect::<Vec<_>>();
+			globals.sort_by_key(|(name, _)| name.as_str());
+			for (name, value) in globals {
+				println!("{name} = {value}");
+			}
+		},
+		":type" => {
+			let value = vm.evaluate_value(arg)?;
+			match value.as_obj() {
+				Some(obj) => println!("{:?}", obj.ty),
+				None => println!("{:?}", value.kind()),
+			}
+		},
+		":time" => {
+			let start = Instant::now();
+			let function = vm.compile_expression(arg)?;
+			let compiled = start.elapsed();
+			let start = Instant::now();
+			let value = vm.run_function(function)?;
+			let ran = start.elapsed();
+			println!("{value}");
+			println!(
+				"(ran in {:.3} ms, compiled in {:.3} ms)",
+				ran.as_secs_f64() * 1000.0,
+				compiled.as_secs_f64() * 1000.0
+			);
+		},
+		":gc" => {
+			let stats = vm.collect_garbage();
+			println!("freed {}, {} still alive", stats.freed, stats.live);
+			for (ty, count) in stats.by_type {
+				println!("  {:<12} {count}", format!("{ty:?}"));
+			}
+			println!("  {:<12} {}", "(interned)", stats.interned);
+		},
+		":load" => {
+			let source = std::fs::read_to_string(arg)
+				.map_err(|err| eyre!("Could not read file \"{arg}\": {err}"))?;
+			vm.interpret(&source)?;
+		},
+		":help" => println!("{HELP}"),
+		_ => bail!("Unknown command '{command}'. Try ':help'."),
+	}
+	Ok(())
+}
+
+/// Disassembles `function` and every function nested in it.
+fn disassemble(name: &str, function: GcRef<ObjFunction>) -> String {
+	let mut listing = function.chunk.disassemble(name);
+	let nested = function
+		.chunk
+		.constants
+		.iter()
+		.filter_map(|constant| constant.as_casted_obj::<ObjFunction>());
+	for nested in nested {
+		let name = nested.name.as_ref().map_or("script", |name| name.as_str());
+		listing += &disassemble(name, nested);
+	}
+	listing
+}
+
+/// Turns an entry that is a single expression, with or without its
+/// semicolon, into a statement that prints it. The semicolon goes on a line
+/// of its own, after any comment the entry ends with.
+fn auto_print(entry: &str) -> Cow<'_, str> {
+	let trimmed = entry.trim_end();
+	let expr = trimmed.strip_suffix(';').unwrap_or(trimmed);
+	match ast::parse_expr(expr) {
+		Ok(_) => format!("print {expr}\n;").into(),
+		Err(_) => entry.into(),
+	}
+}
+
+/// Whether `source` ends inside a string or with unclosed brackets.
+fn is_incomplete(source: &str) -> bool {
+	let mut scanner = Scanner::new(source);
+	let mut depth = 0isize;
+	loop {
+		let token = scanner.scan_token();
+		match token.kind {
+			TokenKind::LBrace | TokenKind::LParen => depth += 1,
+			TokenKind::RBrace | TokenKind::RParen => depth -= 1,
+			TokenKind::Error => {
+				if token.text == "Unterminated string." {
+					return true;
+				}
+			},
+			TokenKind::Eof => return depth > 0,
+			_ => (),
+		}
+	}
+}
+
+/// Line editing support: multi-line entries and completion of keywords and
+/// global names.
+#[derive(Default)]
+struct Helper {
+	globals: Vec<String>,
+}
+
+impl Completer for Helper {
+	type Candidate = String;
+
+	fn complete(
+		&self,
+		line: &str,
+		pos: usize,
+		_ctx: &rustyline::Context,
+	) -> rustyline::Result<(usize, Vec<String>)> {
+		let start = line[..pos]
+			.rfind(|ch: char| !ch.is_ascii_alphanumeric() && ch != '_')
+			.map_or(0, |index| index + 1);
+		let prefix = &line[start..pos];
+		if prefix.is_empty() {
+			return Ok((pos, Vec::new()));
+		}
+
+		let mut candidates = self
+			.globals
+			.iter()
+			.map(String::as_str)
+			.chain(KEYWORDS.iter().copied())
+			.filter(|name| name.starts_with(prefix))
+			.map(str::to_owned)
+			.collect::<Vec<_>>();
+		candidates.sort();
+		candidates.dedup();
+		Ok((start, candidates))
+	}
+}
+
+impl Hinter for Helper {
+	type Hint = String;
+}
+
+impl Highlighter for Helper {}
+
+impl Validator for Helper {
+	fn validate(
+		&self,
+		ctx: &mut ValidationContext,
+	) -> rustyline::Result<ValidationResult> {
+		if is_incomplete(ctx.input()) {
+			Ok(ValidationResult::Incomplete)
+		} else {
+			Ok(ValidationResult::Valid(None))
+		}
+	}
+}
+
+impl rustyline::Helper for Helper {}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn auto_prints_single_expressions() {
+		assert_eq!(auto_print("1 + 2"), "print 1 + 2\n;");
+		assert_eq!(auto_print("a;  "), "print a\n;");
+		assert_eq!(auto_print("f(1)"), "print f(1)\n;");
+		assert_eq!(auto_print("1 + 2 // note"), "print 1 + 2 // note\n;");
+		// statements, and expressions followed by more, run as they are
+		assert_eq!(auto_print("var a = 1;"), "var a = 1;");
+		assert_eq!(auto_print("print 1;"), "print 1;");
+		assert_eq!(auto_print("1; 2;"), "1; 2;");
+		assert_eq!(auto_print("a = 2"), "print a = 2\n;");
+	}
+
+	#[test]
+	fn unclosed_brackets_and_strings_are_incomplete() {
+		assert!(is_incomplete("fun f() {"));
+		assert!(is_incomplete("print (1 +"));
+		assert!(is_incomplete("{ if (a) {}"));
+		assert!(is_incomplete("print \"two\nlines"));
+
+		assert!(!is_incomplete("fun f() {}"));
+		assert!(!is_incomplete("print \"closed\";"));
+		// an extra closing bracket is an error to report, not to wait out
+		assert!(!is_incomplete("}"));
+		// brackets in strings and comments don't count
+		assert!(!is_incomplete("print \"{\"; // ("));
+	}
+
+	fn complete(globals: &[&str], line: &str) -> (usize, Vec<String>) {
+		let helper = Helper {
+			globals: globals.iter().map(|name| name.to_string()).collect(),
+		};
+		let history = DefaultHistory::new();
+		let cx = rustyline::Context::new(&history);
+		helper.complete(line, line.len(), &cx).unwrap()
+	}
+
+	#[test]
+	fn completes_keywords_and_globals() {
+		let globals = ["format", "fib", "counter"];
+		assert_eq!(
+			complete(&globals, "print f"),
+			(
+				6,
+				vec!["false", "fib", "for", "format", "fun"]
+					.into_iter()
+					.map(String::from)
+					.collect()
+			)
+		);
+		assert_eq!(complete(&globals, "x = co").1, ["counter"]);
+		assert_eq!(complete(&globals, "wh").1, ["while"]);
+		assert!(complete(&globals, "print ").1.is_empty());
+		assert!(complete(&globals, "zz").1.is_empty());
+	}
+
+	const MAX_FRAMES: usize = 64;
+	const STACK_SIZE: usize = MAX_FRAMES * (u8::MAX as usize + 1);
+
+	fn vm(source: &str) -> Vm<MAX_FRAMES, STACK_SIZE> {
+		let mut vm = Vm::new();
+		vm.set_output(std::io::sink());
+		vm.interpret(source).unwrap();
+		vm
+	}
+
+	fn error(vm: &mut Vm<MAX_FRAMES, STACK_SIZE>, line: &str) -> String {
+		command(vm, line).unwrap_err().to_string()
+	}
+
+	#[test]
+	fn disassembles_nested_functions() {
+		let mut vm =
+			vm("fun outer() { fun inner() { return 1; } return inner; }");
+		let closure = vm.evaluate_value("outer").unwrap();
+		let function = closure.as_casted_obj::<ObjClosure>().unwrap().function;
+
+		let listing = disassemble("outer", function);
+		let outer = listing.find("== outer ==").unwrap();
+		let inner = listing.find("== inner ==").unwrap();
+		assert!(outer < inner);
+		assert!(listing[outer..inner].contains("Closure"));
+		assert!(listing[inner..].contains("Return"));
+	}
+
+	#[test]
+	fn commands_report_bad_arguments() {
+		let mut vm = vm("var a = 1;");
+		assert_eq!(error(&mut vm, ":dis b"), "Undefined variable 'b'.");
+		assert_eq!(error(&mut vm, ":dis a"), "'a' is not a function or class.");
+		assert_eq!(
+			error(&mut vm, ":frobnicate"),
+			"Unknown command ':frobnicate'. Try ':help'."
+		);
+		assert!(error(&mut vm, ":load /nonexistent.lox")
+			.starts_with("Could not read file \"/nonexistent.lox\""));
+		assert!(command(&mut vm, ":type a + 1").is_ok());
+		assert!(command(&mut vm, ":type b").is_err());
+	}
+}
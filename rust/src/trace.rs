@@ -0,0 +1,228 @@
+use std::io::Write;
+use std::ops::RangeInclusive;
+
+use eyre::Result;
+use serde_json::json;
+use serde_json::Value as Json;
+
+use crate::chunk::Op;
+use crate::obj::ObjFunction;
+use crate::value::Value;
+use crate::vm::Context;
+use crate::vm::Hook;
+
+/// Writes one JSON object per instruction run, one per line:
+///
+/// ```text
+/// {"function":"fib","depth":2,"offset":4,"line":3,"op":"GetLocal","operands":[1],"stack":[2.0]}
+/// ```
+///
+/// Ops that take a constant also carry it as `constant`. Attach one to a VM
+/// with `Vm::add_hook`.
+pub struct Tracer {
+	out:      Box<dyn Write + Send>,
+	function: Option<String>,
+	lines:    Option<RangeInclusive<u32>>,
+	stack:    usize,
+}
+
+impl Tracer {
+	pub fn new(out: impl Write + Send + 'static) -> Self {
+		Self {
+			out:      Box::new(out),
+			function: None,
+			lines:    None,
+			stack:    3,
+		}
+	}
+
+	/// Only trace instructions of functions called `name`. The top-level
+	/// script is called `script`.
+	pub fn function(mut self, name: impl Into<String>) -> Self {
+		self.function = Some(name.into());
+		self
+	}
+
+	/// Only trace instructions from source lines in `lines`.
+	pub fn lines(mut self, lines: RangeInclusive<u32>) -> Self {
+		self.lines = Some(lines);
+		self
+	}
+
+	/// How many values from the top of the stack each line shows.
+	pub fn stack_values(mut self, count: usize) -> Self {
+		self.stack = count;
+		self
+	}
+
+	pub fn flush(&mut self) -> std::io::Result<()> {
+		self.out.flush()
+	}
+}
+
+impl Hook for Tracer {
+	fn instruction(&mut self, cx: &Context, op: Op) -> Result<()> {
+		let Some(frame) = cx.frame() else {
+			return Ok(());
+		};
+		let function = frame.function();
+		let name = function
+			.name
+			.as_ref()
+			.map_or("script", |name| name.as_str());
+		let line = frame.line();
+
+		if self.function.as_ref().is_some_and(|only| only != name)
+			|| self
+				.lines
+				.as_ref()
+				.is_some_and(|only| !only.contains(&line))
+		{
+			return Ok(());
+		}
+
+		let offset = frame.offset();
+		let stack = cx.stack();
+		let top = &stack[stack.len().saturating_sub(self.stack)..];
+
+		let mut entry = json!({
+			"function": name,
+			"depth": cx.depth(),
+			"offset": offset,
+			"line": line,
+			"op": format!("{op:?}"),
+			"operands": operands(&function, offset),
+			"stack": top.iter().copied().map(to_json).collect::<Vec<_>>(),
+		});
+		if let Some(constant) = constant(&function, offset, op) {
+			entry["constant"] = to_json(constant);
+		}
+
+		serde_json::to_writer(&mut self.out, &entry)
+			.map_err(std::io::Error::from)
+			.and_then(|()| writeln!(self.out))
+			.map_err(|err| eyre!("Could not write trace: {err}"))
+	}
+}
+
+/// The operand bytes of the instruction at `offset`.
+fn operands(function: &ObjFunction, offset: usize) -> Vec<u8> {
+	let chunk = &function.chunk;
+	let end = offset + chunk.instruction_len(offset);
+	chunk.bytecode[offset + 1..end]
+		.iter()
+		.map(|bytecode| unsafe { bytecode.byte })
+		.collect()
+}
+
+/// The constant the instruction at `offset` refers to, if it takes one.
+fn constant(function: &ObjFunction, offset: usize, op: Op) -> Option<Value> {
+	use Op::*;
+
+	match op {
+		Class | Closure | Constant | DefineGlobal | GetGlobal | GetProperty
+		| GetSuper | Invoke | Method | SetGlobal | SetProperty
+		| SuperInvoke => {
+			let chunk = &function.chunk;
+			let index = unsafe { chunk.bytecode[offset + 1].byte };
+			Some(chunk.constants[index as usize])
+		},
+		_ => None,
+	}
+}
+
+fn to_json(value: Value) -> Json {
+	if value.is_nil() {
+		Json::Null
+	} else if let Some(bool) = value.as_bool() {
+		json!(bool)
+	} else if let Some(number) =
+		value.as_number().filter(|number| number.is_finite())
+	{
+		json!(number)
+	} else {
+		json!(value.to_string())
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::vm::OutputBuffer;
+	use crate::vm::Vm;
+
+	const MAX_FRAMES: usize = 64;
+	const STACK_SIZE: usize = MAX_FRAMES * (u8::MAX as usize + 1);
+
+	const PROGRAM: &str = "fun add(a, b) {
+	return a + b;
+}
+var sum = add(1, 2);
+print sum;
+";
+
+	fn trace(tracer: impl FnOnce(Tracer) -> Tracer) -> Vec<Json> {
+		let out = OutputBuffer::default();
+		let mut vm = Vm::<MAX_FRAMES, STACK_SIZE>::new();
+		vm.set_output(std::io::sink());
+		vm.add_hook(tracer(Tracer::new(out.clone())));
+		vm.interpret(PROGRAM).unwrap();
+		drop(vm);
+
+		out.take()
+			.lines()
+			.map(|line| serde_json::from_str(line).unwrap())
+			.collect()
+	}
+
+	fn field<'a>(entries: &'a [Json], name: &str) -> Vec<&'a Json> {
+		entries.iter().map(|entry| &entry[name]).collect()
+	}
+
+	#[test]
+	fn traces_every_instruction() {
+		let entries = trace(|tracer| tracer);
+		let functions = field(&entries, "function");
+		assert!(functions.contains(&&json!("script")));
+		assert!(functions.contains(&&json!("add")));
+
+		let add = entries.iter().find(|entry| entry["op"] == "Add").unwrap();
+		assert_eq!(add["function"], "add");
+		assert_eq!(add["depth"], 2);
+		assert_eq!(add["line"], 2);
+		// `b`, then the copies of `a` and `b` that `Add` takes
+		assert_eq!(add["stack"], json!([2.0, 1.0, 2.0]));
+
+		let call = entries.iter().find(|entry| entry["op"] == "Call").unwrap();
+		assert_eq!(call["operands"], json!([2]));
+		let define = entries
+			.iter()
+			.find(|entry| entry["op"] == "DefineGlobal")
+			.unwrap();
+		assert_eq!(define["constant"], "add");
+	}
+
+	#[test]
+	fn filters_by_function() {
+		let entries = trace(|tracer| tracer.function("add"));
+		assert!(!entries.is_empty());
+		assert!(field(&entries, "function")
+			.iter()
+			.all(|name| **name == "add"));
+	}
+
+	#[test]
+	fn filters_by_lines() {
+		let entries = trace(|tracer| tracer.lines(4..=4));
+		assert!(!entries.is_empty());
+		assert!(field(&entries, "line").iter().all(|line| **line == 4));
+		assert!(field(&entries, "function").iter().all(|f| **f == "script"));
+	}
+
+	#[test]
+	fn shows_the_top_of_the_stack() {
+		let entries = trace(|tracer| tracer.function("add").stack_values(1));
+		let add = entries.iter().find(|entry| entry["op"] == "Add").unwrap();
+		assert_eq!(add["stack"], json!([2.0]));
+	}
+}
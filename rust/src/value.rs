@@ -1,36 +1,28 @@
+mod convert;
+mod list;
+#[cfg(feature = "nan-boxing")]
+mod nan_boxing;
+#[cfg(not(feature = "nan-boxing"))]
+mod tagged;
+
 use std::fmt::Display;
 
+pub use self::convert::FromLox;
+pub use self::convert::IntoLox;
+pub use self::list::List;
+#[cfg(feature = "nan-boxing")]
+pub use self::nan_boxing::Value;
+#[cfg(not(feature = "nan-boxing"))]
+pub use self::tagged::Value;
 use crate::mem::GcRef;
+use crate::mem::Marker;
 use crate::mem::Trace;
 use crate::obj::Obj;
+use crate::obj::ObjString;
 use crate::obj::ObjTy;
+use crate::obj::ObjType;
 
-#[derive(Clone, Copy)]
-pub struct Value(ValueKind, ValueData);
-
-impl Value {
-	#[allow(non_snake_case)]
-	pub const fn Bool(bool: bool) -> Value {
-		Value(ValueKind::Bool, ValueData { bool })
-	}
-
-	#[allow(non_snake_case)]
-	pub const fn Nil() -> Value {
-		Value(ValueKind::Nil, ValueData { nil: () })
-	}
-
-	#[allow(non_snake_case)]
-	pub const fn Number(number: f64) -> Value {
-		Value(ValueKind::Number, ValueData { number })
-	}
-
-	#[allow(non_snake_case)]
-	pub fn Obj(obj: GcRef<Obj>) -> Value {
-		Value(ValueKind::Obj, ValueData { obj })
-	}
-}
-
-#[derive(Clone, Copy)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum ValueKind {
 	Bool,
 	Nil,
@@ -38,89 +30,89 @@ pub enum ValueKind {
 	Obj,
 }
 
-#[derive(Clone, Copy)]
-pub union ValueData {
-	bool:   bool,
-	nil:    (),
-	number: f64,
-	obj:    GcRef<Obj>,
-}
-
+// The representations of `Value` provide its constructors, `kind` and the
+// `as_*` accessors, which everything else here is built on
 impl Value {
-	pub fn as_bool(&self) -> Option<bool> {
-		match self {
-			Value(ValueKind::Bool, data) => Some(unsafe { data.bool }),
-			_ => None,
-		}
-	}
-
-	pub fn as_number(&self) -> Option<f64> {
-		match self {
-			Value(ValueKind::Number, data) => Some(unsafe { data.number }),
-			_ => None,
-		}
+	pub fn as_casted_obj<Type: ObjTy>(&self) -> Option<GcRef<Type>> {
+		self.as_obj()?.try_cast()
 	}
 
-	pub fn as_obj(&self) -> Option<GcRef<Obj>> {
-		let Value(ValueKind::Obj, data) = self else {
-			return None;
+	/// The name of the value's type, for error messages.
+	pub fn type_name(&self) -> &'static str {
+		let obj = match self.kind() {
+			ValueKind::Bool => return "boolean",
+			ValueKind::Nil => return "nil",
+			ValueKind::Number => return "number",
+			ValueKind::Obj => self.as_obj().unwrap(),
 		};
-		Some(unsafe { data.obj })
-	}
 
-	pub fn as_casted_obj<Type: ObjTy>(&self) -> Option<GcRef<Type>> {
-		self.as_obj()?.try_cast()
+		match obj.ty {
+			ObjType::Class => "class",
+			ObjType::Foreign | ObjType::Instance => "instance",
+			ObjType::String => "string",
+			ObjType::Upvalue => "upvalue",
+			ObjType::BoundMethod
+			| ObjType::Closure
+			| ObjType::Function
+			| ObjType::Native => "function",
+		}
 	}
 
 	pub fn is_bool(&self) -> bool {
-		matches!(self.0, ValueKind::Bool)
+		matches!(self.kind(), ValueKind::Bool)
 	}
 
 	pub fn is_falsey(&self) -> bool {
-		match self {
-			Value(ValueKind::Nil, _) => true,
-			Value(ValueKind::Bool, data) if unsafe { !data.bool } => true,
-			_ => false,
-		}
+		self.is_nil() || self.as_bool() == Some(false)
 	}
 
 	pub fn is_nil(&self) -> bool {
-		matches!(self.0, ValueKind::Nil)
+		matches!(self.kind(), ValueKind::Nil)
 	}
 
 	pub fn is_number(&self) -> bool {
-		matches!(self.0, ValueKind::Number)
+		matches!(self.kind(), ValueKind::Number)
 	}
 
 	pub fn is_obj(&self) -> bool {
-		matches!(self.0, ValueKind::Obj)
+		matches!(self.kind(), ValueKind::Obj)
 	}
 }
 
 impl Display for Value {
 	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-		match self {
-			Value(ValueKind::Bool, data) => unsafe { data.bool }.fmt(f),
-			Value(ValueKind::Nil, _) => "nil".fmt(f),
-			Value(ValueKind::Number, data) => unsafe { data.number }.fmt(f),
-			Value(ValueKind::Obj, data) => unsafe { data.obj }.fmt(f),
+		if let Some(bool) = self.as_bool() {
+			bool.fmt(f)
+		} else if let Some(number) = self.as_number() {
+			number.fmt(f)
+		} else if let Some(obj) = self.as_obj() {
+			obj.fmt(f)
+		} else {
+			"nil".fmt(f)
 		}
 	}
 }
 
 impl PartialEq for Value {
 	fn eq(&self, other: &Self) -> bool {
-		match (self, other) {
-			(Value(ValueKind::Bool, l), Value(ValueKind::Bool, r)) => unsafe {
-				l.bool == r.bool
-			},
-			(Value(ValueKind::Nil, _), Value(ValueKind::Nil, _)) => true,
-			(Value(ValueKind::Number, l), Value(ValueKind::Number, r)) => unsafe {
-				l.number == r.number
-			},
-			(Value(ValueKind::Obj, l), Value(ValueKind::Obj, r)) => unsafe {
+		if let (Some(l), Some(r)) = (self.as_obj(), other.as_obj()) {
+			return match (l.try_cast::<ObjString>(), r.try_cast::<ObjString>())
+			{
+				// ropes aren't interned until they're flattened, so strings
+				// can't always be compared by address
+				(Some(l), Some(r)) => *l == *r,
 				// clox does a pointer equality check
-				l.obj.as_ptr() == r.obj.as_ptr()
+				_ => l.as_ptr() == r.as_ptr(),
+			};
+		}
+
+		match (self.kind(), other.kind()) {
+			(ValueKind::Bool, ValueKind::Bool) => {
+				self.as_bool() == other.as_bool()
+			},
+			(ValueKind::Nil, ValueKind::Nil) => true,
+			(ValueKind::Number, ValueKind::Number) => {
+				self.as_number() == other.as_number()
 			},
 			_ => false,
 		}
@@ -129,4 +121,10 @@ impl PartialEq for Value {
 
 impl Eq for Value {}
 
-impl Trace for Value {}
+impl Trace for Value {
+	fn trace(&self, marker: &mut Marker) {
+		if let Some(obj) = self.as_obj() {
+			marker.mark(obj);
+		}
+	}
+}
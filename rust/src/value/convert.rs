@@ -0,0 +1,148 @@
+use super::List;
+use super::Value;
+use crate::mem::GarbageCollector;
+use crate::obj::ObjForeign;
+use crate::obj::ObjString;
+use crate::vm::RuntimeError;
+
+/// Conversion of a Lox value into a Rust one, failing with a runtime error
+/// that names the expected type. `Vm::call` and `Vm::evaluate` return the
+/// result, which the host may keep after the VM moves to another thread, so
+/// it owns its data rather than pointing into the heap.
+pub trait FromLox: Sized {
+	fn from_lox(value: Value) -> Result<Self, RuntimeError>;
+
+	/// Converts every value of a native's argument list.
+	fn from_lox_args(args: &[Value]) -> Result<Vec<Self>, RuntimeError> {
+		args.iter().copied().map(Self::from_lox).collect()
+	}
+}
+
+/// Conversion of a Rust value into a Lox one, allocated on `gc` if it needs
+/// to be.
+pub trait IntoLox {
+	fn into_lox(self, gc: &GarbageCollector) -> Value;
+}
+
+impl RuntimeError {
+	/// The error for `value` not being of the `expected` type.
+	pub fn expected(expected: &str, value: Value) -> Self {
+		Self::new(format!(
+			"Expected {expected} but got {}.",
+			value.type_name()
+		))
+	}
+}
+
+impl FromLox for f64 {
+	fn from_lox(value: Value) -> Result<Self, RuntimeError> {
+		value
+			.as_number()
+			.ok_or_else(|| RuntimeError::expected("a number", value))
+	}
+}
+
+impl FromLox for bool {
+	fn from_lox(value: Value) -> Result<Self, RuntimeError> {
+		value
+			.as_bool()
+			.ok_or_else(|| RuntimeError::expected("a boolean", value))
+	}
+}
+
+impl FromLox for () {
+	fn from_lox(value: Value) -> Result<Self, RuntimeError> {
+		if value.is_nil() {
+			Ok(())
+		} else {
+			Err(RuntimeError::expected("nil", value))
+		}
+	}
+}
+
+/// There's no conversion to `&str`: a `Value` doesn't borrow its heap, so
+/// nothing would stop the `&str` from outliving its string once a collection
+/// frees it or the VM moves. Natives that only read a string can borrow it
+/// from the `ObjString` with `as_str`, for as long as they hold the value.
+impl FromLox for String {
+	fn from_lox(value: Value) -> Result<Self, RuntimeError> {
+		value
+			.as_casted_obj::<ObjString>()
+			.map(|string| string.as_str().to_owned())
+			.ok_or_else(|| RuntimeError::expected("a string", value))
+	}
+}
+
+/// `nil` converts to `None`.
+impl<T: FromLox> FromLox for Option<T> {
+	fn from_lox(value: Value) -> Result<Self, RuntimeError> {
+		if value.is_nil() {
+			Ok(None)
+		} else {
+			T::from_lox(value).map(Some)
+		}
+	}
+}
+
+/// Converts a `List`, see `IntoLox for Vec<T>`.
+impl<T: FromLox> FromLox for Vec<T> {
+	fn from_lox(value: Value) -> Result<Self, RuntimeError> {
+		let foreign = value.as_casted_obj::<ObjForeign>();
+		let list = foreign.as_ref().and_then(|foreign| foreign.downcast_ref());
+		let Some(List(values)) = list else {
+			return Err(RuntimeError::expected("a list", value));
+		};
+		T::from_lox_args(values)
+	}
+}
+
+impl IntoLox for Value {
+	fn into_lox(self, _gc: &GarbageCollector) -> Value {
+		self
+	}
+}
+
+impl IntoLox for f64 {
+	fn into_lox(self, _gc: &GarbageCollector) -> Value {
+		Value::Number(self)
+	}
+}
+
+impl IntoLox for bool {
+	fn into_lox(self, _gc: &GarbageCollector) -> Value {
+		Value::Bool(self)
+	}
+}
+
+impl IntoLox for () {
+	fn into_lox(self, _gc: &GarbageCollector) -> Value {
+		Value::Nil()
+	}
+}
+
+impl IntoLox for String {
+	fn into_lox(self, gc: &GarbageCollector) -> Value {
+		ObjString::new(gc, self).value()
+	}
+}
+
+impl IntoLox for &str {
+	fn into_lox(self, gc: &GarbageCollector) -> Value {
+		ObjString::new(gc, self).value()
+	}
+}
+
+/// `None` converts to `nil`.
+impl<T: IntoLox> IntoLox for Option<T> {
+	fn into_lox(self, gc: &GarbageCollector) -> Value {
+		self.map_or(Value::Nil(), |value| value.into_lox(gc))
+	}
+}
+
+/// A `Vec` converts to a `List`, the only list type scripts know.
+impl<T: IntoLox> IntoLox for Vec<T> {
+	fn into_lox(self, gc: &GarbageCollector) -> Value {
+		let values = self.into_iter().map(|value| value.into_lox(gc)).collect();
+		ObjForeign::new(gc, gc.list_class(), List(values)).value()
+	}
+}
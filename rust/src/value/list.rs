@@ -0,0 +1,70 @@
+use std::fmt::Formatter;
+
+use super::FromLox;
+use super::Value;
+use crate::mem::Marker;
+use crate::mem::Trace;
+use crate::obj::Arity;
+use crate::obj::ForeignClass;
+use crate::obj::ForeignClassBuilder;
+use crate::vm::RuntimeError;
+
+/// A list of Lox values, what a `Vec` converts to. Scripts read its
+/// `length`, and use it with `get(index)`, `set(index, value)` and
+/// `push(value)`.
+pub struct List(pub(crate) Vec<Value>);
+
+// SAFETY: only this crate makes lists, each as the value of a foreign object
+// on the heap its values live on, so they move between threads together.
+unsafe impl Send for List {}
+
+impl List {
+	/// The foreign class of lists, which every heap builds once.
+	pub(crate) fn class() -> ForeignClassBuilder<List> {
+		ForeignClass::builder("List")
+			.getter(|list: &List, _, name| {
+				let length = list.0.len() as f64;
+				(name == "length").then_some(Value::Number(length))
+			})
+			.method("get", Arity::Exact(1), |list, _, args| {
+				let index = list.index(args[0])?;
+				Ok(list.0[index])
+			})
+			.method("set", Arity::Exact(2), |list, _, args| {
+				let index = list.index(args[0])?;
+				list.0[index] = args[1];
+				Ok(args[1])
+			})
+			.method("push", Arity::Exact(1), |list, _, args| {
+				list.0.push(args[0]);
+				Ok(Value::Nil())
+			})
+			.display(List::fmt)
+	}
+
+	fn index(&self, value: Value) -> Result<usize, RuntimeError> {
+		let index = f64::from_lox(value)?;
+		let in_range = (0.0..self.0.len() as f64).contains(&index);
+		if !in_range || index.fract() != 0.0 {
+			return Err(RuntimeError::new("List index out of range."));
+		}
+		Ok(index as usize)
+	}
+
+	fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
+		write!(f, "[")?;
+		for (i, value) in self.0.iter().enumerate() {
+			if i > 0 {
+				write!(f, ", ")?;
+			}
+			write!(f, "{value}")?;
+		}
+		write!(f, "]")
+	}
+}
+
+impl Trace for List {
+	fn trace(&self, marker: &mut Marker) {
+		self.0.iter().for_each(|value| value.trace(marker));
+	}
+}
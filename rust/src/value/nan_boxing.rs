@@ -0,0 +1,90 @@
+use std::marker::PhantomData;
+
+use super::*;
+
+#[cfg(not(target_pointer_width = "64"))]
+compile_error!("the nan-boxing feature needs 64-bit pointers");
+
+/// A value packed into the bits of an `f64`, like clox's `NAN_BOXING`.
+///
+/// Numbers are stored as themselves. Everything else is a quiet NaN that no
+/// arithmetic produces: nil and booleans are tagged in the low bits, and
+/// objects set the sign bit and keep their address in the low 48 bits.
+///
+/// Like the tagged representation, it's neither `Send` nor `Sync`, since it
+/// may point into a VM's heap.
+#[derive(Clone, Copy)]
+#[repr(transparent)]
+pub struct Value(u64, PhantomData<GcRef<Obj>>);
+
+const SIGN_BIT: u64 = 0x8000_0000_0000_0000;
+const QNAN: u64 = 0x7FFC_0000_0000_0000;
+/// The NaN every NaN number is stored as, which has fewer bits than `QNAN`.
+const CANONICAL_NAN: u64 = 0x7FF8_0000_0000_0000;
+
+const TAG_NIL: u64 = 1;
+const TAG_FALSE: u64 = 2;
+const TAG_TRUE: u64 = 3;
+
+const NIL: u64 = QNAN | TAG_NIL;
+const FALSE: u64 = QNAN | TAG_FALSE;
+const TRUE: u64 = QNAN | TAG_TRUE;
+
+impl Value {
+	#[allow(non_snake_case)]
+	pub const fn Bool(bool: bool) -> Value {
+		Value(if bool { TRUE } else { FALSE }, PhantomData)
+	}
+
+	#[allow(non_snake_case)]
+	pub const fn Nil() -> Value {
+		Value(NIL, PhantomData)
+	}
+
+	#[allow(non_snake_case)]
+	pub const fn Number(number: f64) -> Value {
+		// a NaN's payload could otherwise spell out a tag or an address
+		if number.is_nan() {
+			return Value(CANONICAL_NAN, PhantomData);
+		}
+		Value(number.to_bits(), PhantomData)
+	}
+
+	#[allow(non_snake_case)]
+	pub fn Obj(obj: GcRef<Obj>) -> Value {
+		Value(SIGN_BIT | QNAN | obj.as_ptr() as usize as u64, PhantomData)
+	}
+
+	pub fn as_bool(&self) -> Option<bool> {
+		match self.0 {
+			TRUE => Some(true),
+			FALSE => Some(false),
+			_ => None,
+		}
+	}
+
+	pub fn as_number(&self) -> Option<f64> {
+		self.is_number().then(|| f64::from_bits(self.0))
+	}
+
+	pub fn as_obj(&self) -> Option<GcRef<Obj>> {
+		if !self.is_obj() {
+			return None;
+		}
+		let ptr = (self.0 & !(SIGN_BIT | QNAN)) as usize as *mut Obj;
+		// SAFETY: the bits were packed from a `GcRef` in `Value::Obj`
+		Some(GcRef::new_raw(unsafe { &mut *ptr }))
+	}
+
+	pub fn kind(&self) -> ValueKind {
+		if self.0 & QNAN != QNAN {
+			ValueKind::Number
+		} else if self.0 & (SIGN_BIT | QNAN) == SIGN_BIT | QNAN {
+			ValueKind::Obj
+		} else if self.0 == NIL {
+			ValueKind::Nil
+		} else {
+			ValueKind::Bool
+		}
+	}
+}
@@ -0,0 +1,60 @@
+use super::*;
+
+/// A value as its kind alongside the data for it.
+#[derive(Clone, Copy)]
+pub struct Value(ValueKind, ValueData);
+
+#[derive(Clone, Copy)]
+union ValueData {
+	bool:   bool,
+	nil:    (),
+	number: f64,
+	obj:    GcRef<Obj>,
+}
+
+impl Value {
+	#[allow(non_snake_case)]
+	pub const fn Bool(bool: bool) -> Value {
+		Value(ValueKind::Bool, ValueData { bool })
+	}
+
+	#[allow(non_snake_case)]
+	pub const fn Nil() -> Value {
+		Value(ValueKind::Nil, ValueData { nil: () })
+	}
+
+	#[allow(non_snake_case)]
+	pub const fn Number(number: f64) -> Value {
+		Value(ValueKind::Number, ValueData { number })
+	}
+
+	#[allow(non_snake_case)]
+	pub fn Obj(obj: GcRef<Obj>) -> Value {
+		Value(ValueKind::Obj, ValueData { obj })
+	}
+
+	pub fn as_bool(&self) -> Option<bool> {
+		match self {
+			Value(ValueKind::Bool, data) => Some(unsafe { data.bool }),
+			_ => None,
+		}
+	}
+
+	pub fn as_number(&self) -> Option<f64> {
+		match self {
+			Value(ValueKind::Number, data) => Some(unsafe { data.number }),
+			_ => None,
+		}
+	}
+
+	pub fn as_obj(&self) -> Option<GcRef<Obj>> {
+		let Value(ValueKind::Obj, data) = self else {
+			return None;
+		};
+		Some(unsafe { data.obj })
+	}
+
+	pub fn kind(&self) -> ValueKind {
+		self.0
+	}
+}
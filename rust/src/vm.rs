@@ -1,17 +1,54 @@
 mod call_frame;
+mod caller;
+#[cfg(feature = "hooks")]
+mod hook;
+mod inline_cache;
+mod interrupt;
+mod limits;
+mod output;
 mod run;
+mod runtime_error;
 
+use std::any::TypeId;
+use std::io::Write;
 use std::ptr::NonNull;
+use std::rc::Rc;
 
 use eyre::Result;
 use fnv::FnvHashMap;
 
 use self::call_frame::CallFrame;
+pub use self::caller::Caller;
+#[cfg(feature = "hooks")]
+pub use self::hook::Context;
+#[cfg(feature = "hooks")]
+pub use self::hook::Frame;
+#[cfg(feature = "hooks")]
+pub use self::hook::Hook;
+pub use self::inline_cache::InlineCache;
+#[cfg(feature = "hooks")]
+use self::hook::Hooks;
+pub use self::interrupt::InterruptHandle;
+pub use self::limits::Limits;
+pub use self::output::OutputBuffer;
+use self::limits::Usage;
+pub use self::runtime_error::RuntimeError;
+use crate::compiler;
+use crate::mem::GarbageCollector;
 use crate::mem::GcRef;
+use crate::mem::HeapStats;
 use crate::mem::InlineVec;
+use crate::mem::Marker;
+use crate::mem::Trace;
 use crate::obj::*;
+use crate::value::FromLox;
 use crate::value::Value;
 
+/// A Lox interpreter and the heap its objects live on.
+///
+/// A VM can move to another thread between runs, taking its heap along. It
+/// only hands the host owned Rust values, converted with `FromLox`, so
+/// nothing the host keeps points into the heap.
 #[repr(C)]
 pub struct Vm<const MAX_FRAMES: usize, const STACK_SIZE: usize> {
 	frames: InlineVec<MAX_FRAMES, CallFrame>,
@@ -19,6 +56,24 @@ pub struct Vm<const MAX_FRAMES: usize, const STACK_SIZE: usize> {
 
 	globals:       FnvHashMap<GcRef<ObjString>, Value>,
 	open_upvalues: Vec<GcRef<ObjUpvalue>>,
+	/// Number of frames below the innermost `Vm::call`; returning to it ends
+	/// `run`.
+	base_depth:    usize,
+	/// Classes of the Rust types scripts can use, by type.
+	foreign:       FnvHashMap<TypeId, Rc<ForeignClass>>,
+
+	limits:    Limits,
+	usage:     Usage,
+	interrupt: InterruptHandle,
+
+	output:      Box<dyn Write + Send>,
+	diagnostics: Box<dyn Write + Send>,
+
+	#[cfg(feature = "hooks")]
+	hooks: Hooks,
+
+	/// Declared last so that it outlives everything that points into it.
+	gc: GarbageCollector,
 }
 
 impl<const MAX_FRAMES: usize, const STACK_SIZE: usize>
@@ -27,6 +82,11 @@ impl<const MAX_FRAMES: usize, const STACK_SIZE: usize>
 	pub fn new() -> Self {
 		let mut res = Self::default();
 		res.reset();
+
+		let start = std::time::Instant::now();
+		res.define_native("clock", Arity::Exact(0), Box::new(move |_, _| {
+			Ok(Value::Number(start.elapsed().as_secs_f64()))
+		}));
 		res
 	}
 
@@ -34,20 +94,160 @@ impl<const MAX_FRAMES: usize, const STACK_SIZE: usize>
 		self.stack.clear()
 	}
 
+	/// Makes `function` callable from Lox as the global `name`. The VM checks
+	/// each call's argument count against `arity` before calling it.
+	pub fn define_native(
+		&mut self,
+		name: &str,
+		arity: Arity,
+		function: NativeFn,
+	) {
+		let name = ObjString::new(&self.gc, name);
+		let native = ObjNative::new(&self.gc, name, arity, function);
+		self.globals.insert(name, native.value());
+	}
+
+	/// Lets scripts use Rust values of type `T`, as objects of `class`.
+	pub fn register_foreign<T: Trace + Send + 'static>(
+		&mut self,
+		class: ForeignClassBuilder<T>,
+	) {
+		let class = class.build(&self.gc);
+		self.foreign.insert(TypeId::of::<T>(), Rc::new(class));
+	}
+
+	/// Wraps `value` as a Lox object of its type's registered class, and
+	/// makes it the global `name`.
+	pub fn define_foreign<T: Trace + Send + 'static>(
+		&mut self,
+		name: &str,
+		value: T,
+	) -> Result<()> {
+		let Some(class) = self.foreign.get(&TypeId::of::<T>()) else {
+			bail!(
+				"No foreign class is registered for {}.",
+				std::any::type_name::<T>()
+			);
+		};
+		let foreign = ObjForeign::new(&self.gc, class.clone(), value);
+		let name = ObjString::new(&self.gc, name);
+		self.globals.insert(name, foreign.value());
+		Ok(())
+	}
+
+	/// Frees the objects the VM can no longer reach, and reports what's left
+	/// on the heap.
+	pub fn collect_garbage(&mut self) -> HeapStats {
+		let stats = self.gc.collect(|marker| self.mark_roots(marker));
+		#[cfg(feature = "hooks")]
+		self.collect_hook(&stats);
+		stats
+	}
+
+	fn mark_roots(&self, marker: &mut Marker) {
+		self.stack.trace(marker);
+		for (name, value) in &self.globals {
+			name.trace(marker);
+			value.trace(marker);
+		}
+		self.frames.iter().for_each(|frame| frame.closure.trace(marker));
+		self.open_upvalues.iter().for_each(|upvalue| upvalue.trace(marker));
+		self.foreign.values().for_each(|class| class.trace(marker));
+	}
+
+	#[cfg(any(test, feature = "repl"))]
+	pub(crate) fn globals(&self) -> &FnvHashMap<GcRef<ObjString>, Value> {
+		&self.globals
+	}
+
 	pub fn interpret(&mut self, src: &str) -> Result<()> {
-		todo!()
+		let function = compiler::compile(&self.gc, src).map_err(join_errors)?;
+		let closure = ObjClosure::new(&self.gc, function);
+		self.call_with(closure.value(), std::iter::empty::<Value>())?;
+		Ok(())
 	}
 
-	pub fn peek(&self, index: usize) -> Value {
-		todo!()
+	/// Runs the expression `source` and returns its value, converted to `T`.
+	pub fn evaluate<T: FromLox>(&mut self, source: &str) -> Result<T> {
+		let value = self.evaluate_value(source)?;
+		Ok(T::from_lox(value)?)
 	}
 
-	pub fn pop(&mut self) -> Value {
-		todo!()
+	/// Runs the expression `source` and returns its value, which points into
+	/// the heap if it's an object.
+	pub(crate) fn evaluate_value(&mut self, source: &str) -> Result<Value> {
+		let function = self.compile_expression(source)?;
+		self.run_function(function)
 	}
 
-	pub fn push(&mut self, value: Value) {
-		todo!()
+	/// Compiles the expression `source` into a function returning its value.
+	pub(crate) fn compile_expression(
+		&self,
+		source: &str,
+	) -> Result<GcRef<ObjFunction>> {
+		compiler::compile_expression(&self.gc, source).map_err(join_errors)
+	}
+
+	/// Runs a script `function` compiled on this VM's heap, and returns what
+	/// it returns.
+	pub(crate) fn run_function(
+		&mut self,
+		function: GcRef<ObjFunction>,
+	) -> Result<Value> {
+		let closure = ObjClosure::new(&self.gc, function);
+		self.call_with(closure.value(), std::iter::empty::<Value>())
+	}
+
+	/// The value `index` slots below the top of the stack.
+	pub(crate) fn peek(&self, index: usize) -> Value {
+		self.stack[self.stack.len() - 1 - index]
+	}
+
+	pub(crate) fn pop(&mut self) -> Value {
+		self.stack.pop().expect("stack underflow")
+	}
+
+	pub(crate) fn push(&mut self, value: Value) {
+		self.stack.push(value)
+	}
+}
+
+// SAFETY: the heap is only reachable through the VM, and through the `GcRef`s
+// and `Value`s that natives and hooks see while it runs. Those aren't `Send`,
+// and the VM's public API returns owned values instead, so none are left
+// behind when the VM moves. The `Rc`s of foreign classes are all held by the
+// VM or its heap, so they move together too.
+// Everything else the host puts into a VM, such as natives, foreign objects,
+// output sinks and hooks, must be `Send`.
+unsafe impl<const MAX_FRAMES: usize, const STACK_SIZE: usize> Send
+	for Vm<MAX_FRAMES, STACK_SIZE>
+{
+}
+
+/// Reports every compile error at once.
+fn join_errors(errors: Vec<eyre::Report>) -> eyre::Report {
+	let errors = errors.iter().map(ToString::to_string);
+	eyre!(errors.collect::<Vec<_>>().join("\n"))
+}
+
+/// What `Vm::call` calls.
+#[derive(Clone, Copy)]
+pub enum Callee<'name> {
+	/// The global variable with this name.
+	Global(&'name str),
+	/// The method with the second name of the global with the first.
+	Method(&'name str, &'name str),
+}
+
+impl<'name> From<&'name str> for Callee<'name> {
+	fn from(name: &'name str) -> Self {
+		Self::Global(name)
+	}
+}
+
+impl<'name> From<(&'name str, &'name str)> for Callee<'name> {
+	fn from((receiver, name): (&'name str, &'name str)) -> Self {
+		Self::Method(receiver, name)
 	}
 }
 
@@ -56,10 +256,21 @@ impl<const MAX_FRAMES: usize, const STACK_SIZE: usize> Default
 	for Vm<MAX_FRAMES, STACK_SIZE>
 {
 	fn default() -> Self {
-		// Self {
-		// 	stack:     Pin::new(Box::new_zeroed_slice(STACK_SIZE)),
-		// 	stack_top: NonNull::dangling(),
-		// }
-		todo!()
+		Self {
+			frames:        InlineVec::new(),
+			stack:         InlineVec::new(),
+			globals:       FnvHashMap::default(),
+			foreign:       FnvHashMap::default(),
+			open_upvalues: Vec::new(),
+			base_depth:    0,
+			limits:        Limits::default(),
+			usage:         Usage::default(),
+			interrupt:     InterruptHandle::new(),
+			output:        Box::new(std::io::stdout()),
+			diagnostics:   Box::new(std::io::stderr()),
+			#[cfg(feature = "hooks")]
+			hooks:         Hooks::default(),
+			gc:            GarbageCollector::default(),
+		}
 	}
 }
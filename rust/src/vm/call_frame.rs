@@ -1,12 +1,22 @@
 use std::mem::MaybeUninit;
 use std::ptr::NonNull;
 
+use crate::chunk::Bytecode;
 use crate::mem::GcRef;
 use crate::obj::ObjClosure;
 use crate::value::Value;
 
 pub struct CallFrame {
 	pub closure: GcRef<ObjClosure>,
-	pub ip:      *const u8,
+	pub ip:      *const Bytecode,
 	pub slots:   NonNull<[MaybeUninit<Value>]>,
 }
+
+impl CallFrame {
+	/// Index of the next instruction to run in the function's chunk.
+	#[cfg(feature = "hooks")]
+	pub fn offset(&self) -> usize {
+		let code = self.closure.function.chunk.bytecode.as_ptr();
+		unsafe { self.ip.offset_from(code) as usize }
+	}
+}
@@ -0,0 +1,34 @@
+use super::*;
+
+/// The VM calling a native, which the native can use to allocate on its heap
+/// or to call back into the program.
+pub trait Caller {
+	/// The heap the native's arguments live on, and its results should.
+	fn gc(&self) -> &GarbageCollector;
+
+	/// Calls `callee` with `args` and returns its result, like `Vm::call`. A
+	/// runtime error in the call leaves the VM as it was, so the native can
+	/// handle it or return it.
+	fn call(
+		&mut self,
+		callee: Value,
+		args: &[Value],
+	) -> Result<Value, RuntimeError>;
+}
+
+impl<const MAX_FRAMES: usize, const STACK_SIZE: usize> Caller
+	for Vm<MAX_FRAMES, STACK_SIZE>
+{
+	fn gc(&self) -> &GarbageCollector {
+		&self.gc
+	}
+
+	fn call(
+		&mut self,
+		callee: Value,
+		args: &[Value],
+	) -> Result<Value, RuntimeError> {
+		self.call_with(callee, args.iter().copied())
+			.map_err(|err| RuntimeError::new(err.to_string()))
+	}
+}
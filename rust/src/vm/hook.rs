@@ -0,0 +1,398 @@
+use std::sync::Arc;
+use std::sync::Mutex;
+
+use super::*;
+use crate::chunk::Op;
+use crate::mem::GcEvent;
+
+/// Observes a running program. Register one on a VM with `Vm::add_hook`.
+///
+/// Every callback does nothing by default, so a hook only implements the
+/// events it cares about. Hooks are `Send` so that their VM can move between
+/// threads.
+pub trait Hook: Send {
+	/// Called before each instruction. Returning an error stops the program
+	/// with that error.
+	fn instruction(&mut self, cx: &Context, op: Op) -> Result<()> {
+		_ = (cx, op);
+		Ok(())
+	}
+
+	/// Called when a call to a Lox function starts, before its first
+	/// instruction. The new call is the innermost frame of `cx`.
+	fn enter(&mut self, cx: &Context) {
+		_ = cx;
+	}
+
+	/// Called once a call to `function` has returned, or ended because the
+	/// program stopped.
+	fn exit(&mut self, cx: &Context, function: GcRef<ObjFunction>) {
+		_ = (cx, function);
+	}
+
+	/// Called for each object allocated, before the next instruction runs.
+	fn alloc(&mut self, ty: ObjType, size: usize) {
+		_ = (ty, size);
+	}
+
+	/// Called after each garbage collection cycle with the number of objects
+	/// it freed and the number still alive. Collections run between runs,
+	/// from `Vm::collect_garbage`, unlike the other events.
+	fn collect(&mut self, freed: usize, live: usize) {
+		_ = (freed, live);
+	}
+
+	/// Called when the program stops with a runtime error, while its call
+	/// frames are still intact.
+	fn error(&mut self, cx: &Context, error: &eyre::Report) {
+		_ = (cx, error);
+	}
+
+	/// Called with everything the program prints. Returns whether the hook
+	/// took the text, in which case it isn't written to the output sink.
+	fn output(&mut self, text: &str) -> bool {
+		_ = text;
+		false
+	}
+}
+
+/// Lets the caller keep a handle to a hook, to read its results once the
+/// program has finished.
+impl<H: Hook> Hook for Arc<Mutex<H>> {
+	fn instruction(&mut self, cx: &Context, op: Op) -> Result<()> {
+		self.lock().unwrap().instruction(cx, op)
+	}
+
+	fn enter(&mut self, cx: &Context) {
+		self.lock().unwrap().enter(cx)
+	}
+
+	fn exit(&mut self, cx: &Context, function: GcRef<ObjFunction>) {
+		self.lock().unwrap().exit(cx, function)
+	}
+
+	fn alloc(&mut self, ty: ObjType, size: usize) {
+		self.lock().unwrap().alloc(ty, size)
+	}
+
+	fn collect(&mut self, freed: usize, live: usize) {
+		self.lock().unwrap().collect(freed, live)
+	}
+
+	fn error(&mut self, cx: &Context, error: &eyre::Report) {
+		self.lock().unwrap().error(cx, error)
+	}
+
+	fn output(&mut self, text: &str) -> bool {
+		self.lock().unwrap().output(text)
+	}
+}
+
+/// The state of the VM, as seen by hooks.
+pub struct Context<'vm> {
+	frames:  &'vm [CallFrame],
+	stack:   &'vm [Value],
+	globals: &'vm FnvHashMap<GcRef<ObjString>, Value>,
+	gc:      &'vm GarbageCollector,
+}
+
+/// One active call.
+#[derive(Clone, Copy)]
+pub struct Frame<'vm>(&'vm CallFrame, &'vm [Value]);
+
+#[derive(Default)]
+pub(super) struct Hooks {
+	hooks: Vec<Box<dyn Hook>>,
+	/// The function of each call the hooks have been told about, outermost
+	/// first.
+	calls: Vec<GcRef<ObjFunction>>,
+}
+
+impl<'vm> Context<'vm> {
+	/// Number of active calls.
+	pub fn depth(&self) -> usize {
+		self.frames.len()
+	}
+
+	/// The active calls, innermost first.
+	pub fn frames(&self) -> impl Iterator<Item = Frame<'vm>> + 'vm {
+		let stack = self.stack;
+		self.frames.iter().rev().map(move |frame| Frame(frame, stack))
+	}
+
+	/// The innermost call.
+	pub fn frame(&self) -> Option<Frame<'vm>> {
+		self.frames.last().map(|frame| Frame(frame, self.stack))
+	}
+
+	/// The value stack, top last.
+	pub fn stack(&self) -> &'vm [Value] {
+		self.stack
+	}
+
+	pub fn globals(&self) -> &'vm FnvHashMap<GcRef<ObjString>, Value> {
+		self.globals
+	}
+
+	/// The VM's heap, for allocating values to show the program.
+	pub fn gc(&self) -> &'vm GarbageCollector {
+		self.gc
+	}
+}
+
+impl<'vm> Frame<'vm> {
+	pub fn closure(&self) -> GcRef<ObjClosure> {
+		self.0.closure
+	}
+
+	pub fn function(&self) -> GcRef<ObjFunction> {
+		self.0.closure.function
+	}
+
+	/// Offset of the next instruction to run in the function's chunk.
+	pub fn offset(&self) -> usize {
+		self.0.offset()
+	}
+
+	/// Line of the next instruction to run.
+	pub fn line(&self) -> u32 {
+		self.function().chunk.lines[self.offset()]
+	}
+
+	/// The value in stack slot `slot` of the call, or `None` if the slot is
+	/// above the top of the stack.
+	pub fn slot(&self, slot: u8) -> Option<Value> {
+		let slots = self.0.slots.cast::<Value>().as_ptr().cast_const();
+		// SAFETY: a frame's slots start within the stack it's called on
+		let base = unsafe { slots.offset_from(self.1.as_ptr()) };
+		self.1.get(base as usize + slot as usize).copied()
+	}
+
+	/// The current value of the closure's upvalue `index`.
+	pub fn upvalue(&self, index: usize) -> Value {
+		// SAFETY: an upvalue points either at a stack slot of a frame that
+		// is still active, since it's closed when the frame returns, or at
+		// its own `closed` field
+		unsafe { *self.0.closure.upvalues[index].location.as_ref() }
+	}
+}
+
+impl Hooks {
+	pub(super) fn is_empty(&self) -> bool {
+		self.hooks.is_empty()
+	}
+
+	/// Passes on what the garbage collector did since the last instruction.
+	fn gc_events(&mut self, gc: &GarbageCollector) {
+		for event in gc.take_events() {
+			for hook in &mut self.hooks {
+				match event {
+					GcEvent::Alloc { ty, size } => hook.alloc(ty, size),
+				}
+			}
+		}
+	}
+}
+
+impl<const MAX_FRAMES: usize, const STACK_SIZE: usize>
+	Vm<MAX_FRAMES, STACK_SIZE>
+{
+	pub fn add_hook(&mut self, hook: impl Hook + 'static) {
+		self.hooks.hooks.push(Box::new(hook));
+	}
+
+	/// Runs before the program starts while any hook is installed.
+	pub(super) fn start_hooks(&mut self) {
+		self.gc.record_events(true);
+	}
+
+	/// Runs before each instruction while any hook is installed.
+	pub(super) fn instruction_hook(&mut self) -> Result<()> {
+		let cx = Context {
+			frames:  &self.frames,
+			stack:   &self.stack,
+			globals: &self.globals,
+			gc:      &self.gc,
+		};
+		self.hooks.gc_events(&self.gc);
+		let Hooks { hooks, calls } = &mut self.hooks;
+
+		while calls.len() > cx.depth() {
+			let function = calls.pop().unwrap();
+			hooks.iter_mut().for_each(|hook| hook.exit(&cx, function));
+		}
+		while calls.len() < cx.depth() {
+			calls.push(cx.frames[calls.len()].closure.function);
+			hooks.iter_mut().for_each(|hook| hook.enter(&cx));
+		}
+
+		let Some(frame) = cx.frame() else {
+			return Ok(());
+		};
+		let op = unsafe { frame.function().chunk.bytecode[frame.offset()].op };
+		for hook in hooks {
+			hook.instruction(&cx, op)?;
+		}
+		Ok(())
+	}
+
+	/// Runs once the program, or a `Vm::call` into it, stops, ending the
+	/// calls the hooks still count as active.
+	pub(super) fn finish_hooks(&mut self, res: &Result<()>) {
+		let cx = Context {
+			frames:  &self.frames,
+			stack:   &self.stack,
+			globals: &self.globals,
+			gc:      &self.gc,
+		};
+		self.hooks.gc_events(&self.gc);
+		// a nested `Vm::call` leaves the outer program's state alone
+		if self.base_depth == 0 {
+			self.gc.record_events(false);
+		}
+		let Hooks { hooks, calls } = &mut self.hooks;
+
+		if let Err(err) = res {
+			hooks.iter_mut().for_each(|hook| hook.error(&cx, err));
+		}
+		while calls.len() > self.base_depth {
+			let function = calls.pop().unwrap();
+			hooks.iter_mut().for_each(|hook| hook.exit(&cx, function));
+		}
+	}
+
+	/// Tells the hooks about a collection `collect_garbage` just ran, after
+	/// any allocations they haven't heard of yet.
+	pub(super) fn collect_hook(&mut self, stats: &HeapStats) {
+		self.hooks.gc_events(&self.gc);
+		for hook in &mut self.hooks.hooks {
+			hook.collect(stats.freed, stats.live);
+		}
+	}
+
+	/// Offers printed text to the hooks. Returns whether one of them took it.
+	pub(super) fn output_hook(&mut self, text: &str) -> bool {
+		self.hooks.hooks.iter_mut().any(|hook| hook.output(text))
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	const MAX_FRAMES: usize = 64;
+	const STACK_SIZE: usize = MAX_FRAMES * (u8::MAX as usize + 1);
+
+	/// Records the events it's told about, except instructions.
+	#[derive(Default)]
+	struct Recorder {
+		events: Vec<String>,
+		/// Whether to take printed text away from the output sink.
+		take:   bool,
+	}
+
+	fn name(function: GcRef<ObjFunction>) -> String {
+		function
+			.name
+			.map_or("script".into(), |name| name.to_string())
+	}
+
+	impl Hook for Recorder {
+		fn enter(&mut self, cx: &Context) {
+			let function = cx.frame().unwrap().function();
+			self.events.push(format!("enter {}", name(function)));
+		}
+
+		fn exit(&mut self, _cx: &Context, function: GcRef<ObjFunction>) {
+			self.events.push(format!("exit {}", name(function)));
+		}
+
+		fn alloc(&mut self, ty: ObjType, _size: usize) {
+			if ty == ObjType::Instance {
+				self.events.push("alloc instance".into());
+			}
+		}
+
+		fn collect(&mut self, _freed: usize, _live: usize) {
+			self.events.push("collect".into());
+		}
+
+		fn error(&mut self, cx: &Context, error: &eyre::Report) {
+			self.events
+				.push(format!("error at depth {}: {error}", cx.depth()));
+		}
+
+		fn output(&mut self, text: &str) -> bool {
+			self.events.push(format!("output {text:?}"));
+			self.take
+		}
+	}
+
+	fn run(source: &str, recorder: Recorder) -> (Vec<String>, String) {
+		let recorder = Arc::new(Mutex::new(recorder));
+		let output = OutputBuffer::default();
+		let mut vm = Vm::<MAX_FRAMES, STACK_SIZE>::new();
+		vm.set_output(output.clone());
+		vm.add_hook(recorder.clone());
+		_ = vm.interpret(source);
+		vm.interpret("var a = nil;").unwrap();
+		vm.collect_garbage();
+		drop(vm);
+
+		let recorder = Arc::into_inner(recorder).unwrap();
+		(recorder.into_inner().unwrap().events, output.take())
+	}
+
+	#[test]
+	fn calls_are_entered_and_exited_in_order() {
+		let (events, _) = run(
+			"fun f() { return g(); } fun g() { return 1; } f();",
+			Recorder::default(),
+		);
+		assert_eq!(events, [
+			"enter script",
+			"enter f",
+			"enter g",
+			"exit g",
+			"exit f",
+			"exit script",
+			"enter script",
+			"exit script",
+			"collect",
+		]);
+	}
+
+	#[test]
+	fn errors_come_before_the_calls_they_end() {
+		let (events, _) =
+			run("fun f() { return nil + 1; } f();", Recorder::default());
+		assert_eq!(events[..5], [
+			"enter script",
+			"enter f",
+			"error at depth 2: Operands must be two numbers or two strings.",
+			"exit f",
+			"exit script",
+		]);
+	}
+
+	#[test]
+	fn collections_follow_allocations_and_output() {
+		let recorder = Recorder {
+			take: true,
+			..Recorder::default()
+		};
+		let (events, output) =
+			run("class A {} var a = A(); print 1; a = A();", recorder);
+		assert_eq!(events, [
+			"enter script",
+			"alloc instance",
+			"output \"1\\n\"",
+			"alloc instance",
+			"exit script",
+			"enter script",
+			"exit script",
+			"collect",
+		]);
+		assert_eq!(output, "");
+	}
+}
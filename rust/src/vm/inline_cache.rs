@@ -0,0 +1,248 @@
+use std::rc::Rc;
+
+use super::*;
+
+/// Instance shapes an inline cache remembers at once. Past that, the oldest
+/// is forgotten.
+const POLYMORPHIC: usize = 4;
+
+/// What a `GetProperty`, `SetProperty` or `Invoke` instruction found for the
+/// last few shapes of instance it ran on, so it can skip looking up its
+/// property name when it sees one of them again.
+///
+/// An instance's shape also determines its class, so an entry holds as long
+/// as the class hasn't gained a method since.
+#[derive(Default)]
+pub struct InlineCache {
+	entries: [Option<Entry>; POLYMORPHIC],
+}
+
+struct Entry {
+	shape:   Rc<Shape>,
+	version: u64,
+	target:  Target,
+}
+
+#[derive(Clone)]
+pub(super) enum Target {
+	/// The field in this slot.
+	Field(usize),
+	/// The class's method, as there's no field by that name.
+	Method(GcRef<ObjClosure>),
+	/// The field doesn't exist yet, and setting it gives the instance this
+	/// shape.
+	Add(Rc<Shape>),
+}
+
+impl InlineCache {
+	/// What reading the property `name` of `instance` finds: a field, or
+	/// else a method. `GetProperty` and `Invoke` both read properties.
+	pub(super) fn lookup(
+		&mut self,
+		instance: GcRef<ObjInstance>,
+		name: GcRef<ObjString>,
+	) -> Option<Target> {
+		if let Some(target) = self.get(&instance) {
+			return Some(target.clone());
+		}
+
+		let target = match instance.shape.slot(name) {
+			Some(slot) => Target::Field(slot),
+			None => Target::Method(instance.klass.method(name)?),
+		};
+		self.insert(&instance, target.clone());
+		Some(target)
+	}
+
+	/// Sets the field `name` of `instance`, as `SetProperty` does.
+	pub(super) fn set(
+		&mut self,
+		mut instance: GcRef<ObjInstance>,
+		name: GcRef<ObjString>,
+		value: Value,
+	) {
+		let target = match self.get(&instance) {
+			Some(target) => target.clone(),
+			None => {
+				let target = match instance.shape.slot(name) {
+					Some(slot) => Target::Field(slot),
+					None => Target::Add(instance.shape.with(name)),
+				};
+				self.insert(&instance, target.clone());
+				target
+			},
+		};
+
+		match target {
+			Target::Field(slot) => instance.slots[slot] = value,
+			Target::Add(shape) => {
+				instance.shape = shape;
+				instance.slots.push(value);
+			},
+			Target::Method(_) => unreachable!("only found by `lookup`"),
+		}
+	}
+
+	fn get(&self, instance: &ObjInstance) -> Option<&Target> {
+		let entry = self.entries.iter().flatten().find(|entry| {
+			Rc::ptr_eq(&entry.shape, &instance.shape)
+				&& entry.version == instance.klass.version
+		})?;
+		Some(&entry.target)
+	}
+
+	fn insert(&mut self, instance: &ObjInstance, target: Target) {
+		let entry = Entry {
+			shape: instance.shape.clone(),
+			version: instance.klass.version,
+			target,
+		};
+
+		// a class that gained a method leaves entries for its shapes stale
+		let stale = self.entries.iter().position(|entry| {
+			entry
+				.as_ref()
+				.is_some_and(|entry| Rc::ptr_eq(&entry.shape, &instance.shape))
+		});
+		let free = || self.entries.iter().position(Option::is_none);
+		match stale.or_else(free) {
+			Some(index) => self.entries[index] = Some(entry),
+			// entries are oldest first, as they fill free slots in order
+			None => {
+				self.entries.rotate_left(1);
+				self.entries[POLYMORPHIC - 1] = Some(entry);
+			},
+		}
+	}
+}
+
+impl Trace for InlineCache {
+	fn trace(&self, marker: &mut Marker) {
+		for entry in self.entries.iter().flatten() {
+			entry.shape.trace(marker);
+			match &entry.target {
+				Target::Field(_) => {},
+				Target::Method(method) => method.trace(marker),
+				Target::Add(shape) => shape.trace(marker),
+			}
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn class(gc: &GarbageCollector) -> GcRef<ObjClass> {
+		ObjClass::new(gc, ObjString::new(gc, "Thing"))
+	}
+
+	/// An instance of `klass` given the fields `names`, in order.
+	fn instance(
+		gc: &GarbageCollector,
+		klass: GcRef<ObjClass>,
+		names: &[&str],
+	) -> GcRef<ObjInstance> {
+		let mut instance = ObjInstance::new(gc, klass);
+		for &name in names {
+			instance.set_field(ObjString::new(gc, name), Value::Nil());
+		}
+		instance
+	}
+
+	fn method(gc: &GarbageCollector) -> GcRef<ObjClosure> {
+		ObjClosure::new(gc, ObjFunction::new(gc))
+	}
+
+	fn is_field(target: Option<Target>, slot: usize) -> bool {
+		matches!(target, Some(Target::Field(found)) if found == slot)
+	}
+
+	fn is_method(target: Option<Target>, method: GcRef<ObjClosure>) -> bool {
+		matches!(
+			target,
+			Some(Target::Method(found)) if found.as_ptr() == method.as_ptr()
+		)
+	}
+
+	#[test]
+	fn hits_a_shape_it_has_seen() {
+		let gc = GarbageCollector::default();
+		let klass = class(&gc);
+		let first = instance(&gc, klass, &["a", "b"]);
+		let second = instance(&gc, klass, &["a", "b"]);
+		let b = ObjString::new(&gc, "b");
+
+		let mut cache = InlineCache::default();
+		assert!(cache.get(&first).is_none());
+		assert!(is_field(cache.lookup(first, b), 1));
+		// the second instance shares the first's shape
+		assert!(is_field(cache.get(&second).cloned(), 1));
+		assert!(is_field(cache.lookup(second, b), 1));
+		assert_eq!(cache.entries.iter().flatten().count(), 1);
+	}
+
+	#[test]
+	fn misses_another_shape() {
+		let gc = GarbageCollector::default();
+		let klass = class(&gc);
+		let ab = instance(&gc, klass, &["a", "b"]);
+		let ba = instance(&gc, klass, &["b", "a"]);
+		let b = ObjString::new(&gc, "b");
+
+		let mut cache = InlineCache::default();
+		assert!(is_field(cache.lookup(ab, b), 1));
+		assert!(cache.get(&ba).is_none());
+		assert!(is_field(cache.lookup(ba, b), 0));
+		// both shapes are remembered
+		assert!(is_field(cache.get(&ab).cloned(), 1));
+		assert!(is_field(cache.get(&ba).cloned(), 0));
+	}
+
+	#[test]
+	fn forgets_the_oldest_of_too_many_shapes() {
+		let gc = GarbageCollector::default();
+		let klass = class(&gc);
+		let a = ObjString::new(&gc, "a");
+		// shapes with `a` in slots 0 to 4
+		let fillers = ["v", "w", "x", "y"];
+		let instances = (0..=POLYMORPHIC)
+			.map(|i| {
+				let mut names = fillers[..i].to_vec();
+				names.push("a");
+				instance(&gc, klass, &names)
+			})
+			.collect::<Vec<_>>();
+
+		let mut cache = InlineCache::default();
+		for (slot, instance) in instances.iter().enumerate() {
+			assert!(is_field(cache.lookup(*instance, a), slot));
+		}
+
+		assert!(cache.get(&instances[0]).is_none());
+		for (slot, instance) in instances.iter().enumerate().skip(1) {
+			assert!(is_field(cache.get(instance).cloned(), slot));
+		}
+	}
+
+	#[test]
+	fn adding_a_method_invalidates_the_class() {
+		let gc = GarbageCollector::default();
+		let mut klass = class(&gc);
+		let name = ObjString::new(&gc, "m");
+		let old = method(&gc);
+		klass.add_method(name, old);
+		let instance = instance(&gc, klass, &[]);
+
+		let mut cache = InlineCache::default();
+		assert!(is_method(cache.lookup(instance, name), old));
+		assert!(is_method(cache.get(&instance).cloned(), old));
+
+		let new = method(&gc);
+		klass.add_method(name, new);
+		assert!(cache.get(&instance).is_none());
+		assert!(is_method(cache.lookup(instance, name), new));
+		// the stale entry was replaced rather than kept alongside
+		assert_eq!(cache.entries.iter().flatten().count(), 1);
+	}
+}
@@ -0,0 +1,86 @@
+use std::sync::atomic::AtomicBool;
+use std::sync::atomic::AtomicU64;
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+use std::time::Duration;
+use std::time::Instant;
+
+use super::*;
+
+/// Stops a running program from another thread. Take one from a VM with
+/// `Vm::interrupt_handle`.
+///
+/// The program stops with an "Interrupted." error at its next backward jump
+/// or call, so every loop and recursion notices it.
+#[derive(Clone)]
+pub struct InterruptHandle(Arc<Shared>);
+
+struct Shared {
+	interrupted: AtomicBool,
+	/// Nanoseconds after `epoch` at which to interrupt, or `u64::MAX` for
+	/// never.
+	deadline:    AtomicU64,
+	epoch:       Instant,
+}
+
+impl InterruptHandle {
+	pub(super) fn new() -> Self {
+		Self(Arc::new(Shared {
+			interrupted: AtomicBool::new(false),
+			deadline:    AtomicU64::new(u64::MAX),
+			epoch:       Instant::now(),
+		}))
+	}
+
+	/// Stops the running program, or the next one if none is running.
+	pub fn interrupt(&self) {
+		self.0.interrupted.store(true, Ordering::Relaxed);
+	}
+
+	/// Stops the program once `timeout` has passed.
+	pub fn interrupt_after(&self, timeout: Duration) {
+		self.interrupt_at(Instant::now() + timeout);
+	}
+
+	/// Stops the program once `deadline` has passed.
+	pub fn interrupt_at(&self, deadline: Instant) {
+		let nanos = deadline.saturating_duration_since(self.0.epoch).as_nanos();
+		let nanos = nanos.min(u64::MAX as u128 - 1) as u64;
+		self.0.deadline.store(nanos, Ordering::Relaxed);
+	}
+
+	/// Withdraws a pending interrupt and deadline.
+	pub fn clear(&self) {
+		self.0.interrupted.store(false, Ordering::Relaxed);
+		self.0.deadline.store(u64::MAX, Ordering::Relaxed);
+	}
+
+	/// Whether the program should stop. Stopping uses up the interrupt, so
+	/// the VM can run again.
+	fn take(&self) -> bool {
+		let deadline = self.0.deadline.load(Ordering::Relaxed);
+		if deadline != u64::MAX
+			&& self.0.epoch.elapsed().as_nanos() >= deadline as u128
+		{
+			self.clear();
+			return true;
+		}
+		self.0.interrupted.swap(false, Ordering::Relaxed)
+	}
+}
+
+impl<const MAX_FRAMES: usize, const STACK_SIZE: usize>
+	Vm<MAX_FRAMES, STACK_SIZE>
+{
+	pub fn interrupt_handle(&self) -> InterruptHandle {
+		self.interrupt.clone()
+	}
+
+	/// Runs at each backward jump and call.
+	pub(super) fn check_interrupt(&self) -> Result<()> {
+		if self.interrupt.take() {
+			bail!("Interrupted.");
+		}
+		Ok(())
+	}
+}
@@ -0,0 +1,94 @@
+use super::*;
+
+/// Caps on what a program may use, for running untrusted scripts. Hitting
+/// one stops the program with a runtime error and leaves the VM ready for
+/// the next one.
+///
+/// Every limit is off by default. The call depth and value stack are always
+/// capped by the VM's `MAX_FRAMES` and `STACK_SIZE`.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Limits {
+	/// Instructions run per program.
+	pub instructions: Option<u64>,
+	/// Bytes allocated on the heap per program.
+	pub heap_bytes:   Option<usize>,
+	/// Active calls.
+	pub call_depth:   Option<usize>,
+	/// Values on the stack.
+	pub stack:        Option<usize>,
+}
+
+/// What the running program has used so far.
+#[derive(Clone, Copy, Default)]
+pub(super) struct Usage {
+	instructions: u64,
+	/// Bytes the collector had allocated when the program started.
+	heap_start:   usize,
+	/// Whether none of the limits checked per instruction are set, so that
+	/// only the stack's size needs checking.
+	unbounded:    bool,
+}
+
+impl<const MAX_FRAMES: usize, const STACK_SIZE: usize>
+	Vm<MAX_FRAMES, STACK_SIZE>
+{
+	pub fn limits(&self) -> Limits {
+		self.limits
+	}
+
+	pub fn set_limits(&mut self, limits: Limits) {
+		self.limits = limits;
+	}
+
+	/// Runs when a program starts, as opposed to a call into one.
+	pub(super) fn start_usage(&mut self) {
+		self.usage = Usage {
+			instructions: 0,
+			heap_start:   self.gc.bytes_allocated(),
+			unbounded:    matches!(self.limits, Limits {
+				instructions: None,
+				heap_bytes: None,
+				stack: None,
+				..
+			}),
+		};
+	}
+
+	/// Runs before each instruction, which pushes at most one value.
+	pub(super) fn check_limits(&mut self) -> Result<()> {
+		if self.usage.unbounded {
+			if self.stack.len() >= STACK_SIZE {
+				bail!("Stack overflow.");
+			}
+			return Ok(());
+		}
+
+		let stack = self.limits.stack.unwrap_or(STACK_SIZE).min(STACK_SIZE);
+		if self.stack.len() >= stack {
+			bail!("Stack overflow.");
+		}
+
+		self.usage.instructions += 1;
+		let instructions = self.usage.instructions;
+		let budget = self.limits.instructions;
+		if budget.is_some_and(|budget| instructions > budget) {
+			bail!("Instruction budget exhausted.");
+		}
+
+		let heap = self.gc.bytes_allocated() - self.usage.heap_start;
+		if self.limits.heap_bytes.is_some_and(|limit| heap > limit) {
+			bail!("Out of memory.");
+		}
+		Ok(())
+	}
+
+	/// Runs before a call pushes a frame.
+	pub(super) fn check_call_depth(&self) -> Result<()> {
+		let depth =
+			self.limits.call_depth.unwrap_or(MAX_FRAMES).min(MAX_FRAMES);
+		if self.frames.len() >= depth {
+			bail!("Stack overflow.");
+		}
+		Ok(())
+	}
+}
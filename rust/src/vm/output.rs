@@ -0,0 +1,50 @@
+use std::io::Write;
+use std::sync::Arc;
+use std::sync::Mutex;
+
+use super::*;
+
+/// A sink that collects what's written to it, for capturing a program's
+/// output. Clones share the same buffer, so keep one to read it back:
+///
+/// ```ignore
+/// let output = OutputBuffer::default();
+/// vm.set_output(output.clone());
+/// vm.interpret("print 1 + 2;")?;
+/// assert_eq!(output.take(), "3\n");
+/// ```
+#[derive(Clone, Default)]
+pub struct OutputBuffer(Arc<Mutex<Vec<u8>>>);
+
+impl OutputBuffer {
+	/// Everything written so far, leaving the buffer empty.
+	pub fn take(&self) -> String {
+		let bytes = std::mem::take(&mut *self.0.lock().unwrap());
+		String::from_utf8_lossy(&bytes).into_owned()
+	}
+}
+
+impl Write for OutputBuffer {
+	fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+		self.0.lock().unwrap().write(buf)
+	}
+
+	fn flush(&mut self) -> std::io::Result<()> {
+		Ok(())
+	}
+}
+
+impl<const MAX_FRAMES: usize, const STACK_SIZE: usize>
+	Vm<MAX_FRAMES, STACK_SIZE>
+{
+	/// Where `print` writes, stdout by default.
+	pub fn set_output(&mut self, out: impl Write + Send + 'static) {
+		self.output = Box::new(out);
+	}
+
+	/// Where the VM writes diagnostics, such as the `debug-trace` stack
+	/// dumps, stderr by default.
+	pub fn set_diagnostics(&mut self, out: impl Write + Send + 'static) {
+		self.diagnostics = Box::new(out);
+	}
+}
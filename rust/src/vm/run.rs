@@ -1,31 +1,71 @@
+use std::io::Write;
 use std::ops::Neg;
 
 use eyre::Result;
 
+use super::inline_cache::Target;
 use super::*;
 use crate::chunk::Bytecode;
 use crate::mem::GcRef;
+use crate::obj::ObjBoundMethod;
 use crate::obj::ObjClass;
 use crate::obj::ObjClosure;
+use crate::obj::ObjForeign;
 use crate::obj::ObjFunction;
 use crate::obj::ObjInstance;
 use crate::obj::ObjNative;
+use crate::value::FromLox;
+use crate::value::IntoLox;
 
 impl<const MAX_FRAMES: usize, const STACK_SIZE: usize>
 	Vm<MAX_FRAMES, STACK_SIZE>
 {
 	fn run(&mut self) -> Result<()> {
+		if self.base_depth == 0 {
+			self.start_usage();
+		}
+
+		#[cfg(feature = "hooks")]
+		if !self.hooks.is_empty() {
+			self.start_hooks();
+			let res = self.dispatch();
+			self.finish_hooks(&res);
+			return res.inspect_err(|_| self.unwind());
+		}
+
+		self.dispatch().inspect_err(|_| self.unwind())
+	}
+
+	/// Drops the calls a failed program left behind, so the VM can run the
+	/// next one. A `Vm::call` restores the state it started from itself.
+	fn unwind(&mut self) {
+		if self.base_depth == 0 {
+			self.frames.clear();
+			self.stack.clear();
+			self.open_upvalues.clear();
+		}
+	}
+
+	fn dispatch(&mut self) -> Result<()> {
 		if cfg!(feature = "debug-trace") {
-			let mut slots = self.stack.iter();
-			slots.next().map(|slot| print!("[ {slot} ]"));
-			slots.for_each(|slot| print!(" [ {slot} ]"));
-			println!();
+			let out = &mut self.diagnostics;
+			for (index, slot) in self.stack.iter().enumerate() {
+				let separator = if index == 0 { "" } else { " " };
+				write!(out, "{separator}[ {slot} ]")?;
+			}
+			writeln!(out)?;
 			// TODO: disassembleInstruction
 		}
 
 		loop {
 			use crate::chunk::Op::*;
 
+			#[cfg(feature = "hooks")]
+			if !self.hooks.is_empty() {
+				self.instruction_hook()?;
+			}
+			self.check_limits()?;
+
 			let bytecode = self.read_byte();
 			match unsafe { bytecode.op } {
 				Closure => {
@@ -35,23 +75,28 @@ impl<const MAX_FRAMES: usize, const STACK_SIZE: usize>
 					else {
 						return Err(eyre::eyre!("Only function objects can become a closure"));
 					};
-					let mut closure = ObjClosure::new(function);
+					let mut closure = ObjClosure::new(&self.gc, function);
 					self.push(closure.value());
 
-					for ii in 0..closure.upvalues.len() {
-						let (is_local, slot_index) = unsafe {
+					for _ in 0..function.upvalue_count {
+						let (is_local, index) = unsafe {
 							(self.read_byte().byte, self.read_byte().byte)
 						};
-						closure.upvalues[ii] = if is_local > 0 {
-							let start_slot = self.frame().slots.cast::<Value>();
-							let upvalues = start_slot.map_addr(|addr| {
-								addr.checked_add(slot_index as _).unwrap()
-							});
-
-							self.capture_upvalues(upvalues)
+						let upvalue = if is_local > 0 {
+							let slots = self.frame().slots.cast::<Value>();
+							// SAFETY: the compiler only captures the
+							// enclosing function's slots, which are within
+							// the stack
+							let slot = unsafe {
+								NonNull::new_unchecked(
+									slots.as_ptr().add(index as usize),
+								)
+							};
+							self.capture_upvalues(slot)
 						} else {
-							self.frame().closure.upvalues[ii]
+							self.frame().closure.upvalues[index as usize]
 						};
+						closure.upvalues.push(upvalue);
 					}
 				},
 				Constant => {
@@ -63,27 +108,72 @@ impl<const MAX_FRAMES: usize, const STACK_SIZE: usize>
 				True => self.push(Value::Bool(true)),
 
 				Call => {
+					self.check_interrupt()?;
 					let arg_count = self.read_byte();
 					let arg_count = unsafe { arg_count.byte };
 					self.call_value(self.peek(arg_count as _), arg_count)?;
 				},
+				Invoke => {
+					let name = self.read_string();
+					let arg_count = unsafe { self.read_byte().byte };
+					let cache = unsafe { self.read_byte().byte };
+					let receiver = self.peek(arg_count as _);
+					let Some(instance) = receiver.as_casted_obj::<ObjInstance>()
+					else {
+						let name = name.as_str();
+						let foreign = receiver.as_casted_obj::<ObjForeign>();
+						let method = match foreign {
+							// a method native finds its receiver in the
+							// callee's slot, so it isn't bound
+							Some(foreign) => {
+								ObjForeign::get_unbound(&self.gc, foreign, name)
+									.ok_or_else(|| {
+										eyre!("Undefined property '{name}'.")
+									})?
+							},
+							None => method(&self.gc, receiver, name)?,
+						};
+						self.call_value(method, arg_count)?;
+						continue;
+					};
+
+					let mut function = self.frame().closure.function;
+					let cache = &mut function.chunk.caches[cache as usize];
+					match cache.lookup(instance, name) {
+						Some(Target::Field(slot)) => {
+							// the field's value is called in place of the
+							// receiver
+							let value = instance.slots[slot];
+							let callee =
+								self.stack.len() - arg_count as usize - 1;
+							self.stack[callee] = value;
+							self.call_value(value, arg_count)?;
+						},
+						Some(Target::Method(method)) => {
+							RunUtil::call(self, method, arg_count)?;
+						},
+						_ => {
+							return Err(eyre!("Undefined property '{name}'."));
+						},
+					}
+				},
 				Jump => {
 					let offset = self.read_short();
 					let frame = self.frame_mut();
-					frame.ip = frame.ip.map_addr(|ip| ip + (offset as usize));
+					frame.ip = frame.ip.wrapping_add(offset as usize);
 				},
 				JumpIfFalse => {
 					let offset = self.read_short();
 					if self.peek(0).is_falsey() {
 						let frame = self.frame_mut();
-						frame.ip =
-							frame.ip.map_addr(|ip| ip + (offset as usize));
+						frame.ip = frame.ip.wrapping_add(offset as usize);
 					}
 				},
 				Loop => {
+					self.check_interrupt()?;
 					let offset = self.read_short();
 					let frame = self.frame_mut();
-					frame.ip = frame.ip.map_addr(|ip| ip - (offset as usize));
+					frame.ip = frame.ip.wrapping_sub(offset as usize);
 				},
 				Pop => drop(self.pop()),
 
@@ -103,7 +193,7 @@ impl<const MAX_FRAMES: usize, const STACK_SIZE: usize>
 				SetGlobal => {
 					let name = self.read_string();
 					let value = self.peek(0);
-					if let None = self.globals.insert(name, value) {
+					if self.globals.insert(name, value).is_none() {
 						self.globals.remove(&name);
 						return Err(eyre!("Undefined variable '{name}'."));
 					}
@@ -127,41 +217,84 @@ impl<const MAX_FRAMES: usize, const STACK_SIZE: usize>
 					slot.write(value);
 				},
 				GetProperty => {
+					let name = self.read_string();
+					let cache = unsafe { self.read_byte().byte };
+					if let Some(foreign) =
+						self.peek(0).as_casted_obj::<ObjForeign>()
+					{
+						let value =
+							ObjForeign::get(&self.gc, foreign, name.as_str());
+						let Some(value) = value else {
+							return Err(eyre!("Undefined property '{name}'."));
+						};
+						self.pop(); // foreign
+						self.push(value);
+						continue;
+					}
 					let Some(instance) = self
 						.peek(0)
 						.as_casted_obj::<ObjInstance>()
 					else {
 						return Err(eyre!("Only instances have properties."));
 					};
-					let name = self.read_string();
 
-					let Some(value) = instance.fields.get(&name) else {
-						return Err(eyre!("Undefined property '{name}'."));
+					let mut function = self.frame().closure.function;
+					let cache = &mut function.chunk.caches[cache as usize];
+					let value = match cache.lookup(instance, name) {
+						Some(Target::Field(slot)) => instance.slots[slot],
+						Some(Target::Method(method)) => {
+							let receiver = instance.value();
+							let method = method.value();
+							ObjBoundMethod::new(&self.gc, receiver, method)
+								.value()
+						},
+						_ => {
+							return Err(eyre!("Undefined property '{name}'."));
+						},
 					};
 
 					self.pop(); // instance
-					self.push(*value);
+					self.push(value);
 				},
 				CloseUpvalue => {
-					self.close_upvalues(&self.stack[self.stack.len() - 1] as _)
+					self.close_upvalues(&self.stack[self.stack.len() - 1] as _);
+					self.pop();
 				},
 				GetUpvalue => {
 					let slot = self.read_byte();
 					let slot = unsafe { slot.byte };
 					let value =
 						self.frame().closure.upvalues[slot as usize].location;
-					self.push(unsafe { value.as_ref() }.clone());
+					self.push(unsafe { *value.as_ref() });
+				},
+				SetUpvalue => {
+					let slot = unsafe { self.read_byte().byte };
+					let value = self.peek(0);
+					let mut upvalue = self.frame().closure.upvalues[slot as usize];
+					unsafe { *upvalue.location.as_mut() = value };
 				},
 				SetProperty => {
-					let Some(mut instance) = self
-						.peek(1)
-						.as_casted_obj::<ObjInstance>()
+					let name = self.read_string();
+					let cache = unsafe { self.read_byte().byte };
+					if let Some(mut foreign) =
+						self.peek(1).as_casted_obj::<ObjForeign>()
+					{
+						foreign.set(name.as_str(), self.peek(0))?;
+
+						let value = self.pop();
+						self.pop();
+						self.push(value);
+						continue;
+					}
+					let Some(instance) =
+						self.peek(1).as_casted_obj::<ObjInstance>()
 					else {
 						return Err(eyre!("Only instances have properties."));
 					};
-					let name = self.read_string();
-					let value = self.peek(0);
-					instance.fields.insert(name, value);
+
+					let mut function = self.frame().closure.function;
+					let cache = &mut function.chunk.caches[cache as usize];
+					cache.set(instance, name, self.peek(0));
 
 					let value = self.pop();
 					self.pop();
@@ -173,25 +306,26 @@ impl<const MAX_FRAMES: usize, const STACK_SIZE: usize>
 					let b = self.pop();
 					self.push(Value::Bool(a == b));
 				},
-				Less => self.binary_op(|a, b| Value::Bool(a < b)),
-				Greater => self.binary_op(|a, b| Value::Bool(a > b)),
+				Less => self.binary_op(|a, b| Value::Bool(a < b))?,
+				Greater => self.binary_op(|a, b| Value::Bool(a > b))?,
 
 				Add => {
-					let l = self.peek(0);
-					let r = self.peek(1);
+					let r = self.peek(0);
+					let l = self.peek(1);
 					if let Some(a) = l.as_casted_obj::<ObjString>()
 						&& let Some(b) = r.as_casted_obj::<ObjString>()
 					{
-						let string = ObjString::concat(a, b);
+						let string = ObjString::concat(&self.gc, a, b)?;
+						self.stack.pop_n(2);
 						self.push(string.value());
 					} else if l.is_number() && r.is_number() {
-						self.binary_op(|a, b| Value::Number(a + b))
+						self.binary_op(|a, b| Value::Number(a + b))?
 					} else {
 						return Err(eyre!("Operands must be two numbers or two strings."));
 					}
 				},
-				Divide => self.binary_op(|a, b| Value::Number(a / b)),
-				Multiply => self.binary_op(|a, b| Value::Number(a * b)),
+				Divide => self.binary_op(|a, b| Value::Number(a / b))?,
+				Multiply => self.binary_op(|a, b| Value::Number(a * b))?,
 				Negate => {
 					let Some(val) = self.peek(0).as_number() else {
 						return Err(eyre!("Operand must be a number."));
@@ -199,20 +333,209 @@ impl<const MAX_FRAMES: usize, const STACK_SIZE: usize>
 					self.pop();
 					self.push(Value::Number(val.neg()));
 				},
-				Subtract => self.binary_op(|a, b| Value::Number(a - b)),
+				Subtract => self.binary_op(|a, b| Value::Number(a - b))?,
 
 				Not => {
 					let is_falsey = self.pop().is_falsey();
 					self.push(Value::Bool(is_falsey));
 				},
 
-				Print => println!("{}", self.pop()),
-				_ => todo!(),
+				Print => {
+					let value = self.pop();
+					#[cfg(feature = "hooks")]
+					if !self.hooks.is_empty()
+						&& self.output_hook(&format!("{value}\n"))
+					{
+						continue;
+					}
+					writeln!(self.output, "{value}")?;
+				},
+				Class => {
+					let name = self.read_string();
+					let klass = ObjClass::new(&self.gc, name);
+					self.push(klass.value());
+				},
+				Inherit => {
+					let Some(superclass) = self.peek(1).as_casted_obj::<ObjClass>()
+					else {
+						return Err(eyre!("Superclass must be a class."));
+					};
+					let mut subclass =
+						self.peek(0).as_casted_obj::<ObjClass>().unwrap();
+					for (name, method) in superclass.methods().iter() {
+						subclass.add_method(*name, *method);
+					}
+					self.pop(); // subclass
+				},
+				Method => {
+					let name = self.read_string();
+					let closure = self.peek(0).as_casted_obj::<ObjClosure>().unwrap();
+					let mut klass = self.peek(1).as_casted_obj::<ObjClass>().unwrap();
+					klass.add_method(name, closure);
+					self.pop(); // closure
+				},
+				GetSuper => {
+					let name = self.read_string();
+					let superclass = self.pop().as_casted_obj::<ObjClass>().unwrap();
+					let Some(method) = superclass.method(name) else {
+						return Err(eyre!("Undefined property '{name}'."));
+					};
+
+					let receiver = self.pop();
+					let bound =
+						ObjBoundMethod::new(&self.gc, receiver, method.value());
+					self.push(bound.value());
+				},
+				SuperInvoke => {
+					let name = self.read_string();
+					let arg_count = unsafe { self.read_byte().byte };
+					let superclass = self.pop().as_casted_obj::<ObjClass>().unwrap();
+					let Some(method) = superclass.method(name) else {
+						return Err(eyre!("Undefined property '{name}'."));
+					};
+
+					RunUtil::call(self, method, arg_count)?;
+				},
+				Return => {
+					let result = self.pop();
+					let slots = self.frame().slots.cast::<Value>().as_ptr();
+					self.close_upvalues(slots);
+					self.frames.pop();
+
+					let base =
+						unsafe { slots.offset_from(self.stack.as_ptr()) as usize };
+					self.stack.pop_n(self.stack.len() - base);
+					self.push(result);
+					if self.frames.len() == self.base_depth {
+						return Ok(());
+					}
+				},
 			}
 		}
 	}
 }
 
+impl<const MAX_FRAMES: usize, const STACK_SIZE: usize>
+	Vm<MAX_FRAMES, STACK_SIZE>
+{
+	/// Calls a Lox function, method or class from Rust and returns its
+	/// result, converted to `T`. `callee` is a global's name, or the names
+	/// of a global and of its method:
+	///
+	/// ```ignore
+	/// let sum: f64 = vm.call("add", [1.0, 2.0])?;
+	/// let area: f64 = vm.call(("shape", "area"), [(); 0])?;
+	/// ```
+	///
+	/// Natives call back into the VM with `Caller::call` instead. On error
+	/// the stack, frames and open upvalues are left as they were before the
+	/// call.
+	pub fn call<'name, T: FromLox>(
+		&mut self,
+		callee: impl Into<Callee<'name>>,
+		args: impl IntoIterator<Item = impl IntoLox>,
+	) -> Result<T> {
+		let callee = match callee.into() {
+			Callee::Global(name) => self.global(name)?,
+			Callee::Method(receiver, name) => {
+				let receiver = self.global(receiver)?;
+				method(&self.gc, receiver, name)?
+			},
+		};
+		let value = self.call_with(callee, args)?;
+		Ok(T::from_lox(value)?)
+	}
+
+	fn global(&self, name: &str) -> Result<Value> {
+		let global = self.globals.iter().find(|(key, _)| key.as_str() == name);
+		let Some((_, value)) = global else {
+			bail!("Undefined variable '{name}'.");
+		};
+		Ok(*value)
+	}
+
+	/// Calls `callee` with `args`, and returns its result.
+	pub(super) fn call_with(
+		&mut self,
+		callee: Value,
+		args: impl IntoIterator<Item = impl IntoLox>,
+	) -> Result<Value> {
+		let depth = self.frames.len();
+		let height = self.stack.len();
+		self.push(callee);
+		let mut arg_count = 0usize;
+		for arg in args {
+			if arg_count == u8::MAX as usize {
+				self.stack.pop_n(self.stack.len() - height);
+				bail!("Can't have more than 255 arguments.");
+			}
+			if self.stack.is_full() {
+				self.stack.pop_n(self.stack.len() - height);
+				bail!("Stack overflow.");
+			}
+			let arg = arg.into_lox(&self.gc);
+			self.push(arg);
+			arg_count += 1;
+		}
+
+		let base_depth = std::mem::replace(&mut self.base_depth, depth);
+		let mut res = self.call_value(callee, arg_count as u8);
+		if res.is_ok() && self.frames.len() > depth {
+			res = self.run();
+		}
+		self.base_depth = base_depth;
+
+		match res {
+			Ok(()) => Ok(self.pop()),
+			Err(err) => {
+				// a failed program may have unwound past the call already
+				self.frames.pop_n(self.frames.len().saturating_sub(depth));
+				if self.stack.len() > height {
+					// closures the call made may outlive its variables
+					let first = self.stack.as_ptr().wrapping_add(height);
+					self.close_upvalues(first);
+					self.stack.pop_n(self.stack.len() - height);
+				}
+				Err(err)
+			},
+		}
+	}
+}
+
+/// The method `name` of `receiver`, bound to it.
+fn method(
+	gc: &GarbageCollector,
+	receiver: Value,
+	name: &str,
+) -> Result<Value> {
+	if let Some(foreign) = receiver.as_casted_obj::<ObjForeign>() {
+		return ObjForeign::get(gc, foreign, name)
+			.ok_or_else(|| eyre!("Undefined property '{name}'."));
+	}
+	let Some(instance) = receiver.as_casted_obj::<ObjInstance>() else {
+		bail!("Only instances have methods.");
+	};
+
+	let field = instance.fields().find(|(key, _)| key.as_str() == name);
+	let method = instance
+		.klass
+		.methods()
+		.iter()
+		.find(|(key, _)| key.as_str() == name);
+	match (field, method) {
+		(Some((_, value)), _) => Ok(value),
+		(None, Some((_, method))) => {
+			Ok(ObjBoundMethod::new(gc, receiver, method.value()).value())
+		},
+		(None, None) => bail!("Undefined property '{name}'."),
+	}
+}
+
+/// Stands in for a native while it runs.
+fn reentered(_: &mut dyn Caller, _: &[Value]) -> Result<Value, RuntimeError> {
+	Err(RuntimeError::new("Can't call a native from inside itself."))
+}
+
 // trait for restricting scoping of these methods
 trait RunUtil {
 	fn call(&mut self, closure: GcRef<ObjClosure>, arg_count: u8)
@@ -220,10 +543,9 @@ trait RunUtil {
 
 	fn call_value(&mut self, callee: Value, arg_count: u8) -> Result<()>;
 
-	fn capture_upvalues(&mut self, values: NonNull<Value>)
-		-> GcRef<ObjUpvalue>;
+	fn capture_upvalues(&mut self, slot: NonNull<Value>) -> GcRef<ObjUpvalue>;
 
-	fn binary_op(&mut self, op: impl FnOnce(f64, f64) -> Value);
+	fn binary_op(&mut self, op: impl FnOnce(f64, f64) -> Value) -> Result<()>;
 
 	fn close_upvalues(&mut self, last: *const Value);
 
@@ -241,8 +563,10 @@ trait RunUtil {
 
 	fn read_string(&mut self) -> GcRef<ObjString>;
 
-	unsafe fn stack_slice_from_top<'slice, 'me: 'slice>(
-		&'me self,
+	/// The top `len` values of the stack. They mustn't be popped or
+	/// overwritten while the slice is in use.
+	unsafe fn stack_slice_from_top<'slice>(
+		&self,
 		len: usize,
 	) -> &'slice [Value];
 }
@@ -255,7 +579,28 @@ impl<const MAX_FRAMES: usize, const STACK_SIZE: usize> RunUtil
 		closure: GcRef<ObjClosure>,
 		arg_count: u8,
 	) -> Result<()> {
-		todo!()
+		let arity = closure.function.arity;
+		if arg_count as usize != arity {
+			bail!("Expected {arity} arguments but got {arg_count}.");
+		}
+		self.check_call_depth()?;
+
+		let base = self.stack.len() - arg_count as usize - 1;
+		// SAFETY: the callee's slot is on the stack, and the frame's slots
+		// reach as far as the stack can grow
+		let slots = unsafe {
+			let first = self.stack.as_uninit_ptr().as_ptr().add(base);
+			NonNull::slice_from_raw_parts(
+				NonNull::new_unchecked(first),
+				STACK_SIZE - base,
+			)
+		};
+		self.frames.push(CallFrame {
+			closure,
+			ip: closure.function.chunk.bytecode.as_ptr(),
+			slots,
+		});
+		Ok(())
 	}
 
 	fn call_value(&mut self, callee: Value, arg_count: u8) -> Result<()> {
@@ -264,41 +609,88 @@ impl<const MAX_FRAMES: usize, const STACK_SIZE: usize> RunUtil
 		};
 
 		if let Some(klass) = obj.try_cast::<ObjClass>() {
-			let instance_slot =
-				&self.stack[self.stack.len() - (arg_count as usize) - 1];
-			todo!()
+			let instance = self.new_instance(klass);
+			let callee = self.stack.len() - arg_count as usize - 1;
+			self.stack[callee] = instance.value();
+
+			let init = ObjString::new(&self.gc, "init");
+			match klass.method(init) {
+				Some(initializer) => RunUtil::call(self, initializer, arg_count),
+				None if arg_count != 0 => {
+					bail!("Expected 0 arguments but got {arg_count}.")
+				},
+				None => Ok(()),
+			}
 		} else if let Some(closure) = obj.try_cast::<ObjClosure>() {
-			// self.call(closure, arg_count)
-			todo!()
+			RunUtil::call(self, closure, arg_count)
 		} else if let Some(function) = obj.try_cast::<ObjFunction>() {
-			// self.call(function, arg_count)
-			todo!()
-		} else if let Some(native) = obj.try_cast::<ObjNative>() {
+			let closure = ObjClosure::new(&self.gc, function);
+			RunUtil::call(self, closure, arg_count)
+		} else if let Some(bound) = obj.try_cast::<ObjBoundMethod>() {
+			// the receiver takes the callee's slot
+			let callee = self.stack.len() - arg_count as usize - 1;
+			self.stack[callee] = bound.receiver;
+			self.call_value(bound.method, arg_count)
+		} else if let Some(mut native) = obj.try_cast::<ObjNative>() {
+			if !native.arity.accepts(arg_count) {
+				return Err(eyre!(
+					"Expected {} but got {arg_count}.",
+					native.arity
+				));
+			}
+
 			let arg_count = arg_count as usize;
-			let res = (native.function)(unsafe {
-				self.stack_slice_from_top(arg_count as _)
-			});
-			self.stack.pop_n(arg_count);
+			// a method's receiver is in the callee's slot
+			let receiver = usize::from(native.is_method);
+			// SAFETY: calls back into the VM only push above the arguments,
+			// and pop back down to them before returning
+			let args =
+				unsafe { self.stack_slice_from_top(arg_count + receiver) };
+			// the program the native calls back into may call it again, so
+			// it's taken out while it runs
+			let mut function =
+				std::mem::replace(&mut native.function, Box::new(reentered));
+			let res = function(self, args);
+			native.function = function;
+			let res = res?;
+			self.stack.pop_n(arg_count + 1);
 			self.push(res);
-			return Ok(());
+			Ok(())
 		} else {
 			Err(eyre!("Can only call functions and classes."))
 		}
 	}
 
-	fn capture_upvalues(
-		&mut self,
-		values: NonNull<Value>,
-	) -> GcRef<ObjUpvalue> {
-		todo!()
+	fn capture_upvalues(&mut self, slot: NonNull<Value>) -> GcRef<ObjUpvalue> {
+		let mut open = self.open_upvalues.iter();
+		if let Some(upvalue) = open.find(|upvalue| upvalue.location == slot) {
+			return *upvalue;
+		}
+
+		let upvalue = ObjUpvalue::new(&self.gc, slot);
+		self.open_upvalues.push(upvalue);
+		upvalue
 	}
 
-	fn binary_op(&mut self, op: impl FnOnce(f64, f64) -> Value) {
-		todo!()
+	fn binary_op(&mut self, op: impl FnOnce(f64, f64) -> Value) -> Result<()> {
+		let (Some(b), Some(a)) = (self.peek(0).as_number(), self.peek(1).as_number())
+		else {
+			bail!("Operands must be numbers.");
+		};
+		self.stack.pop_n(2);
+		self.push(op(a, b));
+		Ok(())
 	}
 
 	fn close_upvalues(&mut self, last: *const Value) {
-		todo!()
+		self.open_upvalues.retain_mut(|upvalue| {
+			if upvalue.location.as_ptr().cast_const() < last {
+				return true;
+			}
+			upvalue.closed = unsafe { *upvalue.location.as_ref() };
+			upvalue.location = NonNull::from(&upvalue.closed);
+			false
+		});
 	}
 
 	fn frame<'frame, 'vm: 'frame>(&'vm self) -> &'frame CallFrame {
@@ -306,34 +698,42 @@ impl<const MAX_FRAMES: usize, const STACK_SIZE: usize> RunUtil
 	}
 
 	fn frame_mut<'frame, 'vm: 'frame>(&'vm mut self) -> &'frame mut CallFrame {
-		todo!()
+		let last = self.frames.len() - 1;
+		&mut self.frames[last]
 	}
 
 	fn new_instance(&mut self, klass: GcRef<ObjClass>) -> GcRef<ObjInstance> {
-		todo!()
+		ObjInstance::new(&self.gc, klass)
 	}
 
 	fn read_byte(&mut self) -> Bytecode {
-		// let res = self.stack[]
-		todo!()
+		let frame = self.frame_mut();
+		// SAFETY: the compiler ends every function with a `Return`, so the
+		// frame never runs past its chunk
+		let bytecode = unsafe { std::ptr::read(frame.ip) };
+		frame.ip = frame.ip.wrapping_add(1);
+		bytecode
 	}
 
 	fn read_constant(&mut self) -> Value {
-		todo!()
+		let index = unsafe { self.read_byte().byte };
+		self.frame().closure.function.chunk.constants[index as usize]
 	}
 
 	fn read_short(&mut self) -> u16 {
-		todo!()
+		let (high, low) = unsafe { (self.read_byte().byte, self.read_byte().byte) };
+		u16::from(high) << 8 | u16::from(low)
 	}
 
 	fn read_string(&mut self) -> GcRef<ObjString> {
-		todo!()
+		self.read_constant().as_casted_obj::<ObjString>().unwrap()
 	}
 
-	unsafe fn stack_slice_from_top<'slice, 'me: 'slice>(
-		&'me self,
+	unsafe fn stack_slice_from_top<'slice>(
+		&self,
 		len: usize,
 	) -> &'slice [Value] {
-		todo!()
+		let top = &self.stack[self.stack.len() - len..];
+		unsafe { std::slice::from_raw_parts(top.as_ptr(), len) }
 	}
 }
@@ -0,0 +1,15 @@
+/// An error raised by host code, such as a native function. The VM reports
+/// it like any other runtime error.
+#[derive(Clone, Debug, thiserror::Error)]
+#[error("{message}")]
+pub struct RuntimeError {
+	pub message: String,
+}
+
+impl RuntimeError {
+	pub fn new(message: impl Into<String>) -> Self {
+		Self {
+			message: message.into(),
+		}
+	}
+}
@@ -0,0 +1,47 @@
+use std::io::BufRead;
+use std::io::Write;
+
+use eyre::Result;
+use serde_json::Value as Json;
+
+/// Reads one `Content-Length`-framed JSON message, as used by both the
+/// Language Server and Debug Adapter protocols. Returns `None` at the end of
+/// `input`.
+pub(crate) fn read_message(input: &mut impl BufRead) -> Result<Option<Json>> {
+	let mut len = None;
+	loop {
+		let mut header = String::new();
+		if input.read_line(&mut header)? == 0 {
+			return Ok(None);
+		}
+
+		let header = header.trim_end();
+		if header.is_empty() {
+			break;
+		}
+
+		let Some((name, value)) = header.split_once(':') else {
+			continue;
+		};
+		if name.eq_ignore_ascii_case("Content-Length") {
+			len = Some(value.trim().parse::<usize>()?);
+		}
+	}
+
+	let Some(len) = len else {
+		bail!("Message has no Content-Length header.");
+	};
+	let mut body = vec![0; len];
+	input.read_exact(&mut body)?;
+	Ok(Some(serde_json::from_slice(&body)?))
+}
+
+pub(crate) fn write_message(
+	output: &mut impl Write,
+	message: &Json,
+) -> Result<()> {
+	let body = message.to_string();
+	write!(output, "Content-Length: {}\r\n\r\n{body}", body.len())?;
+	output.flush()?;
+	Ok(())
+}
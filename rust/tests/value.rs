@@ -0,0 +1,76 @@
+//! Checks that hold for either representation of `Value`. CI runs them with
+//! and without the `nan-boxing` feature.
+
+use rlox::mem::GarbageCollector;
+use rlox::obj::ObjString;
+use rlox::value::FromLox;
+use rlox::value::IntoLox;
+use rlox::value::Value;
+use rlox::value::ValueKind;
+
+#[test]
+fn numbers_round_trip() {
+	for number in [0.0, -0.0, 1.5, -2e300, f64::INFINITY, f64::MIN_POSITIVE] {
+		let value = Value::Number(number);
+		assert_eq!(value.kind(), ValueKind::Number);
+		assert_eq!(value.as_number().unwrap().to_bits(), number.to_bits());
+	}
+}
+
+#[test]
+fn any_nan_is_a_number() {
+	let gc = GarbageCollector::default();
+	let nans = [
+		f64::NAN,
+		-f64::NAN,
+		// the bit patterns nan-boxing tags nil, booleans and objects with
+		f64::from_bits(0x7FFC_0000_0000_0001),
+		f64::from_bits(0x7FFC_0000_0000_0003),
+		f64::from_bits(0xFFFC_0000_0000_1234),
+	];
+	for nan in nans {
+		for value in [Value::Number(nan), nan.into_lox(&gc)] {
+			assert_eq!(value.kind(), ValueKind::Number);
+			assert!(value.as_obj().is_none());
+			assert!(value.as_bool().is_none());
+			assert!(value.as_number().unwrap().is_nan());
+			assert!(value != value);
+		}
+	}
+}
+
+#[test]
+fn nil_and_booleans() {
+	assert_eq!(Value::Nil().kind(), ValueKind::Nil);
+	assert_eq!(Value::Bool(true).as_bool(), Some(true));
+	assert_eq!(Value::Bool(false).as_bool(), Some(false));
+	assert!(Value::Nil().as_bool().is_none());
+	assert!(Value::Bool(false).as_number().is_none());
+	assert!(Value::Nil() == Value::Nil());
+	assert!(Value::Bool(true) != Value::Bool(false));
+	assert!(Value::Nil() != Value::Bool(false));
+}
+
+#[test]
+fn objects_round_trip() {
+	let gc = GarbageCollector::default();
+	let string = ObjString::new(&gc, "hello");
+	let value = "hello".to_owned().into_lox(&gc);
+	assert_eq!(value.kind(), ValueKind::Obj);
+	assert!(value.as_casted_obj::<ObjString>() == Some(string));
+	assert_eq!(String::from_lox(value).unwrap(), "hello");
+	assert!(value.as_number().is_none());
+	assert_eq!(value.to_string(), "hello");
+}
+
+#[test]
+fn vecs_round_trip() {
+	let gc = GarbageCollector::default();
+	let value = vec![Some(1.0), None, Some(2.5)].into_lox(&gc);
+	assert_eq!(value.kind(), ValueKind::Obj);
+	assert_eq!(value.to_string(), "[1, nil, 2.5]");
+	let numbers = Vec::<Option<f64>>::from_lox(value).unwrap();
+	assert_eq!(numbers, [Some(1.0), None, Some(2.5)]);
+	assert!(Vec::<String>::from_lox(value).is_err());
+	assert!(Vec::<f64>::from_lox(Value::Nil()).is_err());
+}
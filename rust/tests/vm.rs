@@ -0,0 +1,361 @@
+use std::time::Duration;
+use std::time::Instant;
+
+use rlox::obj::Arity;
+use rlox::obj::ObjString;
+use rlox::value::Value;
+use rlox::vm::Limits;
+use rlox::vm::OutputBuffer;
+use rlox::vm::RuntimeError;
+use rlox::vm::Vm;
+
+const MAX_FRAMES: usize = 64;
+const STACK_SIZE: usize = MAX_FRAMES * (u8::MAX as usize + 1);
+
+type TestVm = Vm<MAX_FRAMES, STACK_SIZE>;
+
+#[test]
+fn print_writes_to_the_output_sink() {
+	let mut vm = TestVm::new();
+	let output = OutputBuffer::default();
+	vm.set_output(output.clone());
+
+	vm.interpret("print 1 + 2; print \"a\" + \"b\"; print nil;")
+		.unwrap();
+	assert_eq!(output.take(), "3\nab\nnil\n");
+
+	// taking empties the buffer, and later runs write to it again
+	assert_eq!(output.take(), "");
+	vm.interpret("print true;").unwrap();
+	assert_eq!(output.take(), "true\n");
+}
+
+#[test]
+fn runs_the_examples() {
+	// benchmarks that take too long unoptimized, or don't compile on purpose
+	const SKIPPED: &[&str] =
+		&["fib_timed.lox", "profile_zoo.lox", "reassign_upvalue.lox"];
+
+	let dir = concat!(env!("CARGO_MANIFEST_DIR"), "/../lox_programs");
+	for entry in std::fs::read_dir(dir).unwrap() {
+		let path = entry.unwrap().path();
+		let name = path.file_name().unwrap().to_str().unwrap();
+		if SKIPPED.contains(&name) {
+			continue;
+		}
+
+		let source = std::fs::read_to_string(&path).unwrap();
+		let mut vm = TestVm::new();
+		vm.set_output(std::io::sink());
+		if let Err(err) = vm.interpret(&source) {
+			panic!("{name}: {err}");
+		}
+	}
+}
+
+#[test]
+fn runtime_errors_leave_the_vm_usable() {
+	let cases = [
+		("-\"a\";", "Operand must be a number."),
+		("1 + nil;", "Operands must be two numbers or two strings."),
+		("1 < \"a\";", "Operands must be numbers."),
+		("print x;", "Undefined variable 'x'."),
+		("x = 1;", "Undefined variable 'x'."),
+		("fun f(a) {} f(1, 2);", "Expected 1 arguments but got 2."),
+		("class A {} A(1);", "Expected 0 arguments but got 1."),
+		("1();", "Can only call functions and classes."),
+		("1.x;", "Only instances have properties."),
+		("class A {} A().x;", "Undefined property 'x'."),
+		("var A = 1; class B < A {}", "Superclass must be a class."),
+		("fun f() { f(); } f();", "Stack overflow."),
+		("var s = \"ab\"; while (true) s = s + s;", "String too long."),
+	];
+
+	let mut vm = TestVm::new();
+	for (source, message) in cases {
+		let err = vm.interpret(source).unwrap_err();
+		assert_eq!(err.to_string(), message, "{source}");
+	}
+	vm.interpret("var x = 1; x = x + 1;").unwrap();
+}
+
+#[test]
+fn natives_keep_state_and_check_their_arity() {
+	let mut vm = TestVm::new();
+	let mut count = 0.0;
+	vm.define_native(
+		"count",
+		Arity::Exact(1),
+		Box::new(move |_, args| {
+			count += args[0].as_number().unwrap_or(1.0);
+			Ok(Value::Number(count))
+		}),
+	);
+	vm.define_native(
+		"fail",
+		Arity::AtLeast(1),
+		Box::new(|_, _| Err(RuntimeError::new("Failed on purpose."))),
+	);
+
+	vm.interpret("count(1); count(nil);").unwrap();
+	assert_eq!(vm.evaluate::<f64>("count(2)").unwrap(), 4.0);
+
+	let cases = [
+		("count(1, 2);", "Expected 1 argument but got 2."),
+		("count();", "Expected 1 argument but got 0."),
+		("fail();", "Expected at least 1 argument but got 0."),
+		("fail(1, 2, 3);", "Failed on purpose."),
+	];
+	for (source, message) in cases {
+		let err = vm.interpret(source).unwrap_err();
+		assert_eq!(err.to_string(), message, "{source}");
+	}
+}
+
+#[test]
+fn call_runs_functions_and_bound_methods() {
+	let mut vm = TestVm::new();
+	vm.interpret(
+		"fun add(a, b) { return a + b; }
+		class Greeter {
+			init(name) { this.name = name; }
+			greet(greeting) { return greeting + \", \" + this.name; }
+		}
+		var greeter = Greeter(\"Ada\");",
+	)
+	.unwrap();
+
+	let sum: f64 = vm.call("add", [1.0, 2.0]).unwrap();
+	assert_eq!(sum, 3.0);
+	let joined: String = vm.call("add", ["a", "b"]).unwrap();
+	assert_eq!(joined, "ab");
+
+	let greeting: String = vm.call(("greeter", "greet"), ["Hello"]).unwrap();
+	assert_eq!(greeting, "Hello, Ada");
+
+	let err = vm.call::<f64>("add", [1.0]).unwrap_err();
+	assert_eq!(err.to_string(), "Expected 2 arguments but got 1.");
+	let err = vm.call::<()>(("greeter", "wave"), [(); 0]).unwrap_err();
+	assert_eq!(err.to_string(), "Undefined property 'wave'.");
+	let err = vm.call::<f64>(("greeter", "greet"), ["Hi"]).unwrap_err();
+	assert_eq!(err.to_string(), "Expected a number but got string.");
+	let err = vm.call::<f64>("missing", [(); 0]).unwrap_err();
+	assert_eq!(err.to_string(), "Undefined variable 'missing'.");
+}
+
+#[test]
+fn evaluate_runs_a_single_expression() {
+	let mut vm = TestVm::new();
+	let output = OutputBuffer::default();
+	vm.set_output(output.clone());
+	vm.interpret("var a = 1;").unwrap();
+
+	assert_eq!(vm.evaluate::<f64>("a = a + 1").unwrap(), 2.0);
+	assert_eq!(vm.evaluate::<f64>("a").unwrap(), 2.0);
+
+	for source in ["1); print \"x\"; (1", "1; 2", "var b = 1", ""] {
+		let err = vm.evaluate::<Option<f64>>(source).unwrap_err();
+		assert!(err.to_string().contains("Expect"), "{source}: {err}");
+	}
+	assert_eq!(output.take(), "");
+}
+
+#[test]
+fn call_restores_the_vm_after_a_runtime_error() {
+	let mut vm = TestVm::new();
+	vm.interpret(
+		"var saved;
+		fun fail(n) {
+			var local = n;
+			fun get() { return local; }
+			saved = get;
+			return n + nil;
+		}
+		fun outer(n) { return fail(n); }",
+	)
+	.unwrap();
+
+	let err = vm.call::<f64>("outer", [1.0]).unwrap_err();
+	assert_eq!(
+		err.to_string(),
+		"Operands must be two numbers or two strings."
+	);
+	// the closure made before the error kept its variable
+	assert_eq!(vm.evaluate::<f64>("saved()").unwrap(), 1.0);
+
+	let err = vm.call::<f64>("fail", [()]).unwrap_err();
+	assert!(err.to_string().starts_with("Operands"), "{err}");
+	vm.interpret("fun ok() { return 2; } var two = ok();")
+		.unwrap();
+	assert_eq!(vm.evaluate::<f64>("two").unwrap(), 2.0);
+}
+
+#[test]
+fn natives_call_back_into_the_vm() {
+	let mut vm = TestVm::new();
+	let output = OutputBuffer::default();
+	vm.set_output(output.clone());
+	vm.define_native(
+		"apply",
+		Arity::AtLeast(1),
+		Box::new(|caller, args| caller.call(args[0], &args[1..])),
+	);
+	// returns the error message of a failed call
+	vm.define_native(
+		"try",
+		Arity::Exact(1),
+		Box::new(|caller, args| match caller.call(args[0], &[]) {
+			Ok(value) => Ok(value),
+			Err(err) => Ok(ObjString::new(caller.gc(), err.message).value()),
+		}),
+	);
+
+	vm.interpret(
+		"fun add(a, b) { return a + b; }
+		class Counter {
+			init() { this.count = 0; }
+			bump(by) { this.count = this.count + by; return this.count; }
+		}
+		var counter = Counter();
+		print apply(add, 1, 2);
+		apply(counter.bump, 2);
+		print apply(counter.bump, 3);",
+	)
+	.unwrap();
+	assert_eq!(output.take(), "3\n5\n");
+
+	vm.interpret(
+		"var saved;
+		fun fail() {
+			var local = \"kept\";
+			fun get() { return local; }
+			saved = get;
+			return 1 + nil;
+		}
+		fun outer() {
+			var before = \"before\";
+			var message = try(fail);
+			print before + \" \" + message;
+			return saved();
+		}
+		print outer();
+		print try(add);",
+	)
+	.unwrap();
+	assert_eq!(
+		output.take(),
+		"before Operands must be two numbers or two strings.\nkept\n\
+		 Expected 2 arguments but got 0.\n"
+	);
+
+	vm.interpret("fun again() { return try(again); } print try(again);")
+		.unwrap();
+	assert_eq!(output.take(), "Can't call a native from inside itself.\n");
+}
+
+/// Runs `src`, which must fail with `error`, then checks the VM still runs
+/// programs.
+fn assert_stops(vm: &mut TestVm, src: &str, error: &str) {
+	let err = vm.interpret(src).unwrap_err();
+	assert!(err.to_string().contains(error), "{err}");
+
+	let output = OutputBuffer::default();
+	vm.set_output(output.clone());
+	vm.interpret("print \"still running\";").unwrap();
+	assert_eq!(output.take(), "still running\n");
+}
+
+#[test]
+fn instruction_budget_stops_a_loop() {
+	let mut vm = TestVm::new();
+	vm.set_limits(Limits {
+		instructions: Some(1_000),
+		..Limits::default()
+	});
+	assert_stops(&mut vm, "while (true) {}", "Instruction budget exhausted.");
+	// the budget is per program
+	vm.interpret("for (var i = 0; i < 10; i = i + 1) {}")
+		.unwrap();
+}
+
+#[test]
+fn heap_cap_stops_a_growing_string() {
+	let mut vm = TestVm::new();
+	vm.set_limits(Limits {
+		heap_bytes: Some(1 << 20),
+		..Limits::default()
+	});
+	assert_stops(
+		&mut vm,
+		"var s = \"doubled\"; while (true) s = s + s;",
+		"Out of memory.",
+	);
+}
+
+#[test]
+fn call_depth_limit_stops_deep_recursion() {
+	let recurse = "fun f(n) { if (n > 0) f(n - 1); } f(20);";
+	let mut vm = TestVm::new();
+	vm.interpret(recurse).unwrap();
+
+	vm.set_limits(Limits {
+		call_depth: Some(10),
+		..Limits::default()
+	});
+	assert_stops(&mut vm, recurse, "Stack overflow.");
+	vm.interpret("fun g(n) { if (n > 0) g(n - 1); } g(5);")
+		.unwrap();
+}
+
+/// Runs `while (true) {}`, which only ends by being interrupted.
+fn run_forever(vm: &mut TestVm) {
+	let err = vm.interpret("while (true) {}").unwrap_err();
+	assert!(err.to_string().contains("Interrupted."), "{err}");
+}
+
+#[test]
+fn interrupt_stops_a_loop_from_another_thread() {
+	let mut vm = TestVm::new();
+	let handle = vm.interrupt_handle();
+	let interrupter = std::thread::spawn(move || {
+		std::thread::sleep(Duration::from_millis(50));
+		handle.interrupt();
+	});
+
+	run_forever(&mut vm);
+	interrupter.join().unwrap();
+}
+
+#[test]
+fn interrupt_after_stops_a_loop_from_another_thread() {
+	let mut vm = TestVm::new();
+	let handle = vm.interrupt_handle();
+	let start = Instant::now();
+	std::thread::spawn(move || {
+		handle.interrupt_after(Duration::from_millis(50))
+	})
+	.join()
+	.unwrap();
+
+	run_forever(&mut vm);
+	assert!(start.elapsed() >= Duration::from_millis(50));
+}
+
+#[test]
+fn interrupt_at_stops_a_loop_from_another_thread() {
+	let mut vm = TestVm::new();
+	let handle = vm.interrupt_handle();
+	let deadline = Instant::now() + Duration::from_millis(50);
+	std::thread::spawn(move || handle.interrupt_at(deadline))
+		.join()
+		.unwrap();
+
+	run_forever(&mut vm);
+	assert!(Instant::now() >= deadline);
+
+	// the interrupt is used up, so the VM runs again
+	let output = OutputBuffer::default();
+	vm.set_output(output.clone());
+	vm.interpret("print 1;").unwrap();
+	assert_eq!(output.take(), "1\n");
+}
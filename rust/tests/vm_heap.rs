@@ -0,0 +1,96 @@
+use std::alloc::GlobalAlloc;
+use std::alloc::Layout;
+use std::alloc::System;
+use std::cell::Cell;
+
+use rlox::vm::OutputBuffer;
+use rlox::vm::Vm;
+
+const MAX_FRAMES: usize = 64;
+const STACK_SIZE: usize = MAX_FRAMES * (u8::MAX as usize + 1);
+
+type TestVm = Vm<MAX_FRAMES, STACK_SIZE>;
+
+/// Counts the bytes each thread has allocated and not freed yet, so that
+/// tests running in parallel don't see each other's allocations.
+struct Counting;
+
+thread_local! {
+	static LIVE: Cell<isize> = const { Cell::new(0) };
+}
+
+unsafe impl GlobalAlloc for Counting {
+	unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+		LIVE.with(|live| live.set(live.get() + layout.size() as isize));
+		unsafe { System.alloc(layout) }
+	}
+
+	unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+		LIVE.with(|live| live.set(live.get() - layout.size() as isize));
+		unsafe { System.dealloc(ptr, layout) }
+	}
+}
+
+#[global_allocator]
+static ALLOCATOR: Counting = Counting;
+
+fn live() -> isize {
+	LIVE.with(Cell::get)
+}
+
+const PROGRAM: &str = "
+class Point {
+	init(x, y) { this.x = x; this.y = y; }
+	sum() { return this.x + this.y; }
+}
+fun counter() {
+	var count = 0;
+	fun next() { count = count + 1; return count; }
+	return next;
+}
+var next = counter();
+var total = 0;
+var text = \"\";
+for (var i = 0; i < 100; i = i + 1) {
+	var point = Point(i, next());
+	total = total + point.sum();
+	text = text + \"a long enough piece of text\";
+}
+print total;
+";
+
+#[test]
+fn dropping_a_vm_frees_its_heap() {
+	let before = live();
+	let mut vm = TestVm::new();
+	vm.set_output(std::io::sink());
+	vm.interpret(PROGRAM).unwrap();
+	assert_eq!(vm.evaluate::<String>("text").unwrap().len(), 2700);
+	assert!(live() > before);
+
+	drop(vm);
+	assert_eq!(live(), before);
+}
+
+#[test]
+fn vms_on_one_thread_keep_separate_heaps() {
+	let mut first = TestVm::new();
+	let mut second = TestVm::new();
+	let output = OutputBuffer::default();
+	second.set_output(output.clone());
+
+	first.interpret("var name = \"first\";").unwrap();
+	second.interpret(PROGRAM).unwrap();
+	let err = first.evaluate::<f64>("next()").unwrap_err();
+	assert_eq!(err.to_string(), "Undefined variable 'next'.");
+
+	// collecting one heap, or dropping it, leaves the other's objects alone
+	for _ in 0..3 {
+		first.collect_garbage();
+	}
+	drop(first);
+	second.interpret("print Point(1, 2).sum() + next();").unwrap();
+	assert_eq!(output.take(), "10000\n104\n");
+	let name = second.evaluate::<String>("\"fir\" + \"st\"").unwrap();
+	assert_eq!(name, "first");
+}
@@ -0,0 +1,103 @@
+use std::sync::mpsc;
+use std::sync::Arc;
+use std::sync::Mutex;
+
+use rlox::vm::OutputBuffer;
+use rlox::vm::Vm;
+
+// small enough for a VM to be moved around on a test thread's stack
+const MAX_FRAMES: usize = 16;
+const STACK_SIZE: usize = MAX_FRAMES * (u8::MAX as usize + 1);
+
+type TestVm = Vm<MAX_FRAMES, STACK_SIZE>;
+
+fn run(vm: &mut TestVm, src: &str) -> String {
+	let output = OutputBuffer::default();
+	vm.set_output(output.clone());
+	vm.interpret(src).unwrap();
+	output.take()
+}
+
+#[test]
+fn runs_on_a_worker_thread() {
+	let mut vm = TestVm::new();
+	assert_eq!(run(&mut vm, "var a = 1; print a;"), "1\n");
+
+	let (mut vm, output) = std::thread::spawn(move || {
+		let output = run(&mut vm, "a = a + 1; print a;");
+		(vm, output)
+	})
+	.join()
+	.unwrap();
+	assert_eq!(output, "2\n");
+
+	assert_eq!(run(&mut vm, "print a + 1;"), "3\n");
+}
+
+#[test]
+fn returned_values_outlive_a_move() {
+	let mut vm = TestVm::new();
+	run(&mut vm, "var names = \"a\" + \"b\";");
+	let names: String = vm.evaluate("names").unwrap();
+
+	let mut vm = std::thread::spawn(move || {
+		vm.collect_garbage();
+		vm
+	})
+	.join()
+	.unwrap();
+	assert_eq!(names, "ab");
+	assert_eq!(run(&mut vm, "print names;"), "ab\n");
+}
+
+#[test]
+fn moves_between_pool_threads() {
+	const VMS: usize = 4;
+	const ROUNDS: usize = 10;
+
+	// two workers taking VMs off a shared queue, like an executor resuming
+	// tasks on whichever thread is free
+	let (jobs, queue) = mpsc::channel::<Option<(TestVm, usize)>>();
+	let queue = Arc::new(Mutex::new(queue));
+	let (done, results) = mpsc::channel();
+
+	let workers = (0..2)
+		.map(|_| {
+			let (queue, jobs, done) =
+				(queue.clone(), jobs.clone(), done.clone());
+			std::thread::spawn(move || loop {
+				// the lock is only held while waiting, not while running
+				let job = queue.lock().unwrap().recv().unwrap();
+				let Some((mut vm, round)) = job else {
+					break;
+				};
+				let output = run(&mut vm, "count = count + 1; print count;");
+				if round + 1 == ROUNDS {
+					done.send((vm, output)).unwrap();
+				} else {
+					jobs.send(Some((vm, round + 1))).unwrap();
+				}
+			})
+		})
+		.collect::<Vec<_>>();
+
+	for _ in 0..VMS {
+		let mut vm = TestVm::new();
+		run(&mut vm, "var count = 0;");
+		jobs.send(Some((vm, 0))).unwrap();
+	}
+
+	for _ in 0..VMS {
+		let (mut vm, output) = results.recv().unwrap();
+		assert_eq!(output, format!("{ROUNDS}\n"));
+		vm.collect_garbage();
+		assert_eq!(run(&mut vm, "print count;"), format!("{ROUNDS}\n"));
+	}
+
+	for _ in &workers {
+		jobs.send(None).unwrap();
+	}
+	for worker in workers {
+		worker.join().unwrap();
+	}
+}